@@ -0,0 +1,18 @@
+#![no_main]
+
+// Run with `cargo +nightly fuzz run parse` from the repo root.
+// Seeding the corpus with a few real replays helps a lot: `cp *.slp fuzz/corpus/parse/`.
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = slp_parser::parse_file(data);
+    let _ = slp_parser::parse_file_ext(data);
+    let _ = slp_parser::parse_file_info(&mut std::io::Cursor::new(data));
+    let _ = slp_parser::parse_file_info_slpz(&mut std::io::Cursor::new(data));
+    let _ = slp_parser::parse_notes(data);
+
+    let mut parser = slp_parser::SlpStreamParser::new();
+    let mut events = Vec::new();
+    let _ = parser.push(data, &mut events);
+});
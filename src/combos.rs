@@ -0,0 +1,221 @@
+use crate::*;
+use std::path::PathBuf;
+
+/// A combo ends after the defender has been actionable for this many frames.
+pub const COMBO_STRING_RESET_FRAMES: u32 = 45;
+
+/// A string of hits where the defender did not get back control for more than `COMBO_STRING_RESET_FRAMES`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Combo {
+    pub attacker: u8,
+    pub defender: u8,
+    /// Index of the frame the first hit landed.
+    pub start_frame: usize,
+    /// Index of the frame after the last hit, or of the defender's death.
+    pub end_frame: usize,
+    pub start_percent: f32,
+    pub end_percent: f32,
+    /// (frame_idx, attack, damage). Multi-hit moves are merged into one.
+    pub moves: Vec<(usize, AttackKind, f32)>,
+    pub did_kill: bool,
+}
+
+impl Combo {
+    pub fn damage(&self) -> f32 { self.end_percent - self.start_percent }
+
+    /// Clip for Dolphin playback, padded by `padding` frames on both sides.
+    pub fn playback_clip(&self, path: &Path, padding: usize) -> PlaybackClip {
+        PlaybackClip {
+            path: path.to_path_buf(),
+            start_frame: (self.start_frame.saturating_sub(padding) as i32).saturating_sub(123),
+            end_frame: (self.end_frame.saturating_add(padding).min(i32::MAX as usize) as i32).saturating_sub(123),
+        }
+    }
+}
+
+// the defender is not in control of their character
+fn is_stuck(frame: &Frame) -> bool {
+    frame.state.is_hitstun()
+        || frame.hitlag_frames > 0.0
+        || matches!(
+            frame.state.broad_state(),
+            BroadState::Standard(StandardBroadState::Hitstun | StandardBroadState::GenericInactionable)
+        )
+}
+
+/// Finds the combos by the attacker on the defender.
+///
+/// Hits are attributed with `last_hit_by`.
+/// Grabs and throws do not set `last_hit_by`, so they are attributed to the attacker.
+pub fn find_combos(attacker_frames: &[Frame], defender_frames: &[Frame]) -> Vec<Combo> {
+    let mut combos = Vec::new();
+    let len = attacker_frames.len().min(defender_frames.len());
+    let Some(first) = attacker_frames.first() else { return combos };
+    let attacker = first.port_idx;
+    let defender = defender_frames[0].port_idx;
+
+    let mut combo: Option<Combo> = None;
+    let mut reset_counter = 0;
+    // (instance id, attacker state) of the last move to hit, to merge multi-hit moves
+    let mut last_hit: Option<(u16, u16)> = None;
+
+    for i in 1..len {
+        let prev = &defender_frames[i-1];
+        let frame = &defender_frames[i];
+        let attacker_frame = &attacker_frames[i];
+
+        let stock_lost = died(prev, frame);
+        let attributed = frame.last_hit_by == attacker
+            || (frame.last_hit_by >= 4 && frame.state.broad_state() == BroadState::Standard(StandardBroadState::Hitstun));
+        let damaged = frame.percent > prev.percent && !stock_lost && attributed;
+
+        if combo.is_none() && damaged {
+            combo = Some(Combo {
+                attacker,
+                defender,
+                start_frame: i,
+                end_frame: i+1,
+                start_percent: prev.percent,
+                end_percent: frame.percent,
+                moves: Vec::new(),
+                did_kill: false,
+            });
+            reset_counter = 0;
+            last_hit = None;
+        }
+
+        let Some(c) = combo.as_mut() else { continue };
+
+        if stock_lost {
+            c.did_kill = true;
+            c.end_frame = i;
+            combos.extend(combo.take());
+            continue;
+        }
+
+        if damaged {
+            let damage = frame.percent - prev.percent;
+            let hit = (frame.last_hit_by_instance_id, attacker_frame.state_num);
+            match c.moves.last_mut() {
+                Some(m) if last_hit == Some(hit) => m.2 += damage,
+                _ => c.moves.push((i, attacker_frame.last_hitting_attack_id, damage)),
+            }
+            last_hit = Some(hit);
+            c.end_percent = frame.percent;
+        } else if last_hit.is_some_and(|(_, state)| state != attacker_frame.state_num) {
+            // the attacker moved on, so the next hit is a new move even if the instance id is unchanged
+            last_hit = None;
+        }
+
+        if damaged || is_stuck(frame) {
+            reset_counter = 0;
+            c.end_frame = i+1;
+        } else {
+            reset_counter += 1;
+        }
+
+        if reset_counter > COMBO_STRING_RESET_FRAMES {
+            combos.extend(combo.take());
+        }
+    }
+
+    combos.extend(combo);
+    combos
+}
+
+/// Finds the combos between every pair of opponents, sorted by start frame.
+pub fn compute_combos(game: &Game) -> Vec<Combo> {
+    let mut combos = Vec::new();
+    for attacker in 0..4 {
+        let Some(attacker_frames) = game.frames[attacker].as_deref() else { continue };
+        for defender in 0..4 {
+            if attacker == defender || game.info.teams[attacker] == game.info.teams[defender] { continue }
+            let Some(defender_frames) = game.frames[defender].as_deref() else { continue };
+            combos.extend(find_combos(attacker_frames, defender_frames));
+        }
+    }
+    combos.sort_by_key(|c| (c.start_frame, c.attacker, c.defender));
+    combos
+}
+
+/// A replay section for a Dolphin playback queue. Frames are game frames, starting at -123.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlaybackClip {
+    pub path: PathBuf,
+    pub start_frame: i32,
+    pub end_frame: i32,
+}
+
+/// Writes the playback queue json read by Slippi Dolphin's `-i` option.
+pub fn dolphin_queue_json(clips: &[PlaybackClip]) -> String {
+    let mut json = String::from("{\"mode\":\"queue\",\"queue\":[");
+    for (i, clip) in clips.iter().enumerate() {
+        if i != 0 { json.push(','); }
+        json.push_str("{\"path\":\"");
+        json_escape(&mut json, &clip.path.to_string_lossy());
+        json.push_str(&format!("\",\"startFrame\":{},\"endFrame\":{}}}", clip.start_frame, clip.end_frame));
+    }
+    json.push_str("]}");
+    json
+}
+
+fn json_escape(json: &mut String, s: &str) {
+    for c in s.chars() {
+        match c {
+            '"'  => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+}
+
+#[test]
+fn combo_detection() {
+    let game = parse_file(&test_replay()).unwrap();
+    let mut attacker = test_frames(&game, 0, 300);
+    let mut defender = test_frames(&game, 1, 300);
+
+    // a two frame multi-hit, a second move, then a kill after the combo has dropped
+    for f in attacker[100..].iter_mut() { f.last_hitting_attack_id = AttackKind::FAir; f.state_num = 65; }
+    for f in attacker[110..].iter_mut() { f.last_hitting_attack_id = AttackKind::UAir; f.state_num = 68; }
+    for f in defender[100..].iter_mut() { f.percent = 5.0; f.last_hit_by = 0; f.last_hit_by_instance_id = 1; }
+    for f in defender[101..].iter_mut() { f.percent = 8.0; }
+    for f in defender[110..].iter_mut() { f.percent = 20.0; f.last_hit_by_instance_id = 2; }
+    for f in defender[250..].iter_mut() { f.percent = 30.0; }
+    for f in defender[260..].iter_mut() { f.percent = 0.0; f.stock_count = 3; }
+    defender[260].state = ActionState::Standard(StandardActionState::DeadDown);
+
+    let combos = find_combos(&attacker, &defender);
+    assert_eq!(combos.len(), 2);
+    assert_eq!((combos[0].start_frame, combos[0].end_frame, combos[0].damage()), (100, 111, 20.0));
+    assert_eq!(combos[0].moves, vec![(100, AttackKind::FAir, 8.0), (110, AttackKind::UAir, 12.0)]);
+    assert!(!combos[0].did_kill);
+    assert_eq!((combos[1].start_frame, combos[1].end_frame, combos[1].did_kill), (250, 260, true));
+    assert!(find_combos(&defender, &attacker).is_empty());
+}
+
+#[test]
+fn dolphin_queue_export() {
+    let combo = Combo {
+        attacker: 0,
+        defender: 1,
+        start_frame: 100,
+        end_frame: 111,
+        start_percent: 0.0,
+        end_percent: 20.0,
+        moves: Vec::new(),
+        did_kill: false,
+    };
+    let clips = [
+        combo.playback_clip(Path::new("C:\\replays\\game.slp"), 30),
+        PlaybackClip { path: PathBuf::from("a \"b\".slp"), start_frame: -123, end_frame: 10 },
+    ];
+    assert_eq!(
+        dolphin_queue_json(&clips),
+        r#"{"mode":"queue","queue":[{"path":"C:\\replays\\game.slp","startFrame":-53,"endFrame":18},{"path":"a \"b\".slp","startFrame":-123,"endFrame":10}]}"#,
+    );
+}
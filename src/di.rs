@@ -0,0 +1,296 @@
+use crate::*;
+
+/// Maximum change in launch angle from DI, in degrees.
+pub const DI_MAX_ANGLE: f32 = 18.0;
+
+/// Stick magnitude needed for SDI and ASDI.
+pub const SDI_STICK_MAGNITUDE: f32 = 0.7;
+
+/// Launch speed lost each frame.
+pub const KNOCKBACK_DECAY: f32 = 0.051;
+
+/// DI quality at or above this counts as survival DI.
+pub const SURVIVAL_DI_QUALITY: f32 = 0.5;
+
+/// DI, SDI and ASDI on a single hit.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HitDi {
+    pub victim: u8,
+    /// Port of the attacker from `Frame::last_hit_by`, if set.
+    pub attacker: Option<u8>,
+    /// `Frame::last_hit_by_instance_id`, the same for each hit of a move.
+    pub instance_id: u16,
+    /// Index of the first frame of hitlag.
+    pub frame_idx: usize,
+    pub hitlag_frames: u32,
+    /// Launch angle before DI, in degrees counter-clockwise from right.
+    pub launch_angle: f32,
+    /// Launch angle after DI.
+    pub di_angle: f32,
+    pub launch_speed: f32,
+    /// Processed stick on the last frame of hitlag.
+    pub di_stick: Vector,
+    pub sdi_inputs: u32,
+    /// Clamped raw stick that moved the player on the last frame of hitlag.
+    /// The c-stick takes priority over the control stick.
+    pub asdi: Option<VectorI8>,
+    /// Part of a multi-hit move, the same move instance as the previous or next hit.
+    pub multi_hit: bool,
+    /// The launch would reach a blast zone without DI, ignoring gravity.
+    pub is_kill_percent: bool,
+    /// How far the DI moved the launch from the worst to the best angle for survival, from 0 to 1.
+    /// None if the stage has no geometry or DI could not change the distance to the blast zone.
+    pub di_quality: Option<f32>,
+}
+
+/// DI habits of a single player.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DiReport {
+    pub hits: Vec<HitDi>,
+    pub kill_percent_hits: u32,
+    /// Average `HitDi::di_quality` on kill percent hits.
+    pub kill_percent_di_quality: Option<f32>,
+    /// Fraction of kill percent hits with `SURVIVAL_DI_QUALITY` or better.
+    pub survival_di_consistency: Option<f32>,
+    pub multi_hit_moves: u32,
+    pub sdi_per_multi_hit: Option<f32>,
+    /// Fraction of hits with ASDI.
+    pub asdi_rate: Option<f32>,
+}
+
+/// Change in launch angle from DI, in degrees.
+///
+/// The perpendicular component of the stick to the launch direction, squared and scaled by `DI_MAX_ANGLE`.
+pub fn di_angle_offset(launch_angle: f32, stick: Vector) -> f32 {
+    let (sin, cos) = launch_angle.to_radians().sin_cos();
+    let perpendicular = (cos * stick.y - sin * stick.x).clamp(-1.0, 1.0);
+    DI_MAX_ANGLE * perpendicular * perpendicular.abs()
+}
+
+fn normalize_angle(angle: f32) -> f32 {
+    angle.rem_euclid(360.0)
+}
+
+fn raw_magnitude(stick: VectorI8) -> f32 {
+    let (x, y) = (stick.x as f32, stick.y as f32);
+    (x*x + y*y).sqrt() / VectorI8::MAX as f32
+}
+
+// 8 way region used for SDI, None if below `SDI_STICK_MAGNITUDE`
+fn sdi_region(stick: VectorI8) -> Option<u8> {
+    let stick = stick.clamped();
+    if raw_magnitude(stick) < SDI_STICK_MAGNITUDE { return None }
+    Some(((stick_angle(stick) + 22.5).rem_euclid(360.0) / 45.0) as u8 % 8)
+}
+
+/// Distance from the position to the blast zone in the direction of the angle.
+fn blast_zone_distance(geometry: &StageGeometry, position: Vector, angle: f32) -> f32 {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let mut distance = f32::INFINITY;
+    if cos > 0.0 { distance = distance.min(( geometry.blast_zone_x - position.x) / cos); }
+    if cos < 0.0 { distance = distance.min((-geometry.blast_zone_x - position.x) / cos); }
+    if sin > 0.0 { distance = distance.min((geometry.blast_zone_top - position.y) / sin); }
+    if sin < 0.0 { distance = distance.min((geometry.blast_zone_bottom - position.y) / sin); }
+    distance.max(0.0)
+}
+
+/// Every hit with knockback on the player, in order.
+/// Returns an empty list if the port is unused.
+pub fn hit_di(game: &Game, port: usize) -> Vec<HitDi> {
+    let mut hits: Vec<HitDi> = Vec::new();
+    let Some(frames) = game.frames.get(port).and_then(|f| f.as_deref()) else { return hits };
+    let geometry = stage_geometry(game.info.stage);
+    // (attacker, instance id) of the previous hit
+    let mut prev_hit: Option<(u8, u16)> = None;
+
+    let mut i = 1;
+    while i < frames.len() {
+        let prev = &frames[i-1];
+        let frame = &frames[i];
+        i += 1;
+        let hitlag_started = frame.hitlag_frames > 0.0 && prev.hitlag_frames <= 0.0;
+        if !hitlag_started || frame.percent <= prev.percent { continue }
+
+        let start = i-1;
+        let mut end = start;
+        while end < frames.len() && frames[end].hitlag_frames > 0.0 { end += 1; }
+        if end == start || end >= frames.len() { break }
+        i = end;
+
+        let last = &frames[end-1];
+        let launch = last.hit_velocity;
+        let launch_speed = (launch.x*launch.x + launch.y*launch.y).sqrt();
+        if launch_speed == 0.0 { continue }
+
+        let launch_angle = normalize_angle(launch.y.atan2(launch.x).to_degrees());
+        let di_stick = last.left_stick_coords;
+        let di_angle = normalize_angle(launch_angle + di_angle_offset(launch_angle, di_stick));
+
+        let mut sdi_inputs = 0;
+        for k in start+1..end {
+            let region = sdi_region(frames[k].left_stick_coords_raw);
+            if region.is_some() && region != sdi_region(frames[k-1].left_stick_coords_raw) { sdi_inputs += 1; }
+        }
+
+        let asdi = [last.right_stick_coords_raw, last.left_stick_coords_raw]
+            .into_iter()
+            .map(VectorI8::clamped)
+            .find(|s| raw_magnitude(*s) >= SDI_STICK_MAGNITUDE);
+
+        let travel = launch_speed * launch_speed / (2.0 * KNOCKBACK_DECAY);
+        let mut is_kill_percent = false;
+        let mut di_quality = None;
+        if let Some(geometry) = geometry.as_ref() {
+            let distance = |angle| blast_zone_distance(geometry, last.position, angle);
+            is_kill_percent = travel >= distance(launch_angle);
+
+            let (mut best, mut worst) = (f32::MIN, f32::MAX);
+            for step in 0..=36 {
+                let d = distance(launch_angle - DI_MAX_ANGLE + step as f32);
+                best = best.max(d);
+                worst = worst.min(d);
+            }
+            if best - worst >= 1.0 {
+                di_quality = Some(((distance(di_angle) - worst) / (best - worst)).clamp(0.0, 1.0));
+            }
+        }
+
+        let attacker = (frame.last_hit_by < 4).then_some(frame.last_hit_by);
+        let hit_id = attacker.map(|a| (a, frame.last_hit_by_instance_id));
+        let multi_hit = hit_id.is_some() && hit_id == prev_hit;
+        if multi_hit {
+            if let Some(prev) = hits.last_mut() { prev.multi_hit = true; }
+        }
+        prev_hit = hit_id;
+
+        hits.push(HitDi {
+            victim: port as u8,
+            attacker,
+            instance_id: frame.last_hit_by_instance_id,
+            frame_idx: start,
+            hitlag_frames: (end - start) as u32,
+            launch_angle,
+            di_angle,
+            launch_speed,
+            di_stick,
+            sdi_inputs,
+            asdi,
+            multi_hit,
+            is_kill_percent,
+            di_quality,
+        });
+    }
+
+    hits
+}
+
+impl DiReport {
+    pub fn from_hits(hits: Vec<HitDi>) -> Self {
+        let mut report = DiReport::default();
+
+        let mut quality_sum = 0.0;
+        let mut quality_count = 0;
+        let mut survival = 0;
+        for hit in hits.iter().filter(|h| h.is_kill_percent) {
+            report.kill_percent_hits += 1;
+            if let Some(quality) = hit.di_quality {
+                quality_sum += quality;
+                quality_count += 1;
+                survival += (quality >= SURVIVAL_DI_QUALITY) as u32;
+            }
+        }
+        if quality_count != 0 {
+            report.kill_percent_di_quality = Some(quality_sum / quality_count as f32);
+            report.survival_di_consistency = Some(survival as f32 / quality_count as f32);
+        }
+
+        let mut sdi_inputs = 0;
+        let mut prev: Option<&HitDi> = None;
+        for hit in hits.iter() {
+            if hit.multi_hit {
+                sdi_inputs += hit.sdi_inputs;
+                let same_move = prev.is_some_and(|p| p.multi_hit && p.attacker == hit.attacker && p.instance_id == hit.instance_id);
+                if !same_move { report.multi_hit_moves += 1; }
+            }
+            prev = Some(hit);
+        }
+        if report.multi_hit_moves != 0 {
+            report.sdi_per_multi_hit = Some(sdi_inputs as f32 / report.multi_hit_moves as f32);
+        }
+
+        if !hits.is_empty() {
+            report.asdi_rate = Some(hits.iter().filter(|h| h.asdi.is_some()).count() as f32 / hits.len() as f32);
+        }
+
+        report.hits = hits;
+        report
+    }
+}
+
+/// DI reports for each used port.
+pub fn compute_di_reports(game: &Game) -> [Option<DiReport>; 4] {
+    std::array::from_fn(|port| {
+        game.frames[port].as_ref()?;
+        Some(DiReport::from_hits(hit_di(game, port)))
+    })
+}
+
+#[cfg(test)]
+fn di_game() -> Game {
+    let mut game = parse_file(&test_replay()).unwrap();
+    assert_eq!(game.info.stage, Stage::Battlefield);
+    let mut frames = test_frames(&game, 1, 400);
+    for f in frames.iter_mut() { f.last_hit_by = 0; }
+
+    let mut hit = |range: std::ops::Range<usize>, percent: f32, speed: f32, instance_id: u16| {
+        for (k, f) in frames[range.clone()].iter_mut().enumerate() {
+            f.hitlag_frames = (range.len() - k) as f32;
+            f.hit_velocity = Vector { x: speed, y: 0.0 };
+        }
+        for f in frames[range.start..].iter_mut() { f.percent = percent; f.last_hit_by_instance_id = instance_id; }
+    };
+    // launched straight right at kill percent, DI up then no DI
+    hit(100..104, 10.0, 5.0, 1);
+    hit(200..204, 20.0, 5.0, 2);
+    // a two hit move with weak knockback
+    hit(300..302, 25.0, 1.0, 3);
+    hit(303..305, 30.0, 1.0, 3);
+
+    frames[103].left_stick_coords = Vector { x: 0.0, y: 1.0 };
+    frames[201].left_stick_coords_raw = VectorI8 { x: 80, y: 0 };
+    frames[202].left_stick_coords_raw = VectorI8 { x: 0, y: 80 };
+    frames[203].left_stick_coords_raw = VectorI8 { x: 80, y: 0 };
+    frames[203].right_stick_coords_raw = VectorI8 { x: 0, y: -80 };
+    frames[301].left_stick_coords_raw = VectorI8 { x: 80, y: 0 };
+    frames[304].left_stick_coords_raw = VectorI8 { x: 0, y: -80 };
+    game.frames[1] = Some(frames.into_boxed_slice());
+    game
+}
+
+#[test]
+fn di_angle_and_quality() {
+    let hits = hit_di(&di_game(), 1);
+    assert_eq!(hits.len(), 4);
+    assert_eq!(hits[0].attacker, Some(0));
+    assert!((hits[0].di_angle - 18.0).abs() < 0.01);
+    assert!(hits[0].is_kill_percent && hits[1].is_kill_percent && !hits[2].is_kill_percent);
+    assert_eq!((hits[0].di_quality, hits[1].di_quality), (Some(1.0), Some(0.0)));
+}
+
+#[test]
+fn sdi_asdi_and_multi_hits() {
+    let hits = hit_di(&di_game(), 1);
+    assert_eq!((hits[1].sdi_inputs, hits[1].asdi), (3, Some(VectorI8 { x: 0, y: -80 })));
+    assert_eq!(hits.iter().map(|h| h.multi_hit).collect::<Vec<_>>(), vec![false, false, true, true]);
+}
+
+#[test]
+fn di_report() {
+    let game = di_game();
+    let report = DiReport::from_hits(hit_di(&game, 1));
+    assert_eq!(report.kill_percent_hits, 2);
+    assert_eq!((report.kill_percent_di_quality, report.survival_di_consistency), (Some(0.5), Some(0.5)));
+    assert_eq!((report.multi_hit_moves, report.sdi_per_multi_hit), (1, Some(2.0)));
+    assert_eq!(report.asdi_rate, Some(0.75));
+    assert_eq!(compute_di_reports(&game)[0].as_ref().unwrap().kill_percent_hits, 0);
+}
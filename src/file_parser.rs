@@ -1,17 +1,18 @@
 use crate::*;
 
-const EVENT_PAYLOADS:       u8 = 0x35;
-const GAME_START:           u8 = 0x36;
-const PRE_FRAME_UPDATE:     u8 = 0x37;
-const POST_FRAME_UPDATE:    u8 = 0x38;
-const GAME_END:             u8 = 0x39;
-//const FRAME_START:          u8 = 0x3A;
-const ITEM_UPDATE:          u8 = 0x3B;
-const FRAME_BOOKEND:        u8 = 0x3C;
-const FOD_INFO:             u8 = 0x3F;
-const DREAMLAND_INFO:       u8 = 0x40;
-const STADIUM_INFO:         u8 = 0x41;
-//const GECKO_LIST:           u8 = 0x3D;
+pub(crate) const EVENT_PAYLOADS:       u8 = 0x35;
+pub(crate) const GAME_START:           u8 = 0x36;
+pub(crate) const PRE_FRAME_UPDATE:     u8 = 0x37;
+pub(crate) const POST_FRAME_UPDATE:    u8 = 0x38;
+pub(crate) const GAME_END:             u8 = 0x39;
+pub(crate) const FRAME_START:          u8 = 0x3A;
+pub(crate) const ITEM_UPDATE:          u8 = 0x3B;
+pub(crate) const FRAME_BOOKEND:        u8 = 0x3C;
+pub(crate) const FOD_INFO:             u8 = 0x3F;
+pub(crate) const DREAMLAND_INFO:       u8 = 0x40;
+pub(crate) const STADIUM_INFO:         u8 = 0x41;
+pub(crate) const GECKO_LIST:           u8 = 0x3D;
+pub(crate) const MESSAGE_SPLITTER:     u8 = 0x10;
 
 pub const MAX_SUPPORTED_SLPZ_VERSION: u32 = 0;
 
@@ -21,33 +22,65 @@ pub const HEADER_LEN: u64 = 15;
 pub const MIN_VERSION_MAJOR: u8 = 3;
 pub const MIN_VERSION_MINOR: u8 = 0;
 
-fn read_array<const SIZE: usize>(bytes: &[u8], offset: usize) -> [u8; SIZE] {
-    if offset + SIZE > bytes.len() { return [0u8; SIZE]; }
-    bytes[offset..][..SIZE].try_into().unwrap()
+pub(crate) fn read_array<const SIZE: usize>(bytes: &[u8], offset: usize) -> [u8; SIZE] {
+    match bytes.get(offset..).and_then(|b| b.first_chunk::<SIZE>()) {
+        Some(array) => *array,
+        None => [0u8; SIZE],
+    }
 }
-fn read_f32(bytes: &[u8], offset: usize) -> f32 { f32::from_be_bytes(read_array(bytes, offset)) }
-fn read_u32(bytes: &[u8], offset: usize) -> u32 { u32::from_be_bytes(read_array(bytes, offset)) }
-fn read_u16(bytes: &[u8], offset: usize) -> u16 { u16::from_be_bytes(read_array(bytes, offset)) }
-fn read_u8 (bytes: &[u8], offset: usize) -> u8  {  u8::from_be_bytes(read_array(bytes, offset)) }
-fn read_i32(bytes: &[u8], offset: usize) -> i32 { i32::from_be_bytes(read_array(bytes, offset)) }
-fn read_i8 (bytes: &[u8], offset: usize) -> i8  {  i8::from_be_bytes(read_array(bytes, offset)) }
+pub(crate) fn read_f32(bytes: &[u8], offset: usize) -> f32 { f32::from_be_bytes(read_array(bytes, offset)) }
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> u32 { u32::from_be_bytes(read_array(bytes, offset)) }
+pub(crate) fn read_u16(bytes: &[u8], offset: usize) -> u16 { u16::from_be_bytes(read_array(bytes, offset)) }
+pub(crate) fn read_u8 (bytes: &[u8], offset: usize) -> u8  {  u8::from_be_bytes(read_array(bytes, offset)) }
+pub(crate) fn read_i32(bytes: &[u8], offset: usize) -> i32 { i32::from_be_bytes(read_array(bytes, offset)) }
+pub(crate) fn read_i8 (bytes: &[u8], offset: usize) -> i8  {  i8::from_be_bytes(read_array(bytes, offset)) }
 
-type EventSizes = [u16; 255];
+pub(crate) type EventSizes = [u16; 256];
 
 pub fn parse_file_slpz(slpz: &[u8]) -> SlpResult<Game> {
+    parse_file_slpz_detailed(slpz).map_err(SlpError::from)
+}
+
+/// Same as `parse_file_slpz`, but the error records where parsing failed.
+pub fn parse_file_slpz_detailed(slpz: &[u8]) -> DetailedSlpResult<Game> {
     let mut decompressor = slpz::Decompressor::new().ok_or(SlpError::ZstdInitError)?;
     let slp = slpz::decompress(&mut decompressor, slpz)
         .map_err(|_| SlpError::InvalidFile(InvalidLocation::SlpzDecompression))?;
-    parse_file(&slp)
+    parse_file_detailed(&slp)
+}
+
+pub fn parse_file_slpz_ext(slpz: &[u8]) -> SlpResult<Game> {
+    let mut decompressor = slpz::Decompressor::new().ok_or(SlpError::ZstdInitError)?;
+    let slp = slpz::decompress(&mut decompressor, slpz)
+        .map_err(|_| SlpError::InvalidFile(InvalidLocation::SlpzDecompression))?;
+    parse_file_ext(&slp)
 }
 
 pub fn parse_file(slp: &[u8]) -> SlpResult<Game> {
+    parse_file_inner(slp, false).map_err(SlpError::from)
+}
+
+/// Same as `parse_file`, but also fills `Game::frames_ext` and `Game::follower_frames_ext`.
+pub fn parse_file_ext(slp: &[u8]) -> SlpResult<Game> {
+    parse_file_inner(slp, true).map_err(SlpError::from)
+}
+
+/// Same as `parse_file`, but the error records where parsing failed.
+pub fn parse_file_detailed(slp: &[u8]) -> DetailedSlpResult<Game> {
+    parse_file_inner(slp, false)
+}
+
+fn parse_file_inner(slp: &[u8], extended: bool) -> DetailedSlpResult<Game> {
     // parse header and metadata --------------------------------------------------------
 
     let RawHeaderRet { event_sizes_offset, metadata_offset } = parse_raw_header(slp)?;
-    let EventSizesRet { game_start_offset, event_sizes } = event_sizes(slp, event_sizes_offset)?;
+    let EventSizesRet { game_start_offset, event_sizes } = event_sizes(slp, event_sizes_offset)
+        .map_err(|kind| DetailedSlpError { offset: Some(event_sizes_offset), command: Some(EVENT_PAYLOADS), ..kind.into() })?;
     let game_start_size = event_sizes[GAME_START as usize] as usize + 1;
-    let game_start = parse_game_start(&slp[game_start_offset..][..game_start_size])?;
+    let game_start_bytes = slp.get(game_start_offset..game_start_offset+game_start_size).unwrap_or(&[]);
+    let game_start = parse_game_start(game_start_bytes)
+        .map_err(|kind| DetailedSlpError { offset: Some(game_start_offset), command: Some(GAME_START), ..kind.into() })?;
+    let version = Some((game_start.version_major, game_start.version_minor, game_start.version_patch));
     
     let metadata = if metadata_offset == 0 {
         // occasionally the raw len is written incorrectly. Just skip parsing in this case.
@@ -55,7 +88,7 @@ pub fn parse_file(slp: &[u8]) -> SlpResult<Game> {
     } else if metadata_offset < slp.len() {
         parse_metadata(&slp[metadata_offset..])
     } else {
-        return Err(SlpError::InvalidFile(InvalidLocation::Metadata));
+        return Err(DetailedSlpError { offset: Some(metadata_offset), version, ..InvalidLocation::Metadata.into() });
     };
 
     // setup mem for event parsing --------------------------------------------------------
@@ -66,21 +99,26 @@ pub fn parse_file(slp: &[u8]) -> SlpResult<Game> {
     struct FrameWriteOp {
         pub from_idx: usize,
         pub to: Vec<Frame>,
+        pub to_ext: Vec<FrameExt>, // left empty unless extended
     }
     let mut frame_ops = [
-        FrameWriteOp { from_idx: 0, to: Vec::new() }, FrameWriteOp { from_idx: 0, to: Vec::new() },
-        FrameWriteOp { from_idx: 0, to: Vec::new() }, FrameWriteOp { from_idx: 0, to: Vec::new() },
-        FrameWriteOp { from_idx: 0, to: Vec::new() }, FrameWriteOp { from_idx: 0, to: Vec::new() },
-        FrameWriteOp { from_idx: 0, to: Vec::new() }, FrameWriteOp { from_idx: 0, to: Vec::new() },
+        FrameWriteOp { from_idx: 0, to: Vec::new(), to_ext: Vec::new() }, FrameWriteOp { from_idx: 0, to: Vec::new(), to_ext: Vec::new() },
+        FrameWriteOp { from_idx: 0, to: Vec::new(), to_ext: Vec::new() }, FrameWriteOp { from_idx: 0, to: Vec::new(), to_ext: Vec::new() },
+        FrameWriteOp { from_idx: 0, to: Vec::new(), to_ext: Vec::new() }, FrameWriteOp { from_idx: 0, to: Vec::new(), to_ext: Vec::new() },
+        FrameWriteOp { from_idx: 0, to: Vec::new(), to_ext: Vec::new() }, FrameWriteOp { from_idx: 0, to: Vec::new(), to_ext: Vec::new() },
     ];
     let mut frame_op_count = 0;
 
-    let frame_count_heuristic = (metadata.duration + 123) as usize + 1;
+    // the metadata may be corrupt, so this is bounded the same as frame indices.
+    let frame_count_heuristic = usize::try_from(metadata.duration.saturating_add(124)).unwrap_or(0).min(slp.len());
+    let ext_count_heuristic = if extended { frame_count_heuristic } else { 0 };
+    let mut frame_starts = vec![FrameStart::NULL; frame_count_heuristic];
     for i in 0..4 {
         if let Some(ch_colour) = game_start.starting_character_colours[i] {
             frame_ops[frame_op_count] = FrameWriteOp {
                 from_idx: i,
                 to: vec![Frame::NULL; frame_count_heuristic],
+                to_ext: vec![FrameExt::NULL; ext_count_heuristic],
             };
             frame_op_count += 1;
 
@@ -88,22 +126,37 @@ pub fn parse_file(slp: &[u8]) -> SlpResult<Game> {
                 frame_ops[frame_op_count] = FrameWriteOp {
                     from_idx: i + 4,
                     to: vec![Frame::NULL; frame_count_heuristic],
+                    to_ext: vec![FrameExt::NULL; ext_count_heuristic],
                 };
                 frame_op_count += 1;
             }
         }
     }
+    if frame_op_count == 0 {
+        return Err(DetailedSlpError { offset: Some(game_start_offset), command: Some(GAME_START), version, ..InvalidLocation::GameStart.into() });
+    }
 
     let mut pre_frame_temp = [PreFrameUpdate::NULL; 8];
     let mut post_frame_temp = [PostFrameUpdate::NULL; 8];
 
+    // only written if extended
+    let mut pre_frame_bytes_temp: [&[u8]; 8] = [&[]; 8];
+    let mut post_frame_bytes_temp: [&[u8]; 8] = [&[]; 8];
+
     let mut stage_info = None;
+    let mut game_end = None;
+    let mut gecko_list = Vec::new();
+    let mut gecko_codes = None;
 
     // event parsing --------------------------------------------------------
 
+    // last frame number seen, for errors
+    let mut frame = None;
+
     let mut event_cursor = game_start_offset + game_start_size;
     let end = if metadata_offset == 0 { slp.len() } else { metadata_offset };
     while event_cursor < end {
+        let event_offset = event_cursor;
         let event_cmd = slp[event_cursor];
         let event_size = event_sizes[event_cmd as usize] as usize + 1;
         
@@ -116,29 +169,53 @@ pub fn parse_file(slp: &[u8]) -> SlpResult<Game> {
         let event_bytes = &slp[event_cursor..][..event_size];
         event_cursor += event_size;
 
+        if let (FRAME_START | PRE_FRAME_UPDATE | POST_FRAME_UPDATE | FRAME_BOOKEND, 5..) = (event_cmd, event_size) {
+            frame = Some(read_i32(event_bytes, 0x1));
+        }
+        let err = |kind: SlpError| DetailedSlpError {
+            offset: Some(event_offset),
+            command: Some(event_cmd),
+            frame,
+            version,
+            ..kind.into()
+        };
+
         match event_cmd {
+            FRAME_START => {
+                let frame_start = parse_frame_start(event_bytes).map_err(err)?;
+                let frame_idx = frame_idx(event_bytes, slp.len())
+                    .ok_or(err(SlpError::InvalidFile(InvalidLocation::FrameStart)))?;
+
+                // same as frames, just overwrite on rollback
+                if frame_starts.len() <= frame_idx { frame_starts.resize(frame_idx+1, FrameStart::NULL); }
+                frame_starts[frame_idx] = frame_start;
+            }
             ITEM_UPDATE => {
-                items.push(parse_item_update(event_bytes)?);
+                items.push(parse_item_update(event_bytes).map_err(err)?);
             }
             PRE_FRAME_UPDATE => {
-                let pre_frame = parse_pre_frame_update(event_bytes)?;
+                let pre_frame = parse_pre_frame_update(event_bytes).map_err(err)?;
                 let mut temp_idx = pre_frame.port_idx as usize;
                 if pre_frame.is_follower { temp_idx += 4 }
                 
                 // In the case that game end is not written, and the raw len is zero,
                 // we probably ended up reading the metadata as events. So we break
                 // and hope for the best.
-                if temp_idx > pre_frame_temp.len() { break; }
+                if temp_idx >= pre_frame_temp.len() { break; }
                 pre_frame_temp[temp_idx] = pre_frame;
+                if extended { pre_frame_bytes_temp[temp_idx] = event_bytes; }
             }
             POST_FRAME_UPDATE => {
-                let post_frame = parse_post_frame_update(event_bytes)?;
+                let post_frame = parse_post_frame_update(event_bytes).map_err(err)?;
                 let mut temp_idx = post_frame.port_idx as usize;
                 if post_frame.is_follower { temp_idx += 4 }
+                if temp_idx >= post_frame_temp.len() { break; }
                 post_frame_temp[temp_idx] = post_frame;
+                if extended { post_frame_bytes_temp[temp_idx] = event_bytes; }
             }
             FRAME_BOOKEND => {
-                let frame_idx = (read_i32(event_bytes, 0x1) + 123) as usize;
+                let frame_idx = frame_idx(event_bytes, slp.len())
+                    .ok_or(err(SlpError::InvalidFile(InvalidLocation::EventSlicing)))?;
 
                 for i in 0..frame_op_count {
                     let op = &mut frame_ops[i];
@@ -148,9 +225,20 @@ pub fn parse_file(slp: &[u8]) -> SlpResult<Game> {
                     // no need to special case rollback, just overwrite the frame
                     if op.to.len() <= frame_idx { op.to.resize(frame_idx+1, Frame::NULL); }
                     op.to[frame_idx] = merge_pre_post_frames(pre, post);
+
+                    if extended {
+                        let pre_bytes = pre_frame_bytes_temp[op.from_idx];
+                        let post_bytes = post_frame_bytes_temp[op.from_idx];
+                        if op.to_ext.len() <= frame_idx { op.to_ext.resize(frame_idx+1, FrameExt::NULL); }
+                        op.to_ext[frame_idx] = parse_frame_ext(pre_bytes, post_bytes, &game_start).map_err(err)?;
+                    }
                 }
 
-                if item_idx.len() != frame_idx + 1 {
+                if item_idx.len() < frame_idx + 1 {
+                    // skipped frames have no items
+                    let last = item_idx[item_idx.len()-1];
+                    item_idx.resize(frame_idx + 1, last);
+                } else if item_idx.len() != frame_idx + 1 {
                     // handle rollback
 
                     // remove items from rollback frame until the items added this frame
@@ -163,30 +251,21 @@ pub fn parse_file(slp: &[u8]) -> SlpResult<Game> {
                 item_idx.push(items.len() as u32);
             }
             FOD_INFO => {
-                let fountain_heights = match stage_info {
-                    Some(StageInfo::Fountain(ref mut heights)) => heights,
-                    None => {
-                        stage_info = Some(StageInfo::Fountain(FountainHeights {
-                            heights_l: Vec::new(),
-                            heights_r: Vec::new(),
-                        }));
-
-                        match stage_info {
-                            Some(StageInfo::Fountain(ref mut heights)) => heights,
-                            _ => unreachable!(),
-                        }
-                    },
-                    _ => unreachable!(),
-                };
-
-                let frame_idx = (read_i32(event_bytes, 0x1) + 123) as u32;
+                let invalid = || err(SlpError::InvalidFile(InvalidLocation::FountainPlatformHeight));
+                let info = stage_info.get_or_insert_with(|| StageInfo::Fountain(FountainHeights {
+                    heights_l: Vec::new(),
+                    heights_r: Vec::new(),
+                }));
+                let StageInfo::Fountain(fountain_heights) = info else { return Err(invalid()) };
+
+                let frame_idx = frame_idx(event_bytes, slp.len()).ok_or_else(invalid)? as u32;
                 let plat = read_u8(event_bytes, 0x5);
                 let height = read_f32(event_bytes, 0x6);
 
                 let arr = match plat {
                     0 => &mut fountain_heights.heights_r,
                     1 => &mut fountain_heights.heights_l,
-                    _ => unreachable!()
+                    _ => return Err(invalid()),
                 };
 
                 // handle rollback (a little silly, but should work)
@@ -201,24 +280,42 @@ pub fn parse_file(slp: &[u8]) -> SlpResult<Game> {
 
                 arr.push((frame_idx, height));
             }
-            DREAMLAND_INFO => {} // TODO
-            STADIUM_INFO => {
-                let transformations = match stage_info {
-                    Some(StageInfo::Stadium(ref mut transformations)) => transformations,
-                    None => {
-                        stage_info = Some(StageInfo::Stadium(StadiumTransformations {
-                            events: Vec::new(),
-                        }));
-
-                        match stage_info {
-                            Some(StageInfo::Stadium(ref mut transformations)) => transformations,
-                            _ => unreachable!(),
-                        }
-                    },
-                    _ => unreachable!(),
+            DREAMLAND_INFO => {
+                let invalid = || err(SlpError::InvalidFile(InvalidLocation::WhispyBlowDirection));
+                let info = stage_info.get_or_insert_with(|| StageInfo::DreamLand(WhispyBlowDirections {
+                    events: Vec::new(),
+                }));
+                let StageInfo::DreamLand(blow_directions) = info else { return Err(invalid()) };
+
+                let frame_idx = frame_idx(event_bytes, slp.len()).ok_or_else(invalid)? as u32;
+                let direction = match read_u8(event_bytes, 0x5) {
+                    0 => WhispyDirection::None,
+                    1 => WhispyDirection::Left,
+                    2 => WhispyDirection::Right,
+                    _ => return Err(invalid()),
                 };
 
-                let frame_idx = (read_i32(event_bytes, 0x1) + 123) as u32;
+                // handle rollback (a little silly, but should work)
+                let events = &mut blow_directions.events;
+                for i in 0..8 {
+                    if events.len() > i {
+                        let i_rev = events.len() - i - 1;
+                        if events[i_rev].0 >= frame_idx {
+                            events.truncate(i_rev);
+                        }
+                    }
+                }
+
+                events.push((frame_idx, direction));
+            }
+            STADIUM_INFO => {
+                let invalid = || err(SlpError::InvalidFile(InvalidLocation::StadiumTransformation));
+                let info = stage_info.get_or_insert_with(|| StageInfo::Stadium(StadiumTransformations {
+                    events: Vec::new(),
+                }));
+                let StageInfo::Stadium(transformations) = info else { return Err(invalid()) };
+
+                let frame_idx = frame_idx(event_bytes, slp.len()).ok_or_else(invalid)? as u32;
                 let event = read_u16(event_bytes, 0x5);
                 let transformation_id = read_u16(event_bytes, 0x7);
 
@@ -230,58 +327,107 @@ pub fn parse_file(slp: &[u8]) -> SlpResult<Game> {
                         5 => StadiumTransformation::Normal,
                         6 => StadiumTransformation::Rock,
                         9 => StadiumTransformation::Water,
-                        _ => return Err(SlpError::InvalidFile(InvalidLocation::StadiumTransformation)),
+                        _ => return Err(invalid()),
                     };
 
                     // Shouldn't rollback, as slippi doesn't use transformations
                     transformations.events.push((frame_idx, transformation));
                 }
             }
-            GAME_END => break,
+            // The gecko list is the only event sent through the message splitter.
+            MESSAGE_SPLITTER if read_u8(event_bytes, 0x203) == GECKO_LIST => {
+                let size = read_u16(event_bytes, 0x201) as usize;
+                let data = event_bytes.get(1..1+size)
+                    .ok_or(err(SlpError::InvalidFile(InvalidLocation::GeckoList)))?;
+                gecko_list.extend_from_slice(data);
+
+                if read_u8(event_bytes, 0x204) != 0 {
+                    let raw = std::mem::take(&mut gecko_list).into_boxed_slice();
+                    gecko_codes = Some(GeckoCodes::new(raw));
+                }
+            }
+            GAME_END => {
+                game_end = Some(parse_game_end(event_bytes).map_err(err)?);
+                break;
+            }
             _ => {}
         }
     }
 
     // finish up --------------------------------------------------------
 
-    let info = merge_metadata(game_start, metadata);
-    let notes = if metadata_offset == 0 {
-        Notes::NULL
-    } else {
-        parse_notes(&slp[metadata_offset..])
+    let info = merge_metadata(game_start, &metadata, game_end);
+    let notes = match metadata.raw.get("notes") {
+        Some(notes) => notes_from_ubjson(notes),
+        None => Notes::NULL,
     };
 
     let mut frames = [None, None, None, None];
     let mut follower_frames = [None, None, None, None];
+    let mut frames_ext = [None, None, None, None];
+    let mut follower_frames_ext = [None, None, None, None];
+
+    // the frame count heuristic may overshoot, only keep frames up to the last frame bookend
+    let parsed_frame_count = item_idx.len() - 1;
 
     for i in 0..frame_op_count {
         let op = &mut frame_ops[i];
+        op.to.truncate(parsed_frame_count);
+        op.to_ext.truncate(parsed_frame_count);
 
         let to = std::mem::replace(&mut op.to, Vec::new());
         let to = Some(to.into_boxed_slice());
+        let to_ext = if extended {
+            let to_ext = std::mem::take(&mut op.to_ext);
+            Some(to_ext.into_boxed_slice())
+        } else {
+            None
+        };
         if op.from_idx < 4 {
             frames[op.from_idx] = to;
+            frames_ext[op.from_idx] = to_ext;
         } else {
             follower_frames[op.from_idx - 4] = to;
+            follower_frames_ext[op.from_idx - 4] = to_ext;
         }
     }
 
-    let frame_count = frames.iter().find(|f| f.is_some()).unwrap().as_ref().unwrap().len();
+    let frame_count = frames.iter().chain(follower_frames.iter()).flatten().next().map_or(0, |f| f.len());
+    frame_starts.resize(frame_count, FrameStart::NULL);
+    for ext in frames_ext.iter_mut().chain(follower_frames_ext.iter_mut()).flatten() {
+        let mut to_ext = std::mem::take(ext).into_vec();
+        to_ext.resize(frame_count, FrameExt::NULL);
+        *ext = to_ext.into_boxed_slice();
+    }
 
     let game = Game {
         frame_count,
         frames,
         follower_frames,
+        frames_ext,
+        follower_frames_ext,
+        frame_starts: frame_starts.into(),
         item_idx: item_idx.into(),
         items: items.into(),
         info,
         stage_info,
+        gecko_codes,
         notes,
+        metadata,
+        game_start_raw: game_start_bytes.into(),
     };
 
     Ok(game)
 }
 
+/// Frame number + 123 of a frame event.
+/// None if it is before the first frame, or too large to fit in the file
+/// (every frame takes at least a byte), so corrupt files can't cause huge allocations.
+fn frame_idx(event_bytes: &[u8], file_len: usize) -> Option<usize> {
+    let frame_idx = usize::try_from(read_i32(event_bytes, 0x1).checked_add(123)?).ok()?;
+    (frame_idx <= file_len).then_some(frame_idx)
+}
+
 // EVENTS ------------------------------------------------------------------------
 
 pub fn parse_game_start(game_start: &[u8]) -> SlpResult<GameStart> {
@@ -307,14 +453,20 @@ pub fn parse_game_start(game_start: &[u8]) -> SlpResult<GameStart> {
         .ok_or(SlpError::InvalidFile(InvalidLocation::GameStart))?;
 
     let timer = read_u32(game_info_block, 0x10);
+    let item_spawn_frequency = read_i8(game_info_block, 0xB);
+    let item_spawn_bitfield = read_array::<5>(game_info_block, 0x23);
+    let damage_ratio = read_f32(game_info_block, 0x30).into();
     
     let mut starting_character_colours = [None; 4];
+    let mut player_settings = [PlayerSettings::NULL; 4];
     let mut teams = [0; 4];
     let mut names = [[0u8; 31]; 4];
     let mut connect_codes = [[0u8; 10]; 4];
 
     for i in 0..4 {
-        if read_u8(game_info_block, 0x61 + 0x24*i) == 3 { continue; }
+        let player_type = PlayerType::from_u8(read_u8(game_info_block, 0x61 + 0x24*i))
+            .ok_or(SlpError::InvalidFile(InvalidLocation::GameStart))?;
+        if player_type == PlayerType::Empty { continue; }
 
         let character = Character::from_u8_external(read_u8(game_info_block, 0x60 + 0x24*i))
             .ok_or(SlpError::InvalidFile(InvalidLocation::GameStart))?;
@@ -322,11 +474,26 @@ pub fn parse_game_start(game_start: &[u8]) -> SlpResult<GameStart> {
         let costume_idx = read_u8(game_info_block, 0x63 + 0x24*i);
         let character_colour: CharacterColour = CharacterColour::from_character_and_colour(character, costume_idx)
         // if a player is using a mod that adds more character slots then we default to the neutral costume.
-            .unwrap_or_else(|| CharacterColour::from_character_and_colour(character, 0).unwrap());
+            .or_else(|| CharacterColour::from_character_and_colour(character, 0))
+            .ok_or(SlpError::InvalidFile(InvalidLocation::GameStart))?;
 
         starting_character_colours[i] = Some(character_colour);
         names[i] = read_array::<31>(game_start, 0x1A5 + 0x1F*i);
         connect_codes[i] = read_array::<10>(game_start, 0x221 + 0xA*i);
+
+        player_settings[i] = PlayerSettings {
+            player_type,
+            cpu_level       : read_u8(game_info_block, 0x6F + 0x24*i),
+            stocks          : read_u8(game_info_block, 0x62 + 0x24*i),
+            handicap        : read_u8(game_info_block, 0x68 + 0x24*i),
+            stamina_mode    : read_u8(game_info_block, 0x6C + 0x24*i) & 0x01 != 0,
+            offense_ratio   : read_f32(game_info_block, 0x78 + 0x24*i).into(),
+            defense_ratio   : read_f32(game_info_block, 0x7C + 0x24*i).into(),
+            model_scale     : read_f32(game_info_block, 0x80 + 0x24*i).into(),
+            // older replays may have garbage here, so we don't error.
+            ucf_dashback    : UcfSetting::from_u32(read_u32(game_start, 0x141 + 0x8*i)).unwrap_or(UcfSetting::Off),
+            ucf_shield_drop : UcfSetting::from_u32(read_u32(game_start, 0x145 + 0x8*i)).unwrap_or(UcfSetting::Off),
+        };
         
         // parse team.
         if is_teams {
@@ -349,17 +516,39 @@ pub fn parse_game_start(game_start: &[u8]) -> SlpResult<GameStart> {
         connect_codes,
         teams,
         is_teams,
+        player_settings,
+        damage_ratio,
+        item_spawn_frequency,
+        item_spawn_bitfield,
+        random_seed       : read_u32(game_start, 0x13D),
+        is_pal            : read_u8(game_start, 0x1A1) != 0,
+        is_frozen_stadium : read_u8(game_start, 0x1A2) != 0,
+        minor_scene       : read_u8(game_start, 0x1A3),
+        major_scene       : read_u8(game_start, 0x1A4),
+        language          : if game_start.len() > 0x2BD { Language::from_u8(read_u8(game_start, 0x2BD)) } else { None },
+        match_id          : read_array::<51>(game_start, 0x2BE),
+        game_number       : read_u32(game_start, 0x2F1),
+        tiebreaker_number : read_u32(game_start, 0x2F5),
         version_major,
         version_minor,
         version_patch,
     })
 }
 
+pub fn parse_frame_start(frame_start: &[u8]) -> SlpResult<FrameStart> {
+    if frame_start.first() != Some(&FRAME_START) { return Err(SlpError::InvalidFile(InvalidLocation::FrameStart)); }
+
+    Ok(FrameStart {
+        random_seed         : read_u32(frame_start, 0x5),
+        scene_frame_counter : read_u32(frame_start, 0x9),
+    })
+}
+
 pub fn parse_item_update(item_update: &[u8]) -> SlpResult<ItemUpdate> {
-    if item_update[0] != ITEM_UPDATE { return Err(SlpError::InvalidFile(InvalidLocation::ItemUpdate)); }
+    if item_update.first() != Some(&ITEM_UPDATE) { return Err(SlpError::InvalidFile(InvalidLocation::ItemUpdate)); }
 
     Ok(ItemUpdate {
-        frame_idx            : (read_i32(item_update, 0x1) + 123) as u32,
+        frame_idx            : read_i32(item_update, 0x1).wrapping_add(123) as u32,
         type_id              : read_u16(item_update, 0x5),
         state                : read_u8(item_update, 0x7),
         direction            : if read_f32(item_update, 0x8) == 1.0 { Direction::Right } else { Direction::Left },
@@ -377,6 +566,36 @@ pub fn parse_item_update(item_update: &[u8]) -> SlpResult<ItemUpdate> {
     })
 }
 
+pub fn parse_game_end(game_end: &[u8]) -> SlpResult<GameEnd> {
+    if game_end.len() < 2 { return Err(SlpError::InvalidFile(InvalidLocation::GameEnd)); }
+    if game_end[0] != GAME_END { return Err(SlpError::InvalidFile(InvalidLocation::GameEnd)); }
+
+    // newer Slippi versions may add end methods, these should not make the replay unreadable
+    let method_byte = read_u8(game_end, 0x1);
+    let method = GameEndMethod::from_u8(method_byte).unwrap_or(GameEndMethod::Unknown(method_byte));
+
+    // The LRAS initiator was added in v2.0.0, and placements in v3.13.0.
+    // Check the event size rather than the version, as the event may be truncated otherwise.
+    let lras_initiator = if game_end.len() > 0x2 {
+        u8::try_from(read_i8(game_end, 0x2)).ok()
+    } else {
+        None
+    };
+
+    let mut placements = [None; 4];
+    if game_end.len() > 0x6 {
+        for (i, placement) in placements.iter_mut().enumerate() {
+            *placement = u8::try_from(read_i8(game_end, 0x3 + i)).ok();
+        }
+    }
+
+    Ok(GameEnd {
+        method,
+        lras_initiator,
+        placements,
+    })
+}
+
 pub type ButtonsMask = u16;
 pub mod buttons_mask {
     pub const D_PAD_LEFT  : u16 = 0b0000000000000001;
@@ -394,7 +613,7 @@ pub mod buttons_mask {
 }
 
 #[derive(Copy, Clone, Debug)]
-struct PreFrameUpdate {
+pub(crate) struct PreFrameUpdate {
     pub port_idx: u8,
     pub is_follower: bool,
     pub buttons_mask: ButtonsMask,
@@ -408,7 +627,7 @@ struct PreFrameUpdate {
 }
 
 impl PreFrameUpdate {
-    const NULL: PreFrameUpdate = PreFrameUpdate {
+    pub(crate) const NULL: PreFrameUpdate = PreFrameUpdate {
         port_idx: 0,
         is_follower: false,
         buttons_mask: 0,
@@ -461,8 +680,30 @@ impl Frame {
 }
 
 
-fn parse_pre_frame_update(pre_frame_update: &[u8]) -> SlpResult<PreFrameUpdate> {
-    if pre_frame_update[0] != PRE_FRAME_UPDATE { return Err(SlpError::InvalidFile(InvalidLocation::PreFrameUpdate)); }
+impl FrameExt {
+    pub const NULL: FrameExt = FrameExt {
+        random_seed             : 0,
+        pre_state_num           : 0,
+        pre_position            : Vector::NULL,
+        pre_direction           : Direction::Left,
+        processed_buttons       : 0,
+        pre_percent             : 0.0,
+        left_stick_coords_raw   : None,
+        right_stick_coords_raw  : None,
+        combo_count             : 0,
+        l_cancel_status         : LCancelStatus::None,
+        velocity                : None,
+        hit_velocity            : None,
+        ground_x_velocity       : None,
+        hitlag_frames           : None,
+        animation_idx           : None,
+        last_hit_by_instance_id : None,
+        instance_id             : None,
+    };
+}
+
+pub(crate) fn parse_pre_frame_update(pre_frame_update: &[u8]) -> SlpResult<PreFrameUpdate> {
+    if pre_frame_update.first() != Some(&PRE_FRAME_UPDATE) { return Err(SlpError::InvalidFile(InvalidLocation::PreFrameUpdate)); }
 
     Ok(PreFrameUpdate {
         port_idx                      : read_u8(pre_frame_update, 0x5),
@@ -491,7 +732,7 @@ fn parse_pre_frame_update(pre_frame_update: &[u8]) -> SlpResult<PreFrameUpdate>
 }
 
 #[derive(Copy, Clone, Debug)]
-struct PostFrameUpdate {
+pub(crate) struct PostFrameUpdate {
     pub port_idx: u8,
     pub is_follower: bool,
     pub character: Character,
@@ -520,7 +761,7 @@ struct PostFrameUpdate {
 }
 
 impl PostFrameUpdate {
-    const NULL: PostFrameUpdate = PostFrameUpdate {
+    pub(crate) const NULL: PostFrameUpdate = PostFrameUpdate {
         port_idx: 0,
         is_follower: false,
         character: Character::Mario,
@@ -549,8 +790,8 @@ impl PostFrameUpdate {
     };
 }
 
-fn parse_post_frame_update(post_frame_update: &[u8]) -> SlpResult<PostFrameUpdate> {
-    if post_frame_update[0] != POST_FRAME_UPDATE { return Err(SlpError::InvalidFile(InvalidLocation::PostFrameUpdate)); }
+pub(crate) fn parse_post_frame_update(post_frame_update: &[u8]) -> SlpResult<PostFrameUpdate> {
+    if post_frame_update.first() != Some(&POST_FRAME_UPDATE) { return Err(SlpError::InvalidFile(InvalidLocation::PostFrameUpdate)); }
 
     let character = Character::from_u8_internal(read_u8(post_frame_update, 0x7))
         .ok_or(SlpError::InvalidFile(InvalidLocation::PostFrameUpdate))?;
@@ -595,7 +836,53 @@ fn parse_post_frame_update(post_frame_update: &[u8]) -> SlpResult<PostFrameUpdat
     })
 }
 
-fn merge_pre_post_frames(pre: &PreFrameUpdate, post: &PostFrameUpdate) -> Frame {
+pub(crate) fn parse_frame_ext(pre_frame_update: &[u8], post_frame_update: &[u8], game_start: &GameStart) -> SlpResult<FrameExt> {
+    let v3_5  = game_start.min_version(3, 5, 0);
+    let v3_8  = game_start.min_version(3, 8, 0);
+    let v3_11 = game_start.min_version(3, 11, 0);
+    let v3_15 = game_start.min_version(3, 15, 0);
+    let v3_16 = game_start.min_version(3, 16, 0);
+    let v3_17 = game_start.min_version(3, 17, 0);
+
+    Ok(FrameExt {
+        random_seed             : read_u32(pre_frame_update, 0x7),
+        pre_state_num           : read_u16(pre_frame_update, 0xB),
+        pre_position            : Vector {
+            x                   : read_f32(pre_frame_update, 0xD),
+            y                   : read_f32(pre_frame_update, 0x11),
+        },
+        pre_direction           : if read_f32(pre_frame_update, 0x15) == 1.0 { Direction::Right } else { Direction::Left },
+        processed_buttons       : read_u32(pre_frame_update, 0x2D),
+        pre_percent             : read_f32(pre_frame_update, 0x3C),
+        left_stick_coords_raw   : v3_15.then(|| VectorI8 {
+            x                   : read_i8(pre_frame_update, 0x3B),
+            y                   : read_i8(pre_frame_update, 0x40),
+        }),
+        right_stick_coords_raw  : v3_17.then(|| VectorI8 {
+            x                   : read_i8(pre_frame_update, 0x41),
+            y                   : read_i8(pre_frame_update, 0x42),
+        }),
+
+        combo_count             : read_u8(post_frame_update, 0x1F),
+        l_cancel_status         : LCancelStatus::from_u8(read_u8(post_frame_update, 0x33))
+            .ok_or(SlpError::InvalidFile(InvalidLocation::PostFrameUpdate))?,
+        velocity                : v3_5.then(|| Vector {
+            x                   : read_f32(post_frame_update, 0x35),
+            y                   : read_f32(post_frame_update, 0x39),
+        }),
+        hit_velocity            : v3_5.then(|| Vector {
+            x                   : read_f32(post_frame_update, 0x3D),
+            y                   : read_f32(post_frame_update, 0x41),
+        }),
+        ground_x_velocity       : v3_5.then(|| read_f32(post_frame_update, 0x45)),
+        hitlag_frames           : v3_8.then(|| read_f32(post_frame_update, 0x49)),
+        animation_idx           : v3_11.then(|| read_u32(post_frame_update, 0x4D)),
+        last_hit_by_instance_id : v3_16.then(|| read_u16(post_frame_update, 0x51)),
+        instance_id             : v3_16.then(|| read_u16(post_frame_update, 0x53)),
+    })
+}
+
+pub(crate) fn merge_pre_post_frames(pre: &PreFrameUpdate, post: &PostFrameUpdate) -> Frame {
     Frame {
         character: post.character,
         port_idx: post.port_idx,   
@@ -650,7 +937,7 @@ pub fn event_sizes(slp: &[u8], event_sizes_offset: usize) -> SlpResult<EventSize
     if info_size == 0 { return Err(SlpError::InvalidFile(InvalidLocation::EventSizes)) }
     let event_count = (info_size - 1) / 3;
 
-    let mut event_sizes = [0; 255];
+    let mut event_sizes = [0; 256];
     for i in 0..event_count {
         let offset = event_sizes_offset + 2 + i*3;
         let command_byte = slp[offset] as usize;
@@ -681,7 +968,7 @@ pub fn parse_raw_header(slp: &[u8]) -> SlpResult<RawHeaderRet> {
     }
 
     let raw_len = read_u32(slp, HEADER.len()) as usize;
-    let metadata_offset = if raw_len == 0 { 0 } else { HEADER.len() + raw_len };
+    let metadata_offset = if raw_len == 0 { 0 } else { HEADER.len() + 4 + raw_len };
     Ok(RawHeaderRet {
         event_sizes_offset: HEADER.len() + 4,
         metadata_offset,
@@ -689,6 +976,10 @@ pub fn parse_raw_header(slp: &[u8]) -> SlpResult<RawHeaderRet> {
 }
 
 pub fn parse_file_info(reader: &mut (impl std::io::Read + std::io::Seek)) -> SlpResult<GameInfo> {
+    parse_file_info_inner(reader).map_err(SlpError::from)
+}
+
+pub(crate) fn parse_file_info_inner(reader: &mut (impl std::io::Read + std::io::Seek)) -> DetailedSlpResult<GameInfo> {
     let mut buf = [0u8; 1024];
     
     let mut read_count = reader.read(&mut buf)?;
@@ -701,10 +992,25 @@ pub fn parse_file_info(reader: &mut (impl std::io::Read + std::io::Seek)) -> Slp
     }
 
     let RawHeaderRet { event_sizes_offset, metadata_offset } = parse_raw_header(&buf)?;
-    let EventSizesRet { game_start_offset, event_sizes } = event_sizes(&buf, event_sizes_offset)?;
+    let EventSizesRet { game_start_offset, event_sizes } = event_sizes(&buf, event_sizes_offset)
+        .map_err(|kind| DetailedSlpError { offset: Some(event_sizes_offset), command: Some(EVENT_PAYLOADS), ..kind.into() })?;
     let game_start_size = event_sizes[GAME_START as usize] as usize + 1;
-    let game_start = parse_game_start(&buf[game_start_offset..][..game_start_size])?;
+    let game_start = parse_game_start(buf.get(game_start_offset..game_start_offset+game_start_size).unwrap_or(&[]))
+        .map_err(|kind| DetailedSlpError { offset: Some(game_start_offset), command: Some(GAME_START), ..kind.into() })?;
     
+    // Game end is the last event, so it sits right before the metadata.
+    // We can only find it if the raw length was written.
+    let game_end_size = event_sizes[GAME_END as usize] as usize + 1;
+    let end = if metadata_offset > game_start_offset + game_start_size + game_end_size {
+        let mut game_end = [0u8; 256];
+        let game_end = &mut game_end[..game_end_size.min(256)];
+        reader.seek(std::io::SeekFrom::Start((metadata_offset - game_end_size) as u64))?;
+        reader.read_exact(game_end)?;
+        parse_game_end(game_end).ok()
+    } else {
+        None
+    };
+
     let metadata = if metadata_offset != 0 {
         reader.seek(std::io::SeekFrom::Start(metadata_offset as u64))?;
         let read_count = reader.read(&mut buf)?;
@@ -715,10 +1021,16 @@ pub fn parse_file_info(reader: &mut (impl std::io::Read + std::io::Seek)) -> Slp
         Metadata::NULL
     };
     
-    Ok(merge_metadata(game_start, metadata))
+    Ok(merge_metadata(game_start, &metadata, end))
 }
 
+/// The game end is compressed with the rest of the events, so the whole file is read and decompressed to find it.
+/// `GameInfo::end` is None if the events fail to decompress.
 pub fn parse_file_info_slpz(reader: &mut (impl std::io::Read + std::io::Seek)) -> SlpResult<GameInfo> {
+    parse_file_info_slpz_inner(reader).map_err(SlpError::from)
+}
+
+pub(crate) fn parse_file_info_slpz_inner(reader: &mut (impl std::io::Read + std::io::Seek)) -> DetailedSlpResult<GameInfo> {
     let mut buf = [0u8; 4096];
     
     let mut read_count = reader.read(&mut buf)?;
@@ -731,7 +1043,7 @@ pub fn parse_file_info_slpz(reader: &mut (impl std::io::Read + std::io::Seek)) -
     }
 
     let version = read_u32(&buf, 0);
-    if version > MAX_SUPPORTED_SLPZ_VERSION { return Err(SlpError::TooNewFile) }
+    if version > MAX_SUPPORTED_SLPZ_VERSION { return Err(SlpError::TooNewFile.into()) }
 
     let event_sizes_offset = read_u32(&buf, 4) as usize;
     let game_start_offset = read_u32(&buf, 8) as usize;
@@ -744,17 +1056,30 @@ pub fn parse_file_info_slpz(reader: &mut (impl std::io::Read + std::io::Seek)) -
         read_count += read;
     }
 
-    let EventSizesRet { game_start_offset: _, event_sizes } = event_sizes(&buf, event_sizes_offset)?;
+    let EventSizesRet { game_start_offset: _, event_sizes } = event_sizes(&buf, event_sizes_offset)
+        .map_err(|kind| DetailedSlpError { offset: Some(event_sizes_offset), command: Some(EVENT_PAYLOADS), ..kind.into() })?;
     let game_start_size = event_sizes[GAME_START as usize] as usize + 1;
-    let game_start = parse_game_start(&buf[game_start_offset..][..game_start_size])?;
+    let game_start = parse_game_start(buf.get(game_start_offset..game_start_offset+game_start_size).unwrap_or(&[]))
+        .map_err(|kind| DetailedSlpError { offset: Some(game_start_offset), command: Some(GAME_START), ..kind.into() })?;
 
     // this will truncate the metadata if it contains diagrams, but that is perfectly fine, nothing we need is there.
-    let metadata = parse_metadata(&buf[metadata_offset..]);
+    let metadata = parse_metadata(buf.get(metadata_offset..).unwrap_or(&[]));
 
-    Ok(merge_metadata(game_start, metadata))
+    // Game end is compressed with the other events, and zstd can't decompress just the tail.
+    // Decompress the whole replay and read it from there.
+    reader.seek(std::io::SeekFrom::Start(0))?;
+    let mut slpz = Vec::new();
+    reader.read_to_end(&mut slpz)?;
+    let mut decompressor = slpz::Decompressor::new().ok_or(SlpError::ZstdInitError)?;
+    let end = match slpz::decompress(&mut decompressor, &slpz) {
+        Ok(slp) => parse_file_info_inner(&mut std::io::Cursor::new(&slp)).ok().and_then(|info| info.end),
+        Err(_) => None,
+    };
+
+    Ok(merge_metadata(game_start, &metadata, end))
 }
 
-fn merge_metadata(game_start: GameStart, metadata: Metadata) -> GameInfo {
+pub(crate) fn merge_metadata(game_start: GameStart, metadata: &Metadata, end: Option<GameEnd>) -> GameInfo {
     GameInfo {
         stage                      : game_start.stage,
         port_used                  : game_start.starting_character_colours.map(|c| c.is_some()),
@@ -767,6 +1092,20 @@ fn merge_metadata(game_start: GameStart, metadata: Metadata) -> GameInfo {
         duration                   : metadata.duration,
         has_notes                  : metadata.has_notes,
         is_teams                   : game_start.is_teams,
+        end,
+        player_settings            : game_start.player_settings,
+        damage_ratio               : game_start.damage_ratio,
+        item_spawn_frequency       : game_start.item_spawn_frequency,
+        item_spawn_bitfield        : game_start.item_spawn_bitfield,
+        random_seed                : game_start.random_seed,
+        is_pal                     : game_start.is_pal,
+        is_frozen_stadium          : game_start.is_frozen_stadium,
+        minor_scene                : game_start.minor_scene,
+        major_scene                : game_start.major_scene,
+        language                   : game_start.language,
+        match_id                   : game_start.match_id,
+        game_number                : game_start.game_number,
+        tiebreaker_number          : game_start.tiebreaker_number,
         version_major              : game_start.version_major,
         version_minor              : game_start.version_minor,
         version_patch              : game_start.version_patch,
@@ -807,11 +1146,29 @@ impl Notes {
     };
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Metadata {
     pub duration: i32,
     pub time: Time,
     pub has_notes: bool,
+    /// e.g. "dolphin", "nintendont" or "network". Empty if not written.
+    pub played_on: String,
+    /// Empty if not written.
+    pub console_nick: String,
+    pub players: [Option<MetadataPlayer>; 4],
+
+    /// All fields after the raw events, including `metadata` and `notes`.
+    pub raw: UbjsonValue,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetadataPlayer {
+    /// Frames played as each character.
+    pub character_frames: Vec<(Character, u32)>,
+    /// Empty if not played online.
+    pub netplay_name: String,
+    /// Empty if not played online.
+    pub netplay_code: String,
 }
 
 impl Metadata {
@@ -819,246 +1176,125 @@ impl Metadata {
         duration: -1,
         time: Time::NULL,
         has_notes: false,
+        played_on: String::new(),
+        console_nick: String::new(),
+        players: [None, None, None, None],
+        raw: UbjsonValue::Null,
     };
 }
 
-fn parse_object_header(bytes: &[u8], name: &[u8]) -> Option<usize> {
-    let data_i = bytes.windows(name.len()).position(|w| w == name)?;
-    Some(data_i+name.len()+1)
-}
-
-fn parse_string(bytes: &[u8], name: &[u8]) -> Option<(usize, String)> {
-    let data_i = bytes.windows(name.len()).position(|w| w == name)?;
-    let data_len = i32::from_be_bytes(bytes[data_i+name.len()+2..][..4].try_into().unwrap()) as usize;
-    let data = std::str::from_utf8(&bytes[data_i+name.len()+6..][..data_len]).unwrap().to_string();
-    Some((data_i+data_len+name.len()+6, data))
-}
-
-fn parse_i32(bytes: &[u8], name: &[u8]) -> Option<(usize, i32)> {
-    let count_i = bytes.windows(name.len()).position(|w| w == name)?;
-    let num = i32::from_be_bytes(bytes[count_i+name.len()+1..][..4].try_into().unwrap());
-    Some((count_i+name.len()+5, num))
-}
+/// Expects the bytes following the raw events. 
+/// May be truncated, only the fields read in full are returned.
+pub fn parse_metadata(bytes: &[u8]) -> Metadata {
+    let raw = UbjsonValue::Object(parse_ubjson_fields(bytes));
+    let mut metadata = Metadata::NULL;
 
-fn parse_array(bytes: &[u8], count: usize, vec: &mut Vec<i32>, name: &[u8]) -> Option<usize> {
-    vec.reserve_exact(count);
+    if let Some(fields) = raw.get("metadata") {
+        let str_field = |name| fields.get(name).and_then(|v| v.as_str());
 
-    let arr_i = bytes.windows(name.len()).position(|w| w == name)?;
-    let end = arr_i+name.len()+1+count*5;
-    let data = &bytes[(arr_i+name.len()+1)..end];
+        if let Some(start_at) = str_field("startAt") {
+            metadata.time = parse_timestamp(start_at.as_bytes()).unwrap_or(Time::NULL);
+        }
+        if let Some(last_frame) = fields.get("lastFrame").and_then(|v| v.as_i32()) {
+            metadata.duration = last_frame;
+        }
+        metadata.played_on = str_field("playedOn").unwrap_or("").to_string();
+        metadata.console_nick = str_field("consoleNick").unwrap_or("").to_string();
+
+        if let Some(players) = fields.get("players").and_then(|v| v.as_object()) {
+            for (port, player) in players.iter() {
+                let Some(port) = port.parse::<usize>().ok().filter(|&p| p < 4) else { continue };
+
+                let mut character_frames = Vec::new();
+                if let Some(characters) = player.get("characters").and_then(|v| v.as_object()) {
+                    for (character, frames) in characters.iter() {
+                        let character = character.parse::<u8>().ok().and_then(Character::from_u8_internal);
+                        if let (Some(character), Some(frames)) = (character, frames.as_i64()) {
+                            character_frames.push((character, frames as u32));
+                        }
+                    }
+                }
 
-    for c in data.chunks(5) {
-        vec.push(i32::from_be_bytes(c[1..].try_into().ok()?))
+                let name_field = |name| player.get("names")
+                    .and_then(|names| names.get(name))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string();
+
+                metadata.players[port] = Some(MetadataPlayer {
+                    character_frames,
+                    netplay_name: name_field("netplay"),
+                    netplay_code: name_field("code"),
+                });
+            }
+        }
     }
 
-    Some(end)
-}
-
-// expects the natural part of the metadata, the notes and diagrams need not be included.
-fn parse_metadata(bytes: &[u8]) -> Metadata {
-    let mut metadata = Metadata::NULL;
-    let mut end = 0;
-    
-    if let Some(i) = bytes.windows(7).position(|w| w == b"startAt") {
-        end = i + 30;
-        metadata.time = parse_timestamp(&bytes[i+10..i+30]).unwrap_or(Time::NULL);
+    if let Some(notes) = raw.get("notes") {
+        let data_len = notes.get("data").and_then(|v| v.as_str()).map(|s| s.len()).unwrap_or(0);
+        let image_count = notes.get("imageCount").and_then(|v| v.as_i64()).unwrap_or(0);
+        metadata.has_notes = data_len != 0 || image_count != 0;
     }
 
-    if let Some(i) = bytes.windows(9).position(|w| w == b"lastFrame") {
-        end = end.max(i + 14);
-        metadata.duration = i32::from_be_bytes(bytes[(i+10)..(i+14)].try_into().unwrap());
-    }
-    
-    // Only prune bytes after parsing native fields, as we don't know their order.
-    // But we do know notes come after.
-    let mut bytes = &bytes[end..];
-    if let Some(i) = parse_object_header(bytes, b"notes") {
-        bytes = &bytes[i..];
-        
-        'find_notes: {
-            if let Some(data_i) = bytes.windows(b"data".len()).position(|w| w == b"data") {
-                let data_len = i32::from_be_bytes(bytes[data_i+b"data".len()+2..][..4].try_into().unwrap());
-                if data_len != 0 {
-                    metadata.has_notes = true;
-                    break 'find_notes;
-                }
-            }
-    
-            if let Some((_, image_count)) = parse_i32(bytes, b"imageCount") {
-                if image_count != 0 {
-                    metadata.has_notes = true;
-                    break 'find_notes;
-                }
-            }
-        }
-    }
-    
+    metadata.raw = raw;
     metadata
 }
 
 /// expects metadata
 pub fn parse_notes(metadata: &[u8]) -> Notes {
-    let mut data = String::new();
-    let mut start_frames = Vec::new();
-    let mut frame_lengths = Vec::new();
-    let mut data_idx = Vec::new();
-    let mut image_data_offsets = Vec::new();
-    let mut image_start_frames = Vec::new();
-    let mut image_frame_lengths = Vec::new();
-    let mut image_compressed_data = Vec::new();
-
-    if let Some(i) = parse_object_header(metadata, b"notes") {
-        let mut bytes = &metadata[i..];
-
-        let (end, count) = parse_i32(bytes, b"count").unwrap();
-        let count = count as usize;
-        bytes = &bytes[end..];
-
-        let (end, parsed) = parse_string(bytes, b"data").unwrap();
-        data = parsed;
-        bytes = &bytes[end..];
-
-        // ---------- parse text notes ---------- //
-
-        // These fields are guaranteed, so we can safely unwrap them
-        let end = parse_array(bytes, count, &mut start_frames, b"startFrames").unwrap();
-        bytes = &bytes[end..];
-        let end = parse_array(bytes, count, &mut frame_lengths, b"frameLengths").unwrap();
-        bytes = &bytes[end..];
-        parse_array(bytes, count, &mut data_idx, b"dataStart").unwrap();
-
-        // ---------- parse images ---------- //
-
-        // if we have imageCount, then the rest of the fields will be guaranteed
-        if let Some((end, image_count)) = parse_i32(bytes, b"imageCount") {
-            let image_count = image_count as usize;
-            bytes = &bytes[end..];
-
-            let end = parse_array(bytes, image_count, &mut image_data_offsets, b"imageDataOffsets").unwrap();
-            bytes = &bytes[end..];
-            let end = parse_array(bytes, image_count, &mut image_start_frames, b"imageStartFrames").unwrap();
-            bytes = &bytes[end..];
-            let end = parse_array(bytes, image_count, &mut image_frame_lengths, b"imageFrameLengths").unwrap();
-            bytes = &bytes[end..];
-
-            // parse compressed image data, which is a raw binary array.
-            // Unlike the other fields, which start with a length, then type, this is a UBJSON
-            // specific array, where you specify the type and count of the array, 
-            // then fill it without specifying types for each element.
-            // This is exactly the same layout mode as the 'raw' field in the slp spec.
-            let name = b"imageCompressedData";
-            if let Some(arr_i) = bytes.windows(name.len()).position(|w| w == name) {
-                const BINARY_ARRAY_PREFACE: &'static [u8] = b"[$U#l";
-
-                let data_len_idx = arr_i + name.len() + BINARY_ARRAY_PREFACE.len();
-                let data_len = read_u32(bytes, data_len_idx) as usize;
-                let data_start = data_len_idx + 4;
-                image_compressed_data = bytes[data_start..][..data_len].to_vec();
-            }
-        }
+    match UbjsonValue::Object(parse_ubjson_fields(metadata)).get("notes") {
+        Some(notes) => notes_from_ubjson(notes),
+        None => Notes::NULL,
     }
+}
+
+pub(crate) fn notes_from_ubjson(notes: &UbjsonValue) -> Notes {
+    let i32_array = |name| -> Vec<i32> {
+        notes.get(name)
+            .and_then(|v| v.as_array())
+            .map(|a| a.iter().filter_map(|v| v.as_i32()).collect())
+            .unwrap_or_default()
+    };
 
     Notes {
-        data,
-        start_frames,
-        frame_lengths,
-        data_idx,
-
-        image_data_offsets,
-        image_start_frames,
-        image_frame_lengths,
-        image_compressed_data,
+        data: notes.get("data").and_then(|v| v.as_str()).unwrap_or("").to_string(),
+        start_frames: i32_array("startFrames"),
+        frame_lengths: i32_array("frameLengths"),
+        data_idx: i32_array("dataStart"),
+
+        // image fields are not written by older versions
+        image_data_offsets: i32_array("imageDataOffsets"),
+        image_start_frames: i32_array("imageStartFrames"),
+        image_frame_lengths: i32_array("imageFrameLengths"),
+        image_compressed_data: notes.get("imageCompressedData")
+            .and_then(|v| v.as_binary())
+            .unwrap_or(&[])
+            .to_vec(),
     }
 }
 
 /// writes in ubjson format
 pub fn write_notes(buffer: &mut Vec<u8>, notes: &Notes) {
-    // --------- preface -------- //
-
-    fn write_u8(buffer: &mut Vec<u8>, n: u8) {
-        buffer.push(b'U');
-        buffer.push(n);
-    }
-
-    fn write_i32(buffer: &mut Vec<u8>, n: i32) {
-        buffer.push(b'l');
-        buffer.extend_from_slice(&n.to_be_bytes());
-    }
-
-    fn write_field(buffer: &mut Vec<u8>, s: &str) {
-        write_u8(buffer, s.len() as u8);
-        buffer.extend_from_slice(s.as_bytes());
-    }
-
-    write_field(buffer, "notes");
-    buffer.push(b'{');
-
-    // --------- text notes -------- //
-
-    write_field(buffer, "count");
-    write_i32(buffer, notes.start_frames.len() as i32);
-
-    write_field(buffer, "data");
-    buffer.push(b'S');
-    write_i32(buffer, notes.data.len() as i32);
-    buffer.extend_from_slice(notes.data.as_bytes());
-
-    write_field(buffer, "startFrames");
-    buffer.push(b'[');
-    for f in notes.start_frames.iter().copied() {
-        write_i32(buffer, f);
-    }
-    buffer.push(b']');
-
-    write_field(buffer, "frameLengths");
-    buffer.push(b'[');
-    for f in notes.frame_lengths.iter().copied() {
-        write_i32(buffer, f);
-    }
-    buffer.push(b']');
-
-    write_field(buffer, "dataStart");
-    buffer.push(b'[');
-    for f in notes.data_idx.iter().copied() {
-        write_i32(buffer, f);
-    }
-    buffer.push(b']');
-
-    // --------- images -------- //
-
-    write_field(buffer, "imageCount");
-    write_i32(buffer, notes.image_data_offsets.len() as i32);
-
-    write_field(buffer, "imageDataOffsets");
-    buffer.push(b'[');
-    for f in notes.image_data_offsets.iter().copied() {
-        write_i32(buffer, f);
-    }
-    buffer.push(b']');
-
-    write_field(buffer, "imageStartFrames");
-    buffer.push(b'[');
-    for f in notes.image_start_frames.iter().copied() {
-        write_i32(buffer, f);
-    }
-    buffer.push(b']');
-
-    write_field(buffer, "imageFrameLengths");
-    buffer.push(b'[');
-    for f in notes.image_frame_lengths.iter().copied() {
-        write_i32(buffer, f);
-    }
-    buffer.push(b']');
-
-    write_field(buffer, "imageCompressedData");
-    buffer.extend_from_slice(b"[$U#l");
-    buffer.extend_from_slice(&(notes.image_compressed_data.len() as u32).to_be_bytes());
-    buffer.extend_from_slice(notes.image_compressed_data.as_slice());
-    buffer.push(b']');
-
-    // --------- finish up -------- //
-
-    buffer.push(b'}');
+    let i32_array = |arr: &[i32]| UbjsonValue::Array(arr.iter().map(|&n| UbjsonValue::Int32(n)).collect());
+
+    let notes = UbjsonValue::Object(vec![
+        // text notes
+        ("count".into(),               UbjsonValue::Int32(notes.start_frames.len() as i32)),
+        ("data".into(),                UbjsonValue::String(notes.data.clone())),
+        ("startFrames".into(),         i32_array(&notes.start_frames)),
+        ("frameLengths".into(),        i32_array(&notes.frame_lengths)),
+        ("dataStart".into(),           i32_array(&notes.data_idx)),
+
+        // images
+        ("imageCount".into(),          UbjsonValue::Int32(notes.image_data_offsets.len() as i32)),
+        ("imageDataOffsets".into(),    i32_array(&notes.image_data_offsets)),
+        ("imageStartFrames".into(),    i32_array(&notes.image_start_frames)),
+        ("imageFrameLengths".into(),   i32_array(&notes.image_frame_lengths)),
+        ("imageCompressedData".into(), UbjsonValue::Binary(notes.image_compressed_data.clone())),
+    ]);
+
+    write_ubjson_key(buffer, "notes");
+    write_ubjson(buffer, &notes);
 }
 
 fn parse_timestamp(timestamp: &[u8]) -> SlpResult<Time> {
@@ -1069,6 +1305,9 @@ fn parse_timestamp(timestamp: &[u8]) -> SlpResult<Time> {
     const fn conv(n: u8) -> u8 { n - b'0' }
 
     if timestamp.len() < 19 { return Err(SlpError::InvalidFile(InvalidLocation::Metadata)) }
+    for i in [0, 1, 2, 3, 5, 6, 8, 9, 11, 12, 14, 15, 17, 18] {
+        if !timestamp[i].is_ascii_digit() { return Err(SlpError::InvalidFile(InvalidLocation::Metadata)) }
+    }
 
     let d1 = conv(timestamp[0]) as u16;
     let d2 = conv(timestamp[1]) as u16;
@@ -1146,3 +1385,101 @@ fn notes_round_trip() {
 
     assert_eq!(notes, round_trip_notes);
 }
+
+#[test]
+fn detailed_error_context() {
+    let mut slp = test_replay();
+    let game_end_offset = slp.len() - 3;
+    let sizes = event_sizes(&slp, parse_raw_header(&slp).unwrap().event_sizes_offset).unwrap().event_sizes;
+    let post_frame_offset = game_end_offset
+        - (sizes[FRAME_BOOKEND as usize] as usize + 1)
+        - (sizes[ITEM_UPDATE as usize] as usize + 1)
+        - (sizes[POST_FRAME_UPDATE as usize] as usize + 1);
+    assert_eq!(slp[post_frame_offset], POST_FRAME_UPDATE);
+    slp[post_frame_offset + 0x7] = 0xFF; // invalid character
+
+    let err = parse_file_detailed(&slp).unwrap_err();
+    assert_eq!(err.kind, SlpError::InvalidFile(InvalidLocation::PostFrameUpdate));
+    assert_eq!(err.offset, Some(post_frame_offset));
+    assert_eq!(err.command, Some(POST_FRAME_UPDATE));
+    assert_eq!(err.frame, Some(-121));
+    assert_eq!(err.version, Some((3, 7, 0)));
+    assert_eq!(parse_file(&slp).unwrap_err(), err.kind);
+
+    let err = read_game_detailed(std::path::Path::new("does_not_exist.slp")).unwrap_err();
+    assert_eq!(err.kind, SlpError::FileDoesNotExist);
+    assert_eq!(err.io_error.map(|e| e.kind()), Some(std::io::ErrorKind::NotFound));
+}
+
+#[test]
+fn game_start_settings() {
+    fn is_eq<T: Eq>(_: &T) {}
+
+    let mut slp = test_replay();
+    let game_start = event_sizes(&slp, parse_raw_header(&slp).unwrap().event_sizes_offset).unwrap().game_start_offset;
+    assert_eq!(slp[game_start], GAME_START);
+    slp[game_start + 5 + 0x30..][..4].copy_from_slice(&1.5f32.to_be_bytes());
+    slp[game_start + 5 + 0x78..][..4].copy_from_slice(&0.5f32.to_be_bytes());
+
+    let game = parse_file(&slp).unwrap();
+    is_eq(&game.info);
+    assert_eq!(game.info.damage_ratio, F32Bits::from(1.5));
+    assert_eq!(game.info.player_settings[0].offense_ratio.get(), 0.5);
+    assert_eq!(format!("{:?}", game.info.damage_ratio), "1.5");
+
+    let mut frame = game.frames[0].as_ref().unwrap()[0].clone();
+    assert_eq!(game.info.character_colour(&frame), game.info.try_character_colour(&frame).unwrap());
+    frame.port_idx = 3;
+    assert_eq!(game.info.try_character_colour(&frame), None);
+}
+
+#[test]
+fn unknown_game_end_method() {
+    let mut slp = test_replay();
+    let game_end_offset = slp.len() - 3;
+    slp[game_end_offset + 1] = 9;
+
+    let game = parse_file(&slp).unwrap();
+    let end = game.info.end.unwrap();
+    assert_eq!(end.method, GameEndMethod::Unknown(9));
+    assert_eq!(end.method.to_u8(), 9);
+}
+
+#[test]
+fn slpz_info_has_game_end() {
+    let game = parse_file(&test_replay()).unwrap();
+    let slp = write_slp(&game).unwrap();
+    let mut compressor = slpz::Compressor::new(3).unwrap();
+    let slpz = slpz::compress(&mut compressor, &slp).unwrap();
+
+    let slp_info = parse_file_info(&mut std::io::Cursor::new(&slp)).unwrap();
+    let info = parse_file_info_slpz(&mut std::io::Cursor::new(&slpz)).unwrap();
+    assert!(info.end.is_some());
+    assert_eq!(info.end, game.info.end);
+    assert_eq!(info, slp_info);
+
+    // the header is still readable if the events are corrupt
+    let mut corrupt = slpz.clone();
+    let compressed_events_offset = read_u32(&slpz, 16) as usize;
+    corrupt.truncate(compressed_events_offset + 4);
+    let info = parse_file_info_slpz(&mut std::io::Cursor::new(&corrupt)).unwrap();
+    assert_eq!(info.end, None);
+    assert_eq!(info, GameInfo { end: None, ..slp_info });
+}
+
+#[test]
+fn corrupt_bytes_do_not_panic() {
+    let slp = test_replay();
+    for i in 0..slp.len() {
+        for byte in [0x00, 0x7F, 0x80, 0xFF] {
+            let mut corrupt = slp.clone();
+            corrupt[i] = byte;
+            let _ = parse_file_ext(&corrupt);
+            let _ = parse_file_info(&mut std::io::Cursor::new(&corrupt));
+            let _ = parse_notes(&corrupt);
+
+            corrupt.truncate(i);
+            let _ = parse_file(&corrupt);
+        }
+    }
+}
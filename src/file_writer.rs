@@ -0,0 +1,703 @@
+use crate::*;
+
+// Payload sizes written by `write_slp`, matching v3.17.0.
+const GAME_START_SIZE:        u16 = 0x2F8;
+const PRE_FRAME_UPDATE_SIZE:  u16 = 0x42;
+const POST_FRAME_UPDATE_SIZE: u16 = 0x54;
+const GAME_END_SIZE:          u16 = 0x6;
+const FRAME_START_SIZE:       u16 = 0xC;
+const ITEM_UPDATE_SIZE:       u16 = 0x2C;
+const FRAME_BOOKEND_SIZE:     u16 = 0x8;
+const FOD_INFO_SIZE:          u16 = 0x9;
+const DREAMLAND_INFO_SIZE:    u16 = 0x5;
+const STADIUM_INFO_SIZE:      u16 = 0x8;
+const MESSAGE_SPLITTER_SIZE:  u16 = 0x204;
+
+const MESSAGE_SPLITTER_DATA_LEN: usize = 512;
+
+fn write_array<const SIZE: usize>(bytes: &mut [u8], offset: usize, array: [u8; SIZE]) {
+    if offset + SIZE > bytes.len() { return; }
+    bytes[offset..][..SIZE].copy_from_slice(&array);
+}
+fn write_f32(bytes: &mut [u8], offset: usize, n: f32) { write_array(bytes, offset, n.to_be_bytes()) }
+fn write_u32(bytes: &mut [u8], offset: usize, n: u32) { write_array(bytes, offset, n.to_be_bytes()) }
+fn write_u16(bytes: &mut [u8], offset: usize, n: u16) { write_array(bytes, offset, n.to_be_bytes()) }
+fn write_u8 (bytes: &mut [u8], offset: usize, n: u8 ) { write_array(bytes, offset, n.to_be_bytes()) }
+fn write_i32(bytes: &mut [u8], offset: usize, n: i32) { write_array(bytes, offset, n.to_be_bytes()) }
+fn write_i8 (bytes: &mut [u8], offset: usize, n: i8 ) { write_array(bytes, offset, n.to_be_bytes()) }
+
+fn direction_f32(direction: Direction) -> f32 {
+    match direction {
+        Direction::Right => 1.0,
+        Direction::Left => -1.0,
+    }
+}
+
+/// Appends a zeroed event and returns it for filling in.
+fn push_event(buffer: &mut Vec<u8>, cmd: u8, size: u16) -> &mut [u8] {
+    let start = buffer.len();
+    buffer.push(cmd);
+    buffer.resize(start + size as usize + 1, 0);
+    &mut buffer[start..]
+}
+
+/// Serializes a game into an slp file.
+///
+/// Events are written at v3.17.0 sizes, without rollbacks.
+/// The Game Start event is written over `game.game_start_raw`, so fields this crate doesn't parse are kept.
+/// Fields missing from `Frame` are taken from `frames_ext` if present, otherwise they are zeroed.
+/// Use `write_slp_passthrough` to keep every event exactly as it was.
+///
+/// Returns `SlpError::EventTooLarge` if the Game Start event or gecko list don't fit in the Event Payloads table.
+pub fn write_slp(game: &Game) -> SlpResult<Vec<u8>> {
+    let frame_count = game.frames.iter().chain(game.follower_frames.iter()).flatten().next().map_or(0, |f| f.len());
+    let mut events = Vec::with_capacity(frame_count * 0x200);
+
+    // event payloads --------------------------------------------------------
+
+    let game_start_size = if game.game_start_raw.is_empty() {
+        GAME_START_SIZE
+    } else {
+        u16::try_from(game.game_start_raw.len() - 1).map_err(|_| SlpError::EventTooLarge)?
+    };
+
+    let mut sizes = vec![
+        (GAME_START,        game_start_size),
+        (PRE_FRAME_UPDATE,  PRE_FRAME_UPDATE_SIZE),
+        (POST_FRAME_UPDATE, POST_FRAME_UPDATE_SIZE),
+        (GAME_END,          GAME_END_SIZE),
+        (FRAME_START,       FRAME_START_SIZE),
+        (ITEM_UPDATE,       ITEM_UPDATE_SIZE),
+        (FRAME_BOOKEND,     FRAME_BOOKEND_SIZE),
+    ];
+    if let Some(ref gecko_codes) = game.gecko_codes {
+        let gecko_list_size = u16::try_from(gecko_codes.raw.len()).map_err(|_| SlpError::EventTooLarge)?;
+        sizes.push((GECKO_LIST, gecko_list_size));
+        sizes.push((MESSAGE_SPLITTER, MESSAGE_SPLITTER_SIZE));
+    }
+    match game.stage_info {
+        Some(StageInfo::Fountain(_))  => sizes.push((FOD_INFO, FOD_INFO_SIZE)),
+        Some(StageInfo::DreamLand(_)) => sizes.push((DREAMLAND_INFO, DREAMLAND_INFO_SIZE)),
+        Some(StageInfo::Stadium(_))   => sizes.push((STADIUM_INFO, STADIUM_INFO_SIZE)),
+        None => (),
+    }
+
+    events.push(EVENT_PAYLOADS);
+    events.push((sizes.len() * 3 + 1) as u8);
+    for (cmd, size) in sizes.iter() {
+        events.push(*cmd);
+        events.extend_from_slice(&size.to_be_bytes());
+    }
+
+    // game start --------------------------------------------------------
+
+    let game_start = push_event(&mut events, GAME_START, game_start_size);
+    if !game.game_start_raw.is_empty() { game_start.copy_from_slice(&game.game_start_raw); }
+    write_game_start(game_start, &game.info);
+
+    if let Some(ref gecko_codes) = game.gecko_codes {
+        let mut chunks = gecko_codes.raw.chunks(MESSAGE_SPLITTER_DATA_LEN).peekable();
+        while let Some(chunk) = chunks.next() {
+            let message = push_event(&mut events, MESSAGE_SPLITTER, MESSAGE_SPLITTER_SIZE);
+            message[0x1..][..chunk.len()].copy_from_slice(chunk);
+            write_u16(message, 0x201, u16::try_from(chunk.len()).map_err(|_| SlpError::EventTooLarge)?);
+            write_u8(message, 0x203, GECKO_LIST);
+            write_u8(message, 0x204, chunks.peek().is_none() as u8);
+        }
+    }
+
+    // frames --------------------------------------------------------
+
+    let stage_events = stage_events(game.stage_info.as_ref());
+    let mut stage_event_i = 0;
+
+    for frame_idx in 0..frame_count {
+        let frame = frame_idx as i32 - 123;
+        let frame_start = game.frame_starts.get(frame_idx).copied().unwrap_or(FrameStart::NULL);
+
+        let event = push_event(&mut events, FRAME_START, FRAME_START_SIZE);
+        write_i32(event, 0x1, frame);
+        write_u32(event, 0x5, frame_start.random_seed);
+        write_u32(event, 0x9, frame_start.scene_frame_counter);
+
+        for port_idx in 0..4 {
+            for (is_follower, frames, frames_ext) in [
+                (false, &game.frames[port_idx], &game.frames_ext[port_idx]),
+                (true, &game.follower_frames[port_idx], &game.follower_frames_ext[port_idx]),
+            ] {
+                let Some(f) = frames.as_ref().and_then(|f| f.get(frame_idx)) else { continue };
+                let ext = frames_ext.as_ref().and_then(|f| f.get(frame_idx));
+                let event = push_event(&mut events, PRE_FRAME_UPDATE, PRE_FRAME_UPDATE_SIZE);
+                write_pre_frame_update(event, frame, port_idx as u8, is_follower, f, ext, frame_start.random_seed);
+            }
+        }
+
+        let item_start = game.item_idx.get(frame_idx).copied().unwrap_or(0) as usize;
+        let item_end = game.item_idx.get(frame_idx+1).copied().unwrap_or(0) as usize;
+        for item in game.items.get(item_start..item_end).unwrap_or(&[]) {
+            let event = push_event(&mut events, ITEM_UPDATE, ITEM_UPDATE_SIZE);
+            write_item_update(event, item);
+        }
+
+        for port_idx in 0..4 {
+            for (is_follower, frames, frames_ext) in [
+                (false, &game.frames[port_idx], &game.frames_ext[port_idx]),
+                (true, &game.follower_frames[port_idx], &game.follower_frames_ext[port_idx]),
+            ] {
+                let Some(f) = frames.as_ref().and_then(|f| f.get(frame_idx)) else { continue };
+                let ext = frames_ext.as_ref().and_then(|f| f.get(frame_idx));
+                let event = push_event(&mut events, POST_FRAME_UPDATE, POST_FRAME_UPDATE_SIZE);
+                write_post_frame_update(event, frame, port_idx as u8, is_follower, f, ext);
+            }
+        }
+
+        while let Some((event_frame_idx, stage_event)) = stage_events.get(stage_event_i) {
+            if *event_frame_idx as usize != frame_idx { break }
+            events.extend_from_slice(stage_event);
+            stage_event_i += 1;
+        }
+
+        let event = push_event(&mut events, FRAME_BOOKEND, FRAME_BOOKEND_SIZE);
+        write_i32(event, 0x1, frame);
+        write_i32(event, 0x5, frame);
+    }
+
+    if let Some(ref end) = game.info.end {
+        let event = push_event(&mut events, GAME_END, GAME_END_SIZE);
+        write_game_end(event, end);
+    }
+
+    Ok(finish_slp(&events, &game.metadata, &game.notes, frame_count as i32 - 124))
+}
+
+/// Serializes a game into an slp file, copying events from the file it was parsed from.
+///
+/// Every event is copied byte for byte, including rollbacks and events this crate doesn't parse.
+/// Only the Game Start and Game End events are written over with `game.info`,
+/// and the metadata is rewritten from `game.metadata` and `game.notes`.
+pub fn write_slp_passthrough(game: &Game, original_slp: &[u8]) -> SlpResult<Vec<u8>> {
+    let RawHeaderRet { event_sizes_offset, metadata_offset } = parse_raw_header(original_slp)?;
+    let EventSizesRet { game_start_offset, event_sizes } = event_sizes(original_slp, event_sizes_offset)?;
+    let game_start_size = event_sizes[GAME_START as usize] as usize + 1;
+    if original_slp.len() < game_start_offset + game_start_size {
+        return Err(SlpError::InvalidFile(InvalidLocation::GameStart));
+    }
+
+    let mut events = original_slp[event_sizes_offset..game_start_offset+game_start_size].to_vec();
+    let game_start = &mut events[game_start_offset-event_sizes_offset..];
+    write_game_start(game_start, &game.info);
+
+    let mut event_cursor = game_start_offset + game_start_size;
+    let end = if metadata_offset == 0 { original_slp.len() } else { metadata_offset.min(original_slp.len()) };
+    while event_cursor < end {
+        let event_cmd = original_slp[event_cursor];
+        let event_size = event_sizes[event_cmd as usize] as usize + 1;
+        if original_slp.len() < event_cursor + event_size { break; }
+
+        let event_start = events.len();
+        events.extend_from_slice(&original_slp[event_cursor..][..event_size]);
+        event_cursor += event_size;
+
+        if event_cmd == GAME_END {
+            if let Some(ref game_end) = game.info.end {
+                write_game_end(&mut events[event_start..], game_end);
+            }
+            break;
+        }
+    }
+
+    Ok(finish_slp(&events, &game.metadata, &game.notes, game.frame_count as i32 - 124))
+}
+
+/// Wraps the events in the raw array and appends the metadata.
+fn finish_slp(events: &[u8], metadata: &Metadata, notes: &Notes, last_frame: i32) -> Vec<u8> {
+    let mut slp = Vec::with_capacity(events.len() + 0x1000);
+    slp.extend_from_slice(b"{U\x03raw[$U#l");
+    slp.extend_from_slice(&(events.len() as u32).to_be_bytes());
+    slp.extend_from_slice(events);
+    write_metadata(&mut slp, metadata, notes, last_frame);
+    slp.push(b'}');
+    slp
+}
+
+/// Writes every field from `metadata.raw` except the notes, followed by `notes`.
+/// lastFrame is overwritten.
+fn write_metadata(buffer: &mut Vec<u8>, metadata: &Metadata, notes: &Notes, last_frame: i32) {
+    let last_frame = UbjsonValue::Int32(last_frame);
+
+    let mut wrote_metadata = false;
+    for (key, value) in metadata.raw.as_object().unwrap_or(&[]).iter() {
+        match key.as_str() {
+            "notes" => continue,
+            "metadata" => {
+                let mut fields = value.as_object().unwrap_or(&[]).to_vec();
+                match fields.iter_mut().find(|(k, _)| k == "lastFrame") {
+                    Some((_, v)) => *v = last_frame.clone(),
+                    None => fields.push(("lastFrame".into(), last_frame.clone())),
+                }
+                write_ubjson_key(buffer, key);
+                write_ubjson(buffer, &UbjsonValue::Object(fields));
+                wrote_metadata = true;
+            }
+            _ => {
+                write_ubjson_key(buffer, key);
+                write_ubjson(buffer, value);
+            }
+        }
+    }
+
+    if !wrote_metadata {
+        let mut fields = Vec::new();
+        if metadata.time != Time::NULL {
+            let t = metadata.time.fields();
+            let start_at = format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+                t.year, t.month, t.day, t.hour, t.minute, t.second
+            );
+            fields.push(("startAt".into(), UbjsonValue::String(start_at)));
+        }
+        fields.push(("lastFrame".into(), last_frame));
+
+        write_ubjson_key(buffer, "metadata");
+        write_ubjson(buffer, &UbjsonValue::Object(fields));
+    }
+
+    if *notes != Notes::NULL {
+        write_notes(buffer, notes);
+    }
+}
+
+// CLIPPING ------------------------------------------------------------------------
+
+/// Cuts a replay down to the frames in the range, such that the clip starts at frame -123.
+/// Ranges starting before frame -123 are not shifted.
+/// Accepts and returns either an slp or an slpz file.
+///
+/// Events outside the range are removed, including rollbacks.
+/// Every other event is kept as is, apart from their frame numbers.
+/// Notes starting within the range are kept and shifted to match.
+pub fn clip_replay(slp: &[u8], frames: std::ops::Range<i32>) -> SlpResult<Vec<u8>> {
+    if !slp.starts_with(b"{U\x03raw") {
+        let mut decompressor = slpz::Decompressor::new().ok_or(SlpError::ZstdInitError)?;
+        let slp = slpz::decompress(&mut decompressor, slp)
+            .map_err(|_| SlpError::InvalidFile(InvalidLocation::SlpzDecompression))?;
+        let clip = clip_replay(&slp, frames)?;
+
+        let mut compressor = slpz::Compressor::new(3).ok_or(SlpError::ZstdInitError)?;
+        return slpz::compress(&mut compressor, &clip).map_err(|_| SlpError::SlpzCompressionError);
+    }
+
+    let RawHeaderRet { event_sizes_offset, metadata_offset } = parse_raw_header(slp)?;
+    let EventSizesRet { game_start_offset, event_sizes } = event_sizes(slp, event_sizes_offset)?;
+    let game_start_size = event_sizes[GAME_START as usize] as usize + 1;
+    if slp.len() < game_start_offset + game_start_size {
+        return Err(SlpError::InvalidFile(InvalidLocation::GameStart));
+    }
+
+    // frame numbers in the clip are shifted down by this amount
+    let shift = frames.start.max(-123).saturating_add(123);
+
+    let mut events = slp[event_sizes_offset..game_start_offset+game_start_size].to_vec();
+    let mut last_frame = None;
+
+    // Events without a frame number (e.g. the gecko list) stay with the frame they were sent in.
+    // Events sent before the first frame are always kept.
+    let mut in_clip = true;
+
+    let mut event_cursor = game_start_offset + game_start_size;
+    let end = if metadata_offset == 0 { slp.len() } else { metadata_offset.min(slp.len()) };
+    while event_cursor < end {
+        let event_cmd = slp[event_cursor];
+        let event_size = event_sizes[event_cmd as usize] as usize + 1;
+        if slp.len() < event_cursor + event_size { break; }
+        let event_bytes = &slp[event_cursor..][..event_size];
+        event_cursor += event_size;
+
+        match event_cmd {
+            FRAME_START | PRE_FRAME_UPDATE | POST_FRAME_UPDATE | ITEM_UPDATE 
+                | FRAME_BOOKEND | FOD_INFO | DREAMLAND_INFO | STADIUM_INFO => 
+            {
+                let frame = read_i32(event_bytes, 0x1);
+                in_clip = frames.contains(&frame);
+                if !in_clip { continue; }
+
+                let event_start = events.len();
+                events.extend_from_slice(event_bytes);
+                let event = &mut events[event_start..];
+                write_i32(event, 0x1, frame - shift);
+
+                if event_cmd == FRAME_BOOKEND {
+                    let finalized = read_i32(event_bytes, 0x5);
+                    write_i32(event, 0x5, finalized.saturating_sub(shift).max(-123));
+                    last_frame = Some(last_frame.unwrap_or(i32::MIN).max(frame - shift));
+                }
+            }
+            GAME_END => {
+                events.extend_from_slice(event_bytes);
+                break;
+            }
+            _ => if in_clip { events.extend_from_slice(event_bytes) },
+        }
+    }
+
+    let last_frame = last_frame.ok_or(SlpError::InvalidClipRange)?;
+
+    let metadata = match slp.get(metadata_offset..) {
+        Some(metadata) if metadata_offset != 0 => parse_metadata(metadata),
+        _ => Metadata::NULL,
+    };
+    let notes = match metadata.raw.get("notes") {
+        Some(notes) => clip_notes(&notes_from_ubjson(notes), shift..(shift + last_frame).saturating_add(124)),
+        None => Notes::NULL,
+    };
+
+    Ok(finish_slp(&events, &metadata, &notes, last_frame))
+}
+
+/// Keeps the notes starting within the frame idx range, shifted to start at zero.
+fn clip_notes(notes: &Notes, frame_idx: std::ops::Range<i32>) -> Notes {
+    let mut clipped = Notes::NULL;
+
+    // A note's data runs until the start of the next note's data.
+    fn data_end(starts: &[i32], i: usize, data_len: usize) -> usize {
+        starts.get(i+1).map(|&s| s as usize).unwrap_or(data_len)
+    }
+
+    for i in 0..notes.start_frames.len() {
+        let start_frame = notes.start_frames[i];
+        if !frame_idx.contains(&start_frame) { continue; }
+
+        let data_start = notes.data_idx.get(i).copied().unwrap_or(0) as usize;
+        let data_end = data_end(&notes.data_idx, i, notes.data.len());
+        let Some(text) = notes.data.get(data_start..data_end) else { continue };
+
+        clipped.start_frames.push(start_frame - frame_idx.start);
+        clipped.frame_lengths.push(notes.frame_lengths.get(i).copied().unwrap_or(0));
+        clipped.data_idx.push(clipped.data.len() as i32);
+        clipped.data.push_str(text);
+    }
+
+    for i in 0..notes.image_start_frames.len() {
+        let start_frame = notes.image_start_frames[i];
+        if !frame_idx.contains(&start_frame) { continue; }
+
+        let data_start = notes.image_data_offsets.get(i).copied().unwrap_or(0) as usize;
+        let data_end = data_end(&notes.image_data_offsets, i, notes.image_compressed_data.len());
+        let Some(image) = notes.image_compressed_data.get(data_start..data_end) else { continue };
+
+        clipped.image_start_frames.push(start_frame - frame_idx.start);
+        clipped.image_frame_lengths.push(notes.image_frame_lengths.get(i).copied().unwrap_or(0));
+        clipped.image_data_offsets.push(clipped.image_compressed_data.len() as i32);
+        clipped.image_compressed_data.extend_from_slice(image);
+    }
+
+    clipped
+}
+
+// EVENTS ------------------------------------------------------------------------
+
+/// Writes the fields in `GameInfo` over an existing Game Start event.
+pub fn write_game_start(game_start: &mut [u8], info: &GameInfo) {
+    write_u8(game_start, 0x0, GAME_START);
+    write_array(game_start, 0x1, [info.version_major, info.version_minor, info.version_patch]);
+
+    write_u8  (game_start, 0xD,  info.is_teams as u8);
+    write_i8  (game_start, 0x10, info.item_spawn_frequency);
+    write_u16 (game_start, 0x13, info.stage.to_u16_external());
+    write_u32 (game_start, 0x15, info.timer);
+    write_array(game_start, 0x28, info.item_spawn_bitfield);
+    write_f32 (game_start, 0x35, info.damage_ratio.get());
+
+    for i in 0..4 {
+        let settings = &info.player_settings[i];
+        write_u8(game_start, 0x66 + 0x24*i, settings.player_type as u8);
+
+        let Some(character_colour) = info.starting_character_colours[i] else { continue };
+        let character = character_colour.character().to_u8_external().unwrap_or(0);
+
+        write_u8 (game_start, 0x65 + 0x24*i, character);
+        write_u8 (game_start, 0x67 + 0x24*i, settings.stocks);
+        write_u8 (game_start, 0x68 + 0x24*i, character_colour.costume_idx());
+        write_u8 (game_start, 0x6D + 0x24*i, settings.handicap);
+        if info.is_teams {
+            // undo the green team remapping in parse_game_start
+            let team = if info.teams[i] == 3 { 2 } else { info.teams[i] };
+            write_u8(game_start, 0x6E + 0x24*i, team);
+        }
+        let bitfield = game_start.get(0x71 + 0x24*i).copied().unwrap_or(0);
+        write_u8 (game_start, 0x71 + 0x24*i, (bitfield & !0x01) | settings.stamina_mode as u8);
+        write_u8 (game_start, 0x74 + 0x24*i, settings.cpu_level);
+        write_f32(game_start, 0x7D + 0x24*i, settings.offense_ratio.get());
+        write_f32(game_start, 0x81 + 0x24*i, settings.defense_ratio.get());
+        write_f32(game_start, 0x85 + 0x24*i, settings.model_scale.get());
+
+        write_u32(game_start, 0x141 + 0x8*i, settings.ucf_dashback as u32);
+        write_u32(game_start, 0x145 + 0x8*i, settings.ucf_shield_drop as u32);
+        write_array(game_start, 0x1A5 + 0x1F*i, info.names[i]);
+        write_array(game_start, 0x221 + 0xA*i, info.connect_codes[i]);
+    }
+
+    write_u32(game_start, 0x13D, info.random_seed);
+    write_u8 (game_start, 0x1A1, info.is_pal as u8);
+    write_u8 (game_start, 0x1A2, info.is_frozen_stadium as u8);
+    write_u8 (game_start, 0x1A3, info.minor_scene);
+    write_u8 (game_start, 0x1A4, info.major_scene);
+    if let Some(language) = info.language { write_u8(game_start, 0x2BD, language as u8); }
+    write_array(game_start, 0x2BE, info.match_id);
+    write_u32(game_start, 0x2F1, info.game_number);
+    write_u32(game_start, 0x2F5, info.tiebreaker_number);
+}
+
+pub fn write_game_end(game_end: &mut [u8], end: &GameEnd) {
+    write_u8(game_end, 0x0, GAME_END);
+    write_u8(game_end, 0x1, end.method.to_u8());
+    write_i8(game_end, 0x2, end.lras_initiator.map(|p| p as i8).unwrap_or(-1));
+    for (i, placement) in end.placements.iter().enumerate() {
+        write_i8(game_end, 0x3 + i, placement.map(|p| p as i8).unwrap_or(-1));
+    }
+}
+
+// Port and follower are passed separately, as frames before a character spawns are null.
+fn write_pre_frame_update(
+    pre_frame_update: &mut [u8],
+    frame: i32,
+    port_idx: u8,
+    is_follower: bool,
+    f: &Frame,
+    ext: Option<&FrameExt>,
+    random_seed: u32,
+) {
+    write_u8 (pre_frame_update, 0x0,  PRE_FRAME_UPDATE);
+    write_i32(pre_frame_update, 0x1,  frame);
+    write_u8 (pre_frame_update, 0x5,  port_idx);
+    write_u8 (pre_frame_update, 0x6,  is_follower as u8);
+    write_f32(pre_frame_update, 0x19, f.left_stick_coords.x);
+    write_f32(pre_frame_update, 0x1D, f.left_stick_coords.y);
+    write_f32(pre_frame_update, 0x21, f.right_stick_coords.x);
+    write_f32(pre_frame_update, 0x25, f.right_stick_coords.y);
+    write_f32(pre_frame_update, 0x29, f.analog_trigger_value);
+    write_u16(pre_frame_update, 0x31, f.buttons_mask);
+    write_f32(pre_frame_update, 0x33, f.left_trigger_value_raw);
+    write_f32(pre_frame_update, 0x37, f.right_trigger_value_raw);
+    write_i8 (pre_frame_update, 0x3B, f.left_stick_coords_raw.x);
+    write_i8 (pre_frame_update, 0x40, f.left_stick_coords_raw.y);
+    write_i8 (pre_frame_update, 0x41, f.right_stick_coords_raw.x);
+    write_i8 (pre_frame_update, 0x42, f.right_stick_coords_raw.y);
+
+    match ext {
+        Some(ext) => {
+            write_u32(pre_frame_update, 0x7,  ext.random_seed);
+            write_u16(pre_frame_update, 0xB,  ext.pre_state_num);
+            write_f32(pre_frame_update, 0xD,  ext.pre_position.x);
+            write_f32(pre_frame_update, 0x11, ext.pre_position.y);
+            write_f32(pre_frame_update, 0x15, direction_f32(ext.pre_direction));
+            write_u32(pre_frame_update, 0x2D, ext.processed_buttons);
+            write_f32(pre_frame_update, 0x3C, ext.pre_percent);
+        }
+        None => {
+            write_u32(pre_frame_update, 0x7,  random_seed);
+            write_u16(pre_frame_update, 0xB,  f.state_num);
+            write_f32(pre_frame_update, 0xD,  f.position.x);
+            write_f32(pre_frame_update, 0x11, f.position.y);
+            write_f32(pre_frame_update, 0x15, direction_f32(f.direction));
+            write_f32(pre_frame_update, 0x3C, f.percent);
+        }
+    }
+}
+
+fn write_post_frame_update(
+    post_frame_update: &mut [u8],
+    frame: i32,
+    port_idx: u8,
+    is_follower: bool,
+    f: &Frame,
+    ext: Option<&FrameExt>,
+) {
+    write_u8   (post_frame_update, 0x0,  POST_FRAME_UPDATE);
+    write_i32  (post_frame_update, 0x1,  frame);
+    write_u8   (post_frame_update, 0x5,  port_idx);
+    write_u8   (post_frame_update, 0x6,  is_follower as u8);
+    write_u8   (post_frame_update, 0x7,  f.character.to_u8_internal());
+    write_u16  (post_frame_update, 0x8,  f.state_num);
+    write_f32  (post_frame_update, 0xA,  f.position.x);
+    write_f32  (post_frame_update, 0xE,  f.position.y);
+    write_f32  (post_frame_update, 0x12, direction_f32(f.direction));
+    write_f32  (post_frame_update, 0x16, f.percent);
+    write_f32  (post_frame_update, 0x1A, f.shield_size);
+    write_u8   (post_frame_update, 0x1E, f.last_hitting_attack_id as u8);
+    write_u8   (post_frame_update, 0x20, f.last_hit_by);
+    write_u8   (post_frame_update, 0x21, f.stock_count);
+    write_f32  (post_frame_update, 0x22, f.anim_frame);
+    write_array(post_frame_update, 0x26, f.state_flags);
+    write_f32  (post_frame_update, 0x2B, f.hitstun_misc);
+    write_u8   (post_frame_update, 0x2F, f.is_airborne as u8);
+    write_u16  (post_frame_update, 0x30, f.last_ground_idx);
+    write_u8   (post_frame_update, 0x32, f.jumps_remaining);
+    write_u8   (post_frame_update, 0x34, f.vuln_state as u8);
+    write_f32  (post_frame_update, 0x35, f.velocity.x);
+    write_f32  (post_frame_update, 0x39, f.velocity.y);
+    write_f32  (post_frame_update, 0x3D, f.hit_velocity.x);
+    write_f32  (post_frame_update, 0x41, f.hit_velocity.y);
+    write_f32  (post_frame_update, 0x45, f.ground_x_velocity);
+    write_f32  (post_frame_update, 0x49, f.hitlag_frames);
+    write_u16  (post_frame_update, 0x51, f.last_hit_by_instance_id);
+    write_u16  (post_frame_update, 0x53, f.instance_id);
+
+    if let Some(ext) = ext {
+        write_u8(post_frame_update, 0x1F, ext.combo_count);
+        write_u8(post_frame_update, 0x33, ext.l_cancel_status as u8);
+        write_u32(post_frame_update, 0x4D, ext.animation_idx.unwrap_or(0));
+    }
+}
+
+fn write_item_update(item_update: &mut [u8], item: &ItemUpdate) {
+    write_u8 (item_update, 0x0,  ITEM_UPDATE);
+    write_i32(item_update, 0x1,  item.frame_idx as i32 - 123);
+    write_u16(item_update, 0x5,  item.type_id);
+    write_u8 (item_update, 0x7,  item.state);
+    write_f32(item_update, 0x8,  direction_f32(item.direction));
+    write_f32(item_update, 0x14, item.position.x);
+    write_f32(item_update, 0x18, item.position.y);
+    write_u32(item_update, 0x22, item.spawn_id);
+    write_u8 (item_update, 0x26, item.missile_type);
+    write_u8 (item_update, 0x27, item.turnip_type);
+    write_u8 (item_update, 0x28, item.charge_shot_launched as u8);
+    write_u8 (item_update, 0x29, item.charge_shot_power);
+    write_i8 (item_update, 0x2A, item.owner);
+    write_u16(item_update, 0x2B, item.instance_id);
+}
+
+/// Stage events with their frame idx, sorted by frame.
+fn stage_events(stage_info: Option<&StageInfo>) -> Vec<(u32, Vec<u8>)> {
+    let mut stage_events = Vec::new();
+    let mut buffer = Vec::new();
+
+    match stage_info {
+        Some(StageInfo::Fountain(heights)) => {
+            for (platform, heights) in [(0, &heights.heights_r), (1, &heights.heights_l)] {
+                for &(frame_idx, height) in heights.iter() {
+                    let event = push_event(&mut buffer, FOD_INFO, FOD_INFO_SIZE);
+                    write_i32(event, 0x1, frame_idx as i32 - 123);
+                    write_u8 (event, 0x5, platform);
+                    write_f32(event, 0x6, height);
+                    stage_events.push((frame_idx, std::mem::take(&mut buffer)));
+                }
+            }
+        }
+        Some(StageInfo::DreamLand(directions)) => {
+            for &(frame_idx, direction) in directions.events.iter() {
+                let direction = match direction {
+                    WhispyDirection::None  => 0,
+                    WhispyDirection::Left  => 1,
+                    WhispyDirection::Right => 2,
+                };
+                let event = push_event(&mut buffer, DREAMLAND_INFO, DREAMLAND_INFO_SIZE);
+                write_i32(event, 0x1, frame_idx as i32 - 123);
+                write_u8 (event, 0x5, direction);
+                stage_events.push((frame_idx, std::mem::take(&mut buffer)));
+            }
+        }
+        Some(StageInfo::Stadium(transformations)) => {
+            for &(frame_idx, transformation) in transformations.events.iter() {
+                let transformation_id = match transformation {
+                    StadiumTransformation::Fire   => 3,
+                    StadiumTransformation::Grass  => 4,
+                    StadiumTransformation::Normal => 5,
+                    StadiumTransformation::Rock   => 6,
+                    StadiumTransformation::Water  => 9,
+                };
+                let event = push_event(&mut buffer, STADIUM_INFO, STADIUM_INFO_SIZE);
+                write_i32(event, 0x1, frame_idx as i32 - 123);
+                write_u16(event, 0x5, 2); // only the first event of a transformation is kept
+                write_u16(event, 0x7, transformation_id);
+                stage_events.push((frame_idx, std::mem::take(&mut buffer)));
+            }
+        }
+        None => (),
+    }
+
+    stage_events.sort_by_key(|(frame_idx, _)| *frame_idx);
+    stage_events
+}
+
+#[test]
+fn write_slp_round_trip() {
+    let slp = test_replay();
+    let game = parse_file_ext(&slp).unwrap();
+
+    let written = write_slp(&game).unwrap();
+    let round_trip = parse_file_ext(&written).unwrap();
+    assert_eq!(game.frame_count, 3);
+    assert_eq!(round_trip.frame_count, game.frame_count);
+    // the test replay has no lastFrame, the written one matches the frames
+    assert_eq!(round_trip.info.duration, -121);
+    assert_eq!(GameInfo { duration: game.info.duration, ..round_trip.info }, game.info);
+    assert_eq!(round_trip.items, game.items);
+    assert_eq!(round_trip.frame_starts, game.frame_starts);
+    for port in 0..2 {
+        assert_eq!(round_trip.frames_ext[port], game.frames_ext[port]);
+    }
+    assert_eq!(round_trip.metadata.duration, game.frame_count as i32 - 124);
+    assert_eq!(write_slp(&round_trip).unwrap(), written);
+
+    let mut too_large = game.clone();
+    too_large.gecko_codes = Some(GeckoCodes::new(vec![0; 0x10000].into_boxed_slice()));
+    assert_eq!(write_slp(&too_large), Err(SlpError::EventTooLarge));
+
+    // the rollback is kept in passthrough
+    let passthrough = write_slp_passthrough(&game, &slp).unwrap();
+    let raw_len = u32::from_be_bytes(passthrough[11..15].try_into().unwrap()) as usize;
+    assert_eq!(&passthrough[15..][..raw_len], &slp[15..]);
+    assert_eq!(parse_file_ext(&passthrough).unwrap().frames_ext[1], game.frames_ext[1]);
+}
+
+#[test]
+fn clip_replay_shifts_frames() {
+    let slp = test_replay();
+    let mut game = parse_file_ext(&slp).unwrap();
+    game.notes = Notes {
+        data: "firstsecond".to_owned(),
+        start_frames: vec![0, 2],
+        frame_lengths: vec![60, 30],
+        data_idx: vec![0, 5],
+        ..Notes::NULL
+    };
+    let slp = write_slp_passthrough(&game, &slp).unwrap();
+
+    let clip = clip_replay(&slp, -122..-120).unwrap();
+    let clipped = parse_file_ext(&clip).unwrap();
+    assert_eq!(clipped.game_start_raw, game.game_start_raw);
+    assert_eq!(clipped.info.end, game.info.end);
+    assert_eq!(clipped.metadata.duration, -122);
+    for port in 0..2 {
+        let ext = |g: &Game, r: std::ops::Range<usize>| g.frames_ext[port].as_ref().unwrap()[r].to_vec();
+        assert_eq!(ext(&clipped, 0..2), ext(&game, 1..3));
+    }
+    assert_eq!(clipped.notes.data, "second");
+    assert_eq!(clipped.notes.start_frames, vec![1]);
+    assert_eq!(clipped.notes.frame_lengths, vec![30]);
+    assert_eq!(clipped.notes.data_idx, vec![0]);
+
+    // slpz in, slpz out
+    let mut compressor = slpz::Compressor::new(3).unwrap();
+    let slpz = slpz::compress(&mut compressor, &slp).unwrap();
+    let mut decompressor = slpz::Decompressor::new().unwrap();
+    let clip_slpz = clip_replay(&slpz, -122..-120).unwrap();
+    assert_eq!(slpz::decompress(&mut decompressor, &clip_slpz).unwrap(), clip);
+
+    assert!(matches!(clip_replay(&slp, 0..100), Err(SlpError::InvalidClipRange)));
+
+    // ranges starting before the first frame are not shifted
+    for range in [-200..-122, i32::MIN..-122] {
+        let clipped = parse_file_ext(&clip_replay(&slp, range).unwrap()).unwrap();
+        assert_eq!(clipped.metadata.duration, -123);
+        for port in 0..2 {
+            let ext = |g: &Game| g.frames_ext[port].as_ref().unwrap()[0..1].to_vec();
+            assert_eq!(ext(&clipped), ext(&game));
+        }
+        assert_eq!(clipped.notes.data, "first");
+        assert_eq!(clipped.notes.start_frames, vec![0]);
+    }
+    let whole = parse_file_ext(&clip_replay(&slp, i32::MIN..i32::MAX).unwrap()).unwrap();
+    assert_eq!(whole.frames_ext, game.frames_ext);
+}
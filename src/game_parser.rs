@@ -19,6 +19,24 @@ pub fn parse_actions(frames: &[Frame]) -> Vec<crate::Action> {
     actions
 }
 
+/// Knockdown, getup and tech states.
+pub fn is_tech_state(state: ActionState) -> bool {
+    let n = state.as_u16();
+    matches!(state, ActionState::Standard(_))
+        && (StandardActionState::DownBoundU as u16..=StandardActionState::PassiveCeil as u16).contains(&n)
+}
+
+fn is_missed_tech_state(state: ActionState) -> bool {
+    use StandardActionState as SAS;
+    matches!(
+        state,
+        ActionState::Standard(
+            SAS::DownBoundU | SAS::DownWaitU | SAS::DownDamageU
+            | SAS::DownBoundD | SAS::DownWaitD | SAS::DownDamageD
+        )
+    )
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum JumpType {
     Full,
@@ -144,6 +162,7 @@ impl Action {
             LedgeAction => Action::parse_ledge_action(consumer), // probably never happens
             Hitstun => Action::parse_hitstun(consumer),
             GenericInactionable => {
+                if consumer.peek().is_some_and(is_tech_state) { return Action::parse_tech(consumer) }
                 consumer.skip_broad_state(GenericInactionable);
                 Err(ParseError::Unknown)
             }
@@ -158,6 +177,41 @@ impl Action {
         }
     }
 
+    fn parse_tech(consumer: &mut ActionBuilder) -> Result<Action, ParseError> {
+        use StandardActionState as SAS;
+
+        let frame = consumer.peek_frame().ok_or(ParseError::EOF)?;
+        let state = frame.state;
+        let forward_is_right = frame.direction == Direction::Right;
+        let roll = |forward: bool, left: HighLevelAction, right: HighLevelAction| {
+            if forward == forward_is_right { right } else { left }
+        };
+
+        let hla = match state {
+            ActionState::Standard(SAS::Passive) => HighLevelAction::TechInPlace,
+            ActionState::Standard(SAS::PassiveStandF) => roll(true, HighLevelAction::TechRollLeft, HighLevelAction::TechRollRight),
+            ActionState::Standard(SAS::PassiveStandB) => roll(false, HighLevelAction::TechRollLeft, HighLevelAction::TechRollRight),
+            ActionState::Standard(SAS::PassiveWall) => HighLevelAction::WallTech,
+            ActionState::Standard(SAS::PassiveWallJump) => HighLevelAction::WallTechJump,
+            ActionState::Standard(SAS::PassiveCeil) => HighLevelAction::CeilingTech,
+            ActionState::Standard(
+                SAS::DownBoundU | SAS::DownWaitU | SAS::DownDamageU
+                | SAS::DownBoundD | SAS::DownWaitD | SAS::DownDamageD
+            ) => {
+                consumer.skip_while(is_missed_tech_state);
+                return Ok(consumer.finish_action(HighLevelAction::MissedTech));
+            }
+            ActionState::Standard(SAS::DownStandU | SAS::DownStandD | SAS::DownSpotU | SAS::DownSpotD) => HighLevelAction::GetUp,
+            ActionState::Standard(SAS::DownAttackU | SAS::DownAttackD) => HighLevelAction::GetUpAttack,
+            ActionState::Standard(SAS::DownFowardU | SAS::DownFowardD) => roll(true, HighLevelAction::GetUpRollLeft, HighLevelAction::GetUpRollRight),
+            ActionState::Standard(SAS::DownBackU | SAS::DownBackD) => roll(false, HighLevelAction::GetUpRollLeft, HighLevelAction::GetUpRollRight),
+            _ => return Err(ParseError::Unknown),
+        };
+
+        consumer.skip_while(|st| st == state);
+        Ok(consumer.finish_action(hla))
+    }
+
     fn parse_roll(consumer: &mut ActionBuilder) -> Result<Action, ParseError> {
         let roll_state = consumer.next().ok_or(ParseError::EOF)?;
         let hla = match roll_state {
@@ -0,0 +1,167 @@
+use crate::file_parser::read_u32;
+
+/// The gecko code list, as installed when the replay was recorded. Added in v3.3.0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeckoCodes {
+    /// Reassembled gecko list, exactly as written to the replay.
+    pub raw: Box<[u8]>,
+    pub codes: Vec<GeckoCode>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeckoCode {
+    /// Code type with the address bit masked out. e.g. 0x04 for a 32 bit write, 0xC2 for an injection.
+    pub code_type: u8,
+
+    /// Full address in memory (0x80XXXXXX).
+    pub address: u32,
+
+    /// Everything in the code after the code type and address.
+    pub body: Box<[u8]>,
+}
+
+/// Well-known codes, identified from the gecko code list.
+///
+/// Stealth modes are not identified. Their injection addresses have not been confirmed against
+/// replays, and a guessed address would mark legal builds as modified.
+// TODO: stealth modes, matched by injection address like the UCF codes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KnownGeckoCode {
+    UcfDashback,
+    UcfShieldDrop,
+    FrozenStadium,
+    /// The PAL balance changes. These are many writes to character data with no single address,
+    /// so they are not matched from the code list. Slippi records them in the Game Start instead, see `Game::known_gecko_codes`.
+    Pal,
+}
+
+impl KnownGeckoCode {
+    /// (code type, address) of each known code.
+    /// Matched by injection or write address only, so any version of a code will be recognized.
+    pub const ADDRESSES: &'static [(KnownGeckoCode, u8, u32)] = &[
+        (KnownGeckoCode::UcfDashback,   0xC2, 0x800C9A44),
+        (KnownGeckoCode::UcfShieldDrop, 0xC2, 0x80099D7C),
+        (KnownGeckoCode::FrozenStadium, 0x04, 0x801D1548),
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            KnownGeckoCode::UcfDashback   => "UCF Dashback",
+            KnownGeckoCode::UcfShieldDrop => "UCF Shield Drop",
+            KnownGeckoCode::FrozenStadium => "Frozen Pokemon Stadium",
+            KnownGeckoCode::Pal           => "PAL",
+        }
+    }
+}
+
+impl GeckoCode {
+    pub fn identify(&self) -> Option<KnownGeckoCode> {
+        KnownGeckoCode::ADDRESSES.iter()
+            .find(|(_, code_type, address)| *code_type == self.code_type && *address == self.address)
+            .map(|(known, _, _)| *known)
+    }
+}
+
+impl GeckoCodes {
+    pub fn new(raw: Box<[u8]>) -> Self {
+        let codes = parse_gecko_codes(&raw);
+        GeckoCodes { raw, codes }
+    }
+
+    pub fn find(&self, code_type: u8, address: u32) -> Option<&GeckoCode> {
+        self.codes.iter().find(|c| c.code_type == code_type && c.address == address)
+    }
+
+    pub fn contains(&self, known: KnownGeckoCode) -> bool {
+        self.codes.iter().any(|c| c.identify() == Some(known))
+    }
+
+    /// Codes identified from the code list, in order of first occurrence.
+    pub fn known_codes(&self) -> Vec<KnownGeckoCode> {
+        let mut known = Vec::new();
+        for code in self.codes.iter().filter_map(|c| c.identify()) {
+            if !known.contains(&code) { known.push(code); }
+        }
+        known
+    }
+
+    pub fn has_ucf(&self) -> bool {
+        self.contains(KnownGeckoCode::UcfDashback) && self.contains(KnownGeckoCode::UcfShieldDrop)
+    }
+}
+
+/// Splits a raw gecko code list into individual codes.
+/// Stops at the end of the list (0xF0 code type) or at the first truncated code.
+pub fn parse_gecko_codes(raw: &[u8]) -> Vec<GeckoCode> {
+    let mut codes = Vec::new();
+    let mut cursor = 0;
+
+    while cursor + 8 <= raw.len() {
+        let first_word = read_u32(raw, cursor);
+        let second_word = read_u32(raw, cursor + 4) as usize;
+        let code_type = raw[cursor] & 0xFE;
+        let address = (first_word & 0x01FFFFFF) | 0x80000000;
+
+        if code_type == 0xF0 { break }
+
+        let code_len = match code_type {
+            // string write, second word is the byte count
+            0x06 => 8 + second_word.div_ceil(8) * 8,
+            // slider / serial write
+            0x08 => 16,
+            // execute asm and insert asm, second word is the line count
+            0xC0 | 0xC2 => 8 + second_word * 8,
+            _ => 8,
+        };
+
+        if cursor + code_len > raw.len() { break }
+
+        codes.push(GeckoCode {
+            code_type,
+            address,
+            body: raw[cursor+4..cursor+code_len].into(),
+        });
+
+        cursor += code_len;
+    }
+
+    codes
+}
+
+#[test]
+fn gecko_code_split() {
+    let raw: &[u8] = &[
+        0xC2, 0x0C, 0x9A, 0x44, 0x00, 0x00, 0x00, 0x01,
+        0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x04, 0x1D, 0x15, 0x48, 0x48, 0x00, 0x01, 0x84,
+        0xC2, 0x0C, 0x9A, 0x44, 0x00, 0x00, 0x00, 0x01,
+        0x60, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x06, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x03,
+        b'a',  b'b',  b'c',  0x00, 0x00, 0x00, 0x00, 0x00,
+        0xF0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    let codes = GeckoCodes::new(raw.into());
+    assert_eq!(codes.codes.len(), 4);
+    assert_eq!(codes.codes[0].address, 0x800C9A44);
+    assert_eq!(codes.codes[0].body.len(), 12);
+    assert_eq!(codes.codes[3].code_type, 0x06);
+    assert_eq!(codes.codes[3].address, 0x80001000);
+    assert_eq!(codes.known_codes(), vec![KnownGeckoCode::UcfDashback, KnownGeckoCode::FrozenStadium]);
+    assert!(!codes.has_ucf());
+}
+
+#[test]
+fn known_gecko_codes_from_game() {
+    let mut game = crate::parse_file(&crate::test_replay()).unwrap();
+    let raw: &[u8] = &[0x04, 0x1D, 0x15, 0x48, 0x48, 0x00, 0x01, 0x84];
+    game.gecko_codes = Some(GeckoCodes::new(raw.into()));
+    assert_eq!(game.known_gecko_codes(), vec![KnownGeckoCode::FrozenStadium]);
+
+    game.info.is_pal = true;
+    game.info.is_frozen_stadium = true;
+    assert_eq!(game.known_gecko_codes(), vec![KnownGeckoCode::FrozenStadium, KnownGeckoCode::Pal]);
+
+    game.gecko_codes = None;
+    assert_eq!(game.known_gecko_codes(), vec![KnownGeckoCode::Pal, KnownGeckoCode::FrozenStadium]);
+}
@@ -0,0 +1,390 @@
+use crate::*;
+
+/// Clamped raw stick magnitude counted as holding the stick against the gate.
+pub const STICK_RIM_MAGNITUDE: f32 = 0.9;
+
+/// Maximum angle in degrees between a held stick and a notch.
+pub const NOTCH_ANGLE_TOLERANCE: f32 = 3.0;
+
+/// Clamped raw stick values past the deadzone that still count as a wavedash notch.
+pub const WAVEDASH_NOTCH_RANGE: i8 = 4;
+
+/// Digital buttons, decoded from `Frame::buttons_mask`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Buttons(pub u16);
+
+impl Buttons {
+    pub const NONE        : Buttons = Buttons(0);
+    pub const D_PAD_LEFT  : Buttons = Buttons(buttons_mask::D_PAD_LEFT);
+    pub const D_PAD_RIGHT : Buttons = Buttons(buttons_mask::D_PAD_RIGHT);
+    pub const D_PAD_DOWN  : Buttons = Buttons(buttons_mask::D_PAD_DOWN);
+    pub const D_PAD_UP    : Buttons = Buttons(buttons_mask::D_PAD_UP);
+    pub const Z           : Buttons = Buttons(buttons_mask::Z);
+    pub const R           : Buttons = Buttons(buttons_mask::R_DIGITAL);
+    pub const L           : Buttons = Buttons(buttons_mask::L_DIGITAL);
+    pub const A           : Buttons = Buttons(buttons_mask::A);
+    pub const B           : Buttons = Buttons(buttons_mask::B);
+    pub const X           : Buttons = Buttons(buttons_mask::X);
+    pub const Y           : Buttons = Buttons(buttons_mask::Y);
+    pub const START       : Buttons = Buttons(buttons_mask::START);
+
+    pub const ALL: [(Buttons, &'static str); 12] = [
+        (Buttons::A          , "A"         ),
+        (Buttons::B          , "B"         ),
+        (Buttons::X          , "X"         ),
+        (Buttons::Y          , "Y"         ),
+        (Buttons::Z          , "Z"         ),
+        (Buttons::L          , "L"         ),
+        (Buttons::R          , "R"         ),
+        (Buttons::START      , "Start"     ),
+        (Buttons::D_PAD_UP   , "DPadUp"    ),
+        (Buttons::D_PAD_DOWN , "DPadDown"  ),
+        (Buttons::D_PAD_LEFT , "DPadLeft"  ),
+        (Buttons::D_PAD_RIGHT, "DPadRight" ),
+    ];
+
+    pub fn bits(self) -> u16 { self.0 }
+    pub fn is_empty(self) -> bool { self.0 == 0 }
+    pub fn contains(self, other: Buttons) -> bool { self.0 & other.0 == other.0 }
+    pub fn intersects(self, other: Buttons) -> bool { self.0 & other.0 != 0 }
+
+    /// Each button that is held, with its name.
+    pub fn iter(self) -> impl Iterator<Item = (Buttons, &'static str)> {
+        Buttons::ALL.into_iter().filter(move |(b, _)| self.contains(*b))
+    }
+}
+
+impl From<ButtonsMask> for Buttons {
+    fn from(mask: ButtonsMask) -> Self { Buttons(mask) }
+}
+
+impl std::ops::BitOr for Buttons {
+    type Output = Buttons;
+    fn bitor(self, rhs: Buttons) -> Buttons { Buttons(self.0 | rhs.0) }
+}
+
+impl std::ops::BitAnd for Buttons {
+    type Output = Buttons;
+    fn bitand(self, rhs: Buttons) -> Buttons { Buttons(self.0 & rhs.0) }
+}
+
+impl std::ops::Not for Buttons {
+    type Output = Buttons;
+    fn not(self) -> Buttons { Buttons(!self.0) }
+}
+
+impl std::fmt::Display for Buttons {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (_, name)) in self.iter().enumerate() {
+            if i != 0 { write!(f, "+")?; }
+            write!(f, "{}", name)?;
+        }
+        Ok(())
+    }
+}
+
+impl Frame {
+    pub fn buttons(&self) -> Buttons { Buttons(self.buttons_mask) }
+}
+
+/// Region of a processed stick, split by `STICK_INPUT_DEADZONE` on each axis.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StickRegion {
+    DeadZone,
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+}
+
+impl StickRegion {
+    pub fn from_stick(stick: Vector) -> Self {
+        use StickRegion::*;
+
+        let x = if stick.x >= STICK_INPUT_DEADZONE { 1 } else if stick.x <= -STICK_INPUT_DEADZONE { -1 } else { 0 };
+        let y = if stick.y >= STICK_INPUT_DEADZONE { 1 } else if stick.y <= -STICK_INPUT_DEADZONE { -1 } else { 0 };
+        match (x, y) {
+            ( 0,  0) => DeadZone,
+            ( 0,  1) => Up,
+            ( 1,  1) => UpRight,
+            ( 1,  0) => Right,
+            ( 1, -1) => DownRight,
+            ( 0, -1) => Down,
+            (-1, -1) => DownLeft,
+            (-1,  0) => Left,
+            _        => UpLeft,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        use StickRegion::*;
+        match self {
+            DeadZone  => "DeadZone",
+            Up        => "Up",
+            UpRight   => "UpRight",
+            Right     => "Right",
+            DownRight => "DownRight",
+            Down      => "Down",
+            DownLeft  => "DownLeft",
+            Left      => "Left",
+            UpLeft    => "UpLeft",
+        }
+    }
+}
+
+/// A stick held against a notch of the gate.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StickNotch {
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+    /// The shallowest angle that still registers down, for the longest wavedash.
+    WavedashLeft,
+    WavedashRight,
+}
+
+impl StickNotch {
+    /// Classifies a raw stick. None if the stick is not against the gate or not near a notch.
+    pub fn from_raw(stick: VectorI8) -> Option<Self> {
+        use StickNotch::*;
+
+        let clamped = stick.clamped();
+        let (x, y) = (clamped.x as f32, clamped.y as f32);
+        let max = VectorI8::MAX as f32;
+        if (x*x + y*y).sqrt() < STICK_RIM_MAGNITUDE * max { return None }
+
+        let deadzone = (STICK_INPUT_DEADZONE * max).round() as i8;
+        if (-(deadzone + WAVEDASH_NOTCH_RANGE)..=-deadzone).contains(&clamped.y) {
+            return Some(if clamped.x < 0 { WavedashLeft } else { WavedashRight });
+        }
+
+        let angle = stick_angle(clamped);
+        let notches = [Right, UpRight, Up, UpLeft, Left, DownLeft, Down, DownRight];
+        notches.into_iter().enumerate().find_map(|(i, notch)| {
+            let diff = (angle - i as f32 * 45.0).abs();
+            (diff.min(360.0 - diff) <= NOTCH_ANGLE_TOLERANCE).then_some(notch)
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        use StickNotch::*;
+        match self {
+            Up            => "Up",
+            UpRight       => "UpRight",
+            Right         => "Right",
+            DownRight     => "DownRight",
+            Down          => "Down",
+            DownLeft      => "DownLeft",
+            Left          => "Left",
+            UpLeft        => "UpLeft",
+            WavedashLeft  => "WavedashLeft",
+            WavedashRight => "WavedashRight",
+        }
+    }
+}
+
+/// Angle in degrees counter-clockwise from right, in 0..360.
+pub fn stick_angle(stick: VectorI8) -> f32 {
+    let angle = (stick.y as f32).atan2(stick.x as f32).to_degrees();
+    if angle < 0.0 { angle + 360.0 } else { angle }
+}
+
+/// Decoded inputs of a single frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InputFrame {
+    pub frame_idx: usize,
+    pub buttons: Buttons,
+    /// Buttons held this frame but not the previous frame.
+    pub pressed: Buttons,
+    /// Buttons held the previous frame but not this frame.
+    pub released: Buttons,
+    pub stick: Vector,
+    /// Clamped to the gate.
+    pub stick_raw: VectorI8,
+    pub stick_region: StickRegion,
+    pub stick_notch: Option<StickNotch>,
+    pub c_stick: Vector,
+    pub c_stick_raw: VectorI8,
+    pub c_stick_region: StickRegion,
+    pub trigger: f32,
+    pub left_trigger_raw: f32,
+    pub right_trigger_raw: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ButtonEvent {
+    pub frame_idx: usize,
+    /// A single button.
+    pub button: Buttons,
+    pub pressed: bool,
+}
+
+/// Decodes the inputs of every frame.
+pub fn input_timeline(frames: &[Frame]) -> Vec<InputFrame> {
+    let mut prev_buttons = Buttons::NONE;
+    frames.iter().enumerate().map(|(frame_idx, frame)| {
+        let buttons = frame.buttons();
+        let input = InputFrame {
+            frame_idx,
+            buttons,
+            pressed: buttons & !prev_buttons,
+            released: prev_buttons & !buttons,
+            stick: frame.left_stick_coords,
+            stick_raw: frame.left_stick_coords_raw.clamped(),
+            stick_region: StickRegion::from_stick(frame.left_stick_coords),
+            stick_notch: StickNotch::from_raw(frame.left_stick_coords_raw),
+            c_stick: frame.right_stick_coords,
+            c_stick_raw: frame.right_stick_coords_raw.clamped(),
+            c_stick_region: StickRegion::from_stick(frame.right_stick_coords),
+            trigger: frame.analog_trigger_value,
+            left_trigger_raw: frame.left_trigger_value_raw,
+            right_trigger_raw: frame.right_trigger_value_raw,
+        };
+        prev_buttons = buttons;
+        input
+    }).collect()
+}
+
+/// Every button press and release, in order.
+pub fn button_events(frames: &[Frame]) -> Vec<ButtonEvent> {
+    let mut events = Vec::new();
+    for input in input_timeline(frames) {
+        for (button, _) in input.pressed.iter() {
+            events.push(ButtonEvent { frame_idx: input.frame_idx, button, pressed: true });
+        }
+        for (button, _) in input.released.iter() {
+            events.push(ButtonEvent { frame_idx: input.frame_idx, button, pressed: false });
+        }
+    }
+    events
+}
+
+/// Writes the timeline as csv with a header row. Frames are game frames, starting at -123.
+/// Held buttons are joined with '+'.
+pub fn inputs_csv(timeline: &[InputFrame]) -> String {
+    use std::fmt::Write;
+
+    let mut csv = String::from(
+        "frame,buttons,stick_x,stick_y,stick_raw_x,stick_raw_y,stick_region,stick_notch,\
+         c_stick_x,c_stick_y,c_stick_raw_x,c_stick_raw_y,c_stick_region,trigger,l_trigger_raw,r_trigger_raw\n"
+    );
+    for i in timeline {
+        writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            i.frame_idx as i32 - 123,
+            i.buttons,
+            i.stick.x, i.stick.y,
+            i.stick_raw.x, i.stick_raw.y,
+            i.stick_region.as_str(),
+            i.stick_notch.map(|n| n.as_str()).unwrap_or(""),
+            i.c_stick.x, i.c_stick.y,
+            i.c_stick_raw.x, i.c_stick_raw.y,
+            i.c_stick_region.as_str(),
+            i.trigger,
+            i.left_trigger_raw,
+            i.right_trigger_raw,
+        ).unwrap();
+    }
+    csv
+}
+
+/// Writes the timeline as a json array of frames. Frames are game frames, starting at -123.
+pub fn inputs_json(timeline: &[InputFrame]) -> String {
+    use std::fmt::Write;
+
+    let mut json = String::from("[");
+    for (n, i) in timeline.iter().enumerate() {
+        if n != 0 { json.push(','); }
+        write!(json, "{{\"frame\":{},\"buttons\":[", i.frame_idx as i32 - 123).unwrap();
+        for (k, (_, name)) in i.buttons.iter().enumerate() {
+            if k != 0 { json.push(','); }
+            write!(json, "\"{}\"", name).unwrap();
+        }
+        write!(
+            json,
+            "],\"stick\":{{\"x\":{},\"y\":{},\"rawX\":{},\"rawY\":{},\"region\":\"{}\",\"notch\":",
+            i.stick.x, i.stick.y, i.stick_raw.x, i.stick_raw.y, i.stick_region.as_str(),
+        ).unwrap();
+        match i.stick_notch {
+            Some(notch) => write!(json, "\"{}\"", notch.as_str()).unwrap(),
+            None => json.push_str("null"),
+        }
+        write!(
+            json,
+            "}},\"cStick\":{{\"x\":{},\"y\":{},\"rawX\":{},\"rawY\":{},\"region\":\"{}\"}},\"trigger\":{},\"lTriggerRaw\":{},\"rTriggerRaw\":{}}}",
+            i.c_stick.x, i.c_stick.y, i.c_stick_raw.x, i.c_stick_raw.y, i.c_stick_region.as_str(),
+            i.trigger, i.left_trigger_raw, i.right_trigger_raw,
+        ).unwrap();
+    }
+    json.push(']');
+    json
+}
+
+#[cfg(test)]
+fn input_frames() -> Vec<Frame> {
+    let game = parse_file(&test_replay()).unwrap();
+    let mut frames = test_frames(&game, 0, 3);
+    frames[1].buttons_mask = buttons_mask::A | buttons_mask::B;
+    frames[2].buttons_mask = buttons_mask::B;
+    frames[2].left_stick_coords = Vector { x: 0.95, y: -0.3 };
+    frames[2].left_stick_coords_raw = VectorI8 { x: 76, y: -24 };
+    frames
+}
+
+#[test]
+fn buttons_from_mask() {
+    let frames = input_frames();
+    assert_eq!(frames[0].buttons(), Buttons::NONE);
+    assert_eq!(frames[1].buttons().to_string(), "A+B");
+    assert!(frames[1].buttons().contains(Buttons::A | Buttons::B));
+}
+
+#[test]
+fn stick_notches() {
+    assert_eq!(StickNotch::from_raw(VectorI8 { x: 0, y: 80 }), Some(StickNotch::Up));
+    assert_eq!(StickNotch::from_raw(VectorI8 { x: 57, y: -57 }), Some(StickNotch::DownRight));
+    assert_eq!(StickNotch::from_raw(VectorI8 { x: 30, y: 30 }), None);
+}
+
+#[test]
+fn input_timeline_and_button_events() {
+    let frames = input_frames();
+    let timeline = input_timeline(&frames);
+    assert_eq!(timeline[1].pressed, Buttons::A | Buttons::B);
+    assert_eq!((timeline[2].pressed, timeline[2].released), (Buttons::NONE, Buttons::A));
+    assert_eq!(timeline[2].stick_region, StickRegion::DownRight);
+    assert_eq!(timeline[2].stick_notch, Some(StickNotch::WavedashRight));
+
+    assert_eq!(
+        button_events(&frames),
+        vec![
+            ButtonEvent { frame_idx: 1, button: Buttons::A, pressed: true },
+            ButtonEvent { frame_idx: 1, button: Buttons::B, pressed: true },
+            ButtonEvent { frame_idx: 2, button: Buttons::A, pressed: false },
+        ],
+    );
+}
+
+#[test]
+fn inputs_csv_and_json_export() {
+    let timeline = input_timeline(&input_frames());
+    let csv = inputs_csv(&timeline);
+    assert_eq!(csv.lines().count(), 4);
+    assert!(csv.lines().nth(3).unwrap().starts_with("-121,B,0.95,-0.3,76,-24,DownRight,WavedashRight,"));
+
+    let json = inputs_json(&timeline[1..2]);
+    assert_eq!(
+        json,
+        r#"[{"frame":-122,"buttons":["A","B"],"stick":{"x":0,"y":0,"rawX":0,"rawY":0,"region":"DeadZone","notch":null},"cStick":{"x":0,"y":0,"rawX":0,"rawY":0,"region":"DeadZone"},"trigger":0,"lTriggerRaw":0,"rTriggerRaw":0}]"#,
+    );
+}
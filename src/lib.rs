@@ -4,6 +4,12 @@ pub use game_parser::*;
 mod file_parser;
 pub use file_parser::*;
 
+mod file_writer;
+pub use file_writer::*;
+
+mod stream_parser;
+pub use stream_parser::*;
+
 mod states;
 pub use states::*;
 
@@ -13,6 +19,50 @@ pub use game_enums::*;
 mod shift_jis_decoder;
 pub use shift_jis_decoder::*;
 
+mod gecko_codes;
+pub use gecko_codes::*;
+
+mod ubjson;
+pub use ubjson::*;
+
+mod replay_library;
+pub use replay_library::*;
+
+mod query;
+pub use query::*;
+
+mod sets;
+pub use sets::*;
+
+mod stats;
+pub use stats::*;
+
+mod combos;
+pub use combos::*;
+
+mod situations;
+pub use situations::*;
+
+mod tech;
+pub use tech::*;
+
+mod shield;
+pub use shield::*;
+
+mod inputs;
+pub use inputs::*;
+
+mod di;
+pub use di::*;
+
+mod physics;
+pub use physics::*;
+
+#[cfg(test)]
+mod test_util;
+#[cfg(test)]
+pub(crate) use test_util::*;
+
 use std::path::Path;
 
 pub type SlpResult<T> = Result<T, SlpError>;
@@ -23,10 +73,15 @@ pub enum InvalidLocation {
     Metadata,
     EventSizes,
     GameStart,
+    FrameStart,
     ItemUpdate,
     PreFrameUpdate,
     PostFrameUpdate,
+    GameEnd,
+    GeckoList,
     StadiumTransformation,
+    WhispyBlowDirection,
+    FountainPlatformHeight,
     ParseActionState,
     EventSlicing,
 }
@@ -42,6 +97,10 @@ pub enum SlpError {
     NotAnSlpFile,
     InvalidFile(InvalidLocation),
     ZstdInitError,
+    SlpzCompressionError,
+    InvalidClipRange,
+    /// An event or the gecko list is too large for its size in the Event Payloads table.
+    EventTooLarge,
 
     FileDoesNotExist,
     IOError,
@@ -51,6 +110,57 @@ impl From<std::io::Error> for SlpError {
     fn from(_: std::io::Error) -> SlpError { SlpError::IOError }
 }
 
+impl std::error::Error for SlpError {}
+
+pub type DetailedSlpResult<T> = Result<T, DetailedSlpError>;
+
+/// An `SlpError` along with where it occurred, returned by the `*_detailed` functions.
+/// Converts into the cheap `SlpError` by dropping the context.
+#[derive(Debug)]
+pub struct DetailedSlpError {
+    pub kind: SlpError,
+
+    /// Offset into the slp file (decompressed, for slpz files) of the event or section that failed.
+    pub offset: Option<usize>,
+
+    /// Command byte of the event that failed.
+    pub command: Option<u8>,
+
+    /// Frame number of the last frame event read before the error.
+    pub frame: Option<i32>,
+
+    /// Replay version as (major, minor, patch). None if the error occurred before Game Start was parsed.
+    pub version: Option<(u8, u8, u8)>,
+
+    pub io_error: Option<std::io::Error>,
+}
+
+impl From<SlpError> for DetailedSlpError {
+    fn from(kind: SlpError) -> Self {
+        DetailedSlpError { kind, offset: None, command: None, frame: None, version: None, io_error: None }
+    }
+}
+
+impl From<InvalidLocation> for DetailedSlpError {
+    fn from(il: InvalidLocation) -> Self { SlpError::InvalidFile(il).into() }
+}
+
+impl From<std::io::Error> for DetailedSlpError {
+    fn from(e: std::io::Error) -> Self {
+        DetailedSlpError { io_error: Some(e), ..SlpError::IOError.into() }
+    }
+}
+
+impl From<DetailedSlpError> for SlpError {
+    fn from(e: DetailedSlpError) -> Self { e.kind }
+}
+
+impl std::error::Error for DetailedSlpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.io_error.as_ref().map(|e| e as _)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Action {
     pub start_state: BroadState,
@@ -77,6 +187,24 @@ impl VulnState {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LCancelStatus {
+    None = 0,
+    Successful,
+    Unsuccessful,
+}
+
+impl LCancelStatus {
+    pub fn from_u8(n: u8) -> Option<Self> {
+        Some(match n {
+            0 => LCancelStatus::None,
+            1 => LCancelStatus::Successful,
+            2 => LCancelStatus::Unsuccessful,
+            _ => return None,
+        })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Frame {
     pub character: Character,
@@ -120,6 +248,44 @@ pub struct Frame {
     pub vuln_state: VulnState,
 }
 
+/// Pre and post frame update fields that `Frame` does not keep.
+/// Only parsed by `parse_file_ext`.
+///
+/// Fields that `Frame` leaves zeroed in older replays are repeated here,
+/// and are None if the replay is older than the version that added them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameExt {
+    // pre frame update
+    pub random_seed: u32,
+    pub pre_state_num: u16,
+    pub pre_position: Vector,
+    pub pre_direction: Direction,
+    pub processed_buttons: u32,
+    pub pre_percent: f32,
+    /// None if older than v3.15.0.
+    pub left_stick_coords_raw: Option<VectorI8>,
+    /// None if older than v3.17.0.
+    pub right_stick_coords_raw: Option<VectorI8>,
+
+    // post frame update
+    pub combo_count: u8,
+    pub l_cancel_status: LCancelStatus,
+    /// None if older than v3.5.0.
+    pub velocity: Option<Vector>,
+    /// None if older than v3.5.0.
+    pub hit_velocity: Option<Vector>,
+    /// None if older than v3.5.0.
+    pub ground_x_velocity: Option<f32>,
+    /// None if older than v3.8.0.
+    pub hitlag_frames: Option<f32>,
+    /// None if older than v3.11.0.
+    pub animation_idx: Option<u32>,
+    /// None if older than v3.16.0.
+    pub last_hit_by_instance_id: Option<u16>,
+    /// None if older than v3.16.0.
+    pub instance_id: Option<u16>,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct StaleMove {
     pub attack: AttackKind,
@@ -191,6 +357,17 @@ pub fn compute_staled_moves(
     stale_moves
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FrameStart {
+    pub random_seed: u32,
+    /// Zero if older than v3.10.0.
+    pub scene_frame_counter: u32,
+}
+
+impl FrameStart {
+    pub const NULL: FrameStart = FrameStart { random_seed: 0, scene_frame_counter: 0 };
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ItemUpdate {
     pub frame_idx: u32,
@@ -230,7 +407,35 @@ pub struct GameInfo {
     
     pub has_notes: bool,
     pub is_teams: bool,
-    
+
+    /// None if the game end event was not written.
+    /// Reading this from an slpz file decompresses the whole replay, see `parse_file_info_slpz`.
+    pub end: Option<GameEnd>,
+
+    pub player_settings: [PlayerSettings; 4],
+    pub damage_ratio: F32Bits,
+    /// -1 if items are off, otherwise 0 (very low) to 4 (very high).
+    pub item_spawn_frequency: i8,
+    /// Which items are enabled. Bit layout follows the slippi replay spec.
+    pub item_spawn_bitfield: [u8; 5],
+    pub random_seed: u32,
+    /// False if older than v1.5.0.
+    pub is_pal: bool,
+    /// False if older than v2.0.0.
+    pub is_frozen_stadium: bool,
+    /// Zero if older than v3.7.0.
+    pub minor_scene: u8,
+    /// Zero if older than v3.7.0. e.g. 0x2 for VS mode, 0x8 for online play.
+    pub major_scene: u8,
+    /// None if older than v3.12.0.
+    pub language: Option<Language>,
+    /// Null terminated ascii. Zeroes if played offline or older than v3.14.0.
+    pub match_id: [u8; 51],
+    /// Zero if played offline or older than v3.14.0.
+    pub game_number: u32,
+    /// Zero if played offline or older than v3.14.0.
+    pub tiebreaker_number: u32,
+
     pub version_major: u8,
     pub version_minor: u8,
     pub version_patch: u8,
@@ -249,15 +454,30 @@ impl TeamPorts {
     };
 }
 
+fn min_version(version: (u8, u8, u8), mj: u8, mn: u8, pt: u8) -> bool {
+    let (major, minor, patch) = version;
+    if major < mj { return false; }
+    if major > mj { return true; }
+    if minor < mn { return false; }
+    if minor > mn { return true; }
+    if patch < pt { return false; }
+    if patch > pt { return true; }
+    true
+}
+
 impl GameInfo {
     pub fn min_version(&self, mj: u8, mn: u8, pt: u8) -> bool {
-        if self.version_major < mj { return false; }
-        if self.version_major > mj { return true; }
-        if self.version_minor < mn { return false; }
-        if self.version_minor > mn { return true; }
-        if self.version_patch < pt { return false; }
-        if self.version_patch > pt { return true; }
-        true
+        min_version((self.version_major, self.version_minor, self.version_patch), mj, mn, pt)
+    }
+
+    pub fn has_cpu(&self) -> bool {
+        self.player_settings.iter().any(|p| p.player_type == PlayerType::Cpu)
+    }
+
+    /// Empty if played offline or older than v3.14.0.
+    pub fn match_id_str(&self) -> &str {
+        let len = self.match_id.iter().position(|&b| b == 0).unwrap_or(self.match_id.len());
+        std::str::from_utf8(&self.match_id[..len]).unwrap_or("")
     }
     
     // does not clear name_buf before appending.
@@ -276,10 +496,34 @@ impl GameInfo {
         }
         names
     }
+
+    // does not clear code_buf before appending.
+    pub fn connect_codes<'a>(&self, code_buf: &'a mut String) -> [&'a str; 4] {
+        let mut code_idx = [(0, 0); 4];
+        for (idx, code) in code_idx.iter_mut().zip(self.connect_codes.iter()) {
+            let code_start = code_buf.len();
+            decode_shift_jis(code, code_buf);
+            let code_end = code_buf.len();
+            *idx = (code_start, code_end);
+        }
+        let mut codes = [""; 4];
+        for port in 0..4 {
+            let (a, b) = code_idx[port];
+            codes[port] = &code_buf[a..b];
+        }
+        codes
+    }
     
+    /// Panics if the frame's port is not in the game. See `try_character_colour`.
     pub fn character_colour(&self, frame: &Frame) -> CharacterColour {
-        let costume = self.starting_character_colours[frame.port_idx as usize].unwrap().costume_idx();
-        CharacterColour::from_character_and_colour(frame.character, costume).unwrap()
+        self.try_character_colour(frame).expect("frame's port is not in the game")
+    }
+
+    /// None if the frame's port is not in the game.
+    pub fn try_character_colour(&self, frame: &Frame) -> Option<CharacterColour> {
+        let costume = (*self.starting_character_colours.get(frame.port_idx as usize)?)?.costume_idx();
+        CharacterColour::from_character_and_colour(frame.character, costume)
+            .or_else(|| CharacterColour::from_character_and_colour(frame.character, 0))
     }
     
     pub fn team_ports(&self) -> [TeamPorts; 4] {
@@ -330,23 +574,204 @@ pub struct GameStart {
     pub names: [[u8; 31]; 4],
     pub connect_codes: [[u8; 10]; 4],
     pub is_teams: bool,
+    pub player_settings: [PlayerSettings; 4],
+    pub damage_ratio: F32Bits,
+    /// -1 if items are off, otherwise 0 (very low) to 4 (very high).
+    pub item_spawn_frequency: i8,
+    /// Which items are enabled. Bit layout follows the slippi replay spec.
+    pub item_spawn_bitfield: [u8; 5],
+    pub random_seed: u32,
+    /// False if older than v1.5.0.
+    pub is_pal: bool,
+    /// False if older than v2.0.0.
+    pub is_frozen_stadium: bool,
+    /// Zero if older than v3.7.0.
+    pub minor_scene: u8,
+    /// Zero if older than v3.7.0. e.g. 0x2 for VS mode, 0x8 for online play.
+    pub major_scene: u8,
+    /// None if older than v3.12.0.
+    pub language: Option<Language>,
+    /// Null terminated ascii. Zeroes if played offline or older than v3.14.0.
+    pub match_id: [u8; 51],
+    /// Zero if played offline or older than v3.14.0.
+    pub game_number: u32,
+    /// Zero if played offline or older than v3.14.0.
+    pub tiebreaker_number: u32,
     pub version_major: u8,
     pub version_minor: u8,
     pub version_patch: u8,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PlayerType {
+    Human = 0,
+    Cpu,
+    Demo,
+    Empty,
+}
+
+impl PlayerType {
+    pub fn from_u8(n: u8) -> Option<Self> {
+        Some(match n {
+            0 => PlayerType::Human,
+            1 => PlayerType::Cpu,
+            2 => PlayerType::Demo,
+            3 => PlayerType::Empty,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UcfSetting {
+    Off = 0,
+    Ucf,
+    Dween,
+}
+
+impl UcfSetting {
+    pub fn from_u32(n: u32) -> Option<Self> {
+        Some(match n {
+            0 => UcfSetting::Off,
+            1 => UcfSetting::Ucf,
+            2 => UcfSetting::Dween,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Language {
+    Japanese = 0,
+    English,
+}
+
+impl Language {
+    pub fn from_u8(n: u8) -> Option<Self> {
+        Some(match n {
+            0 => Language::Japanese,
+            1 => Language::English,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PlayerSettings {
+    pub player_type: PlayerType,
+    /// Only meaningful for CPUs.
+    pub cpu_level: u8,
+    pub stocks: u8,
+    pub handicap: u8,
+    pub stamina_mode: bool,
+    pub offense_ratio: F32Bits,
+    pub defense_ratio: F32Bits,
+    pub model_scale: F32Bits,
+    pub ucf_dashback: UcfSetting,
+    pub ucf_shield_drop: UcfSetting,
+}
+
+impl PlayerSettings {
+    pub const NULL: PlayerSettings = PlayerSettings {
+        player_type     : PlayerType::Empty,
+        cpu_level       : 0,
+        stocks          : 0,
+        handicap        : 0,
+        stamina_mode    : false,
+        offense_ratio   : F32Bits(0),
+        defense_ratio   : F32Bits(0),
+        model_scale     : F32Bits(0),
+        ucf_dashback    : UcfSetting::Off,
+        ucf_shield_drop : UcfSetting::Off,
+    };
+}
+
+impl GameStart {
+    pub fn min_version(&self, mj: u8, mn: u8, pt: u8) -> bool {
+        min_version((self.version_major, self.version_minor, self.version_patch), mj, mn, pt)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameEndMethod {
+    /// Only written before v2.0.0.
+    Unresolved,
+    /// Only written before v2.0.0.
+    Resolved,
+    Time,
+    Game,
+    NoContest,
+    /// A method byte this parser does not know.
+    Unknown(u8),
+}
+
+impl GameEndMethod {
+    pub fn from_u8(n: u8) -> Option<Self> {
+        Some(match n {
+            0 => GameEndMethod::Unresolved,
+            1 => GameEndMethod::Time,
+            2 => GameEndMethod::Game,
+            3 => GameEndMethod::Resolved,
+            7 => GameEndMethod::NoContest,
+            _ => return None,
+        })
+    }
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            GameEndMethod::Unresolved => 0,
+            GameEndMethod::Time       => 1,
+            GameEndMethod::Game       => 2,
+            GameEndMethod::Resolved   => 3,
+            GameEndMethod::NoContest  => 7,
+            GameEndMethod::Unknown(n) => n,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GameEnd {
+    pub method: GameEndMethod,
+
+    /// Port idx of the player who quit out with LRAS. None if the game did not end by LRAS.
+    pub lras_initiator: Option<u8>,
+
+    /// Zero indexed placement of each port (0 = first place).
+    /// None if the port is unused or the replay is older than v3.13.0.
+    pub placements: [Option<u8>; 4],
+}
+
+impl GameEnd {
+    pub fn is_lras(&self) -> bool {
+        self.lras_initiator.is_some()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Game {
     pub frame_count: usize,
     pub frames: [Option<Box<[Frame]>>; 4],
     pub follower_frames: [Option<Box<[Frame]>>; 4],
 
+    /// None unless parsed with `parse_file_ext`. Same layout as `frames`.
+    pub frames_ext: [Option<Box<[FrameExt]>>; 4],
+    /// None unless parsed with `parse_file_ext`. Same layout as `follower_frames`.
+    pub follower_frames_ext: [Option<Box<[FrameExt]>>; 4],
+
+    /// Same length as the frames.
+    pub frame_starts: Box<[FrameStart]>,
+
     /// get item_range with `item_idx[frame]..item_idx[frame+1]`
     pub item_idx: Box<[u32]>,
     pub items: Box<[ItemUpdate]>,
     pub info: GameInfo,
     pub stage_info: Option<StageInfo>,
+    /// None if older than v3.3.0.
+    pub gecko_codes: Option<GeckoCodes>,
     pub notes: Notes,
+    pub metadata: Metadata,
+    /// The Game Start event as written. Kept so `write_slp` can preserve fields that are not parsed.
+    pub game_start_raw: Box<[u8]>,
 }
 
 #[derive(Clone, Debug)]
@@ -371,10 +796,24 @@ pub struct StadiumTransformations {
     pub events: Vec<(u32, StadiumTransformation)>,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WhispyDirection {
+    None,
+    Left,
+    Right,
+}
+
+#[derive(Clone, Debug)]
+pub struct WhispyBlowDirections {
+    // (frame_idx, new direction)
+    pub events: Vec<(u32, WhispyDirection)>,
+}
+
 #[derive(Clone, Debug)]
 pub enum StageInfo {
     Fountain(FountainHeights),
     Stadium(StadiumTransformations),
+    DreamLand(WhispyBlowDirections),
 }
 
 impl Game {
@@ -383,6 +822,66 @@ impl Game {
         let end = self.item_idx[frame+1] as usize;
         &self.items[start..end]
     }
+
+    /// Known codes from the gecko list, followed by PAL and Frozen Stadium if the Game Start says they were on.
+    /// Replays older than v3.3.0 have no gecko list, so only the Game Start is used.
+    pub fn known_gecko_codes(&self) -> Vec<KnownGeckoCode> {
+        let mut known = self.gecko_codes.as_ref().map(|g| g.known_codes()).unwrap_or_default();
+        for (on, code) in [(self.info.is_pal, KnownGeckoCode::Pal), (self.info.is_frozen_stadium, KnownGeckoCode::FrozenStadium)] {
+            if on && !known.contains(&code) { known.push(code); }
+        }
+        known
+    }
+
+    /// Every death for each port, in order.
+    ///
+    /// Only the leader's deaths are stock losses, so Nana's deaths are not included.
+    /// Hits by Nana are attributed to her port, with the killing move taken from her frames.
+    pub fn stock_events(&self) -> [Vec<StockEvent>; 4] {
+        let mut events: [Vec<StockEvent>; 4] = Default::default();
+
+        for (port, port_events) in events.iter_mut().enumerate() {
+            let Some(frames) = self.frames[port].as_deref() else { continue };
+            let mut stock_start = 0;
+
+            for i in 1..frames.len() {
+                let (prev, frame) = (&frames[i-1], &frames[i]);
+                if frame.state == ActionState::Standard(StandardActionState::Rebirth) { stock_start = i; }
+                if !died(prev, frame) { continue }
+                let Some(blast_zone) = BlastZone::from_state(frame.state) else { continue };
+
+                let killer = (frame.last_hit_by < 4 && frame.last_hit_by as usize != port)
+                    .then_some(frame.last_hit_by);
+
+                let killing_move = killer.and_then(|killer| {
+                    // the last frame this stock the victim was damaged by the killer
+                    let hit_idx = (stock_start.max(1)..=i).rev().find(|&j| {
+                        frames[j].percent > frames[j-1].percent && frames[j].last_hit_by == killer
+                    }).unwrap_or(i);
+                    let hit_by_id = frames[hit_idx].last_hit_by_instance_id;
+
+                    let leader = self.frames[killer as usize].as_deref()?.get(hit_idx)?;
+                    let follower = self.follower_frames[killer as usize].as_deref().and_then(|f| f.get(hit_idx));
+                    Some(match follower {
+                        Some(follower) if hit_by_id != 0 && follower.instance_id == hit_by_id => follower.last_hitting_attack_id,
+                        _ => leader.last_hitting_attack_id,
+                    })
+                });
+
+                port_events.push(StockEvent {
+                    frame_idx: i,
+                    blast_zone,
+                    death_state: frame.state,
+                    killer,
+                    killing_move,
+                    percent: frame.percent,
+                    stocks_remaining: frame.stock_count,
+                });
+            }
+        }
+
+        events
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -568,42 +1067,56 @@ fn simple_hash(bytes: &[u8]) -> u64 {
 
 
 pub fn read_info(path: &Path) -> SlpResult<GameInfo> {
+    read_info_detailed(path).map_err(SlpError::from)
+}
+
+pub fn read_info_detailed(path: &Path) -> DetailedSlpResult<GameInfo> {
     let ex = path.extension();
 
+    let mut file = open_file(path)?;
     let info = if ex != Some(std::ffi::OsStr::new("slpz")) {
-        let mut file = std::fs::File::open(path).map_err(|_| SlpError::FileDoesNotExist)?;
-        file_parser::parse_file_info(&mut file)?
+        file_parser::parse_file_info_inner(&mut file)?
     } else {
-        let mut file = std::fs::File::open(path).map_err(|_| SlpError::FileDoesNotExist)?;
-        file_parser::parse_file_info_slpz(&mut file)?
+        file_parser::parse_file_info_slpz_inner(&mut file)?
     };
 
     Ok(info)
 }
 
 pub fn read_game(path: &Path) -> SlpResult<Game> {
+    read_game_detailed(path).map_err(SlpError::from)
+}
+
+pub fn read_game_detailed(path: &Path) -> DetailedSlpResult<Game> {
     use std::io::Read;
 
-    let mut file = std::fs::File::open(path).map_err(|_| SlpError::FileDoesNotExist)?;
+    let mut file = open_file(path)?;
     let mut buf = Vec::new();
-    file.read_to_end(&mut buf).unwrap();
+    file.read_to_end(&mut buf)?;
 
     let ex = path.extension();
     let game = if ex != Some(std::ffi::OsStr::new("slpz")) {
-        file_parser::parse_file(&buf)?
+        file_parser::parse_file_detailed(&buf)?
     } else {
-        file_parser::parse_file_slpz(&buf)?
+        file_parser::parse_file_slpz_detailed(&buf)?
     };
 
     Ok(game)
 }
 
+fn open_file(path: &Path) -> DetailedSlpResult<std::fs::File> {
+    std::fs::File::open(path).map_err(|e| DetailedSlpError {
+        io_error: Some(e),
+        ..SlpError::FileDoesNotExist.into()
+    })
+}
+
 pub fn alter_notes(metadata: &mut Vec<u8>, notes: &Notes) {
     let write_i = metadata
         .windows(5)
         .position(|w| w == b"notes")
-        .map(|p| p - 2)
-        .unwrap_or(metadata.len()-1);
+        .and_then(|p| p.checked_sub(2))
+        .unwrap_or(metadata.len().saturating_sub(1));
 
     metadata.resize(write_i, 0u8);
     write_notes(metadata, notes);
@@ -661,6 +1174,11 @@ pub fn write_notes_to_game(path: &Path, notes: &Notes) -> SlpResult<()> {
         let metadata_offset = read_u32(&header[12..]) as usize;
         let compressed_events_offset = read_u32(&header[16..]) as usize;
 
+        let file_len = file.metadata()?.len() as usize;
+        if metadata_offset > compressed_events_offset || compressed_events_offset > file_len {
+            return Err(SlpError::InvalidFile(InvalidLocation::Metadata))
+        }
+
         let metadata_len = compressed_events_offset - metadata_offset;
         let mut metadata = vec![0u8; metadata_len];
         file.seek(std::io::SeekFrom::Start(metadata_offset as u64))?;
@@ -699,15 +1217,7 @@ pub fn write_notes_to_game(path: &Path, notes: &Notes) -> SlpResult<()> {
 
 // x distance from centre to edge
 fn stage_width(stage: Stage) -> Option<f32> {
-    Some(match stage {
-        Stage::YoshisStory => 56.0,
-        Stage::FountainOfDreams => 63.348,
-        Stage::Battlefield => 68.4,
-        Stage::DreamLandN64 => 77.259,
-        Stage::FinalDestination => 85.554,
-        Stage::PokemonStadium => 87.738,
-        _ => return None,
-    })
+    stage_geometry(stage).map(|g| g.edge_x)
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -977,20 +1487,40 @@ impl fmt::Display for SlpError {
             SlpError::InvalidFile(InvalidLocation::Metadata) => "Slp file is invalid: metadata could not be parsed".to_owned(),
             SlpError::InvalidFile(InvalidLocation::EventSizes) => "Slp file is invalid: Payload Sizes event could not be parsed".to_owned(),
             SlpError::InvalidFile(InvalidLocation::GameStart) => "Slp file is invalid: Game Start event could not be parsed".to_owned(),
+            SlpError::InvalidFile(InvalidLocation::FrameStart) => "Slp file is invalid: Frame Start event could not be parsed".to_owned(),
             SlpError::InvalidFile(InvalidLocation::ItemUpdate) => "Slp file is invalid: Item Update event could not be parsed".to_owned(),
             SlpError::InvalidFile(InvalidLocation::PreFrameUpdate) => "Slp file is invalid: Pre Frame Update event could not be parsed".to_owned(),
             SlpError::InvalidFile(InvalidLocation::PostFrameUpdate) => "Slp file is invalid: Post Frame Update event could not be parsed".to_owned(),
+            SlpError::InvalidFile(InvalidLocation::GameEnd) => "Slp file is invalid: Game End event could not be parsed".to_owned(),
+            SlpError::InvalidFile(InvalidLocation::GeckoList) => "Slp file is invalid: Gecko List could not be parsed".to_owned(),
             SlpError::InvalidFile(InvalidLocation::StadiumTransformation) => "Slp file is invalid: Stadium Transformation event could not be parsed".to_owned(),
+            SlpError::InvalidFile(InvalidLocation::WhispyBlowDirection) => "Slp file is invalid: Whispy Blow Direction event could not be parsed".to_owned(),
+            SlpError::InvalidFile(InvalidLocation::FountainPlatformHeight) => "Slp file is invalid: Fountain Platform Height event could not be parsed".to_owned(),
             SlpError::InvalidFile(InvalidLocation::ParseActionState) => "Slp file is invalid: invalid ActionState event could not be parsed".to_owned(),
             SlpError::InvalidFile(InvalidLocation::EventSlicing) => "Slp file is invalid: invalid event could not be parsed".to_owned(),
             SlpError::TooNewFile => "Slp file is too new and unsupported.".to_owned(),
             SlpError::ZstdInitError => "Failed to init zstd.".to_owned(),
+            SlpError::SlpzCompressionError => "Failed to compress slpz file.".to_owned(),
+            SlpError::InvalidClipRange => "Clip range does not contain any frames of the replay.".to_owned(),
+            SlpError::EventTooLarge => "Event is too large to be written to an slp file.".to_owned(),
             SlpError::FileDoesNotExist => "File does not exist.".to_owned(),
             SlpError::IOError => "Error reading file.".to_owned(),
         })
     }
 }
 
+impl fmt::Display for DetailedSlpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(offset) = self.offset { write!(f, " (offset 0x{:X})", offset)?; }
+        if let Some(command) = self.command { write!(f, " (event 0x{:02X})", command)?; }
+        if let Some(frame) = self.frame { write!(f, " (frame {})", frame)?; }
+        if let Some((major, minor, patch)) = self.version { write!(f, " (version {}.{}.{})", major, minor, patch)?; }
+        if let Some(ref e) = self.io_error { write!(f, ": {}", e)?; }
+        Ok(())
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Vector {
     pub x: f32,
@@ -1115,6 +1645,24 @@ pub enum Direction {
     Right
 }
 
+/// An f32 stored as its bits, so settings read from the Game Start can be compared with `Eq`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+pub struct F32Bits(pub u32);
+
+impl F32Bits {
+    pub fn get(self) -> f32 { f32::from_bits(self.0) }
+}
+
+impl From<f32> for F32Bits {
+    fn from(f: f32) -> F32Bits { F32Bits(f.to_bits()) }
+}
+
+impl std::fmt::Debug for F32Bits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.get())
+    }
+}
+
 // zero if invalid time
 #[derive(Copy, Clone, Debug, PartialOrd, Ord, Eq, PartialEq)]
 pub struct Time(pub u64);
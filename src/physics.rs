@@ -0,0 +1,502 @@
+use crate::*;
+
+// Weight, gravity and terminal velocity are from community attribute tables. `validate_hits` compares gravity
+// with the first frame of each launch, as `Frame::velocity.y` is reset on hit.
+
+/// Launch speed per unit of knockback.
+pub const KNOCKBACK_TO_SPEED: f32 = 0.03;
+
+/// Hitstun frames per unit of knockback.
+pub const HITSTUN_PER_KNOCKBACK: f32 = 0.4;
+
+/// Hitbox angle that depends on the knockback and whether the victim is grounded.
+pub const SAKURAI_ANGLE: u16 = 361;
+
+/// Grounded victims hit by the Sakurai angle with less knockback than this are not launched upwards.
+pub const SAKURAI_ANGLE_KNOCKBACK: f32 = 32.0;
+
+/// Knockback is capped at this value.
+pub const MAX_KNOCKBACK: f32 = 2500.0;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CharacterPhysics {
+    pub weight: f32,
+    /// Fall speed gained each frame.
+    pub gravity: f32,
+    /// Maximum fall speed without fast falling.
+    pub terminal_velocity: f32,
+}
+
+pub fn character_physics(character: Character) -> CharacterPhysics {
+    const fn phys(weight: f32, gravity: f32, terminal_velocity: f32) -> CharacterPhysics {
+        CharacterPhysics { weight, gravity, terminal_velocity }
+    }
+
+    match character {
+        //                                     weight  gravity  terminal velocity
+        Character::Mario                  => phys(100.0, 0.095, 1.7),
+        Character::Fox                    => phys( 75.0, 0.23,  2.8),
+        Character::CaptainFalcon          => phys(104.0, 0.13,  2.9),
+        Character::DonkeyKong             => phys(114.0, 0.1,   2.4),
+        Character::Kirby                  => phys( 70.0, 0.08,  1.6),
+        Character::Bowser                 => phys(117.0, 0.13,  1.9),
+        Character::Link                   => phys(104.0, 0.11,  2.13),
+        Character::Sheik                  => phys( 90.0, 0.12,  2.13),
+        Character::Ness                   => phys( 94.0, 0.09,  1.83),
+        Character::Peach                  => phys( 90.0, 0.08,  1.5),
+        Character::Popo | Character::Nana => phys( 88.0, 0.1,   1.6),
+        Character::Pikachu                => phys( 80.0, 0.11,  1.9),
+        Character::Samus                  => phys(110.0, 0.066, 1.4),
+        Character::Yoshi                  => phys(108.0, 0.093, 1.93),
+        Character::Jigglypuff             => phys( 60.0, 0.064, 1.3),
+        Character::Mewtwo                 => phys( 85.0, 0.082, 1.5),
+        Character::Luigi                  => phys(100.0, 0.069, 1.6),
+        Character::Marth                  => phys( 87.0, 0.085, 2.2),
+        Character::Zelda                  => phys( 90.0, 0.073, 1.4),
+        Character::YoungLink              => phys( 85.0, 0.11,  2.13),
+        Character::DrMario                => phys(100.0, 0.095, 1.7),
+        Character::Falco                  => phys( 80.0, 0.17,  3.1),
+        Character::Pichu                  => phys( 55.0, 0.16,  1.9),
+        Character::MrGameAndWatch         => phys( 60.0, 0.095, 1.7),
+        Character::Ganondorf              => phys(109.0, 0.13,  2.0),
+        Character::Roy                    => phys( 85.0, 0.114, 2.4),
+    }
+}
+
+/// Knockback values of a single hitbox.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hitbox {
+    /// Damage before staling.
+    pub damage: f32,
+    /// Degrees counter-clockwise from forwards, or `SAKURAI_ANGLE`.
+    pub angle: u16,
+    pub base_knockback: f32,
+    pub knockback_growth: f32,
+    /// Weight dependent set knockback. Zero if the hitbox scales with percent.
+    pub set_knockback: f32,
+}
+
+/// Knockback dealt to a victim at `percent` before the hit.
+/// `damage` is the percent the hit dealt, after staling.
+pub fn knockback(hitbox: &Hitbox, percent: f32, damage: f32, weight: f32) -> f32 {
+    // the victim's percent is truncated, and the unstaled damage scales it.
+    // set knockback hits use the formula as if dealing the set knockback at 10%
+    let (p, d) = if hitbox.set_knockback == 0.0 {
+        (percent.floor() + damage, hitbox.damage)
+    } else {
+        (10.0, hitbox.set_knockback)
+    };
+
+    let scaled = (p / 10.0 + p * d / 20.0) * 200.0 / (weight + 100.0) * 1.4 + 18.0;
+    (scaled * hitbox.knockback_growth / 100.0 + hitbox.base_knockback).min(MAX_KNOCKBACK)
+}
+
+pub fn hitstun_frames(knockback: f32) -> u32 {
+    (knockback * HITSTUN_PER_KNOCKBACK) as u32
+}
+
+pub fn launch_speed(knockback: f32) -> f32 {
+    knockback * KNOCKBACK_TO_SPEED
+}
+
+/// Knockback of a launch, from `Frame::hit_velocity` during hitlag.
+pub fn knockback_from_velocity(velocity: Vector) -> f32 {
+    (velocity.x*velocity.x + velocity.y*velocity.y).sqrt() / KNOCKBACK_TO_SPEED
+}
+
+/// Launch angle of a hitbox angle in degrees, before DI.
+pub fn launch_angle(angle: u16, knockback: f32, grounded: bool) -> f32 {
+    match angle {
+        SAKURAI_ANGLE if !grounded => 45.0,
+        SAKURAI_ANGLE if knockback < SAKURAI_ANGLE_KNOCKBACK => 0.0,
+        SAKURAI_ANGLE => 44.0,
+        angle => angle as f32,
+    }
+}
+
+/// Launch angle after DI with the processed stick.
+pub fn apply_di(launch_angle: f32, stick: Vector) -> f32 {
+    (launch_angle + di_angle_offset(launch_angle, stick)).rem_euclid(360.0)
+}
+
+pub fn launch_velocity(speed: f32, angle: f32) -> Vector {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    Vector { x: speed * cos, y: speed * sin }
+}
+
+/// Knockback velocity after one frame of `KNOCKBACK_DECAY`.
+pub fn decay_knockback(velocity: Vector) -> Vector {
+    let speed = (velocity.x*velocity.x + velocity.y*velocity.y).sqrt();
+    if speed <= KNOCKBACK_DECAY { return Vector::NULL }
+    let scale = (speed - KNOCKBACK_DECAY) / speed;
+    Vector { x: velocity.x * scale, y: velocity.y * scale }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LaunchSimulation {
+    /// Position on each frame after hitlag, until the knockback ends, the player lands, or they die.
+    pub positions: Vec<Vector>,
+    pub ko: Option<BlastZone>,
+}
+
+/// Simulates a launch from the position on the last frame of hitlag, with the post DI launch velocity.
+///
+/// The player is assumed to hold no inputs. Platforms, walls and ceilings are ignored,
+/// but landing on the main stage ends the launch.
+pub fn simulate_launch(geometry: &StageGeometry, physics: CharacterPhysics, position: Vector, launch: Vector) -> LaunchSimulation {
+    let mut positions = Vec::new();
+    let mut position = position;
+    let mut knockback = launch;
+    let mut fall_speed = 0.0f32;
+
+    loop {
+        knockback = decay_knockback(knockback);
+        fall_speed = (fall_speed + physics.gravity).min(physics.terminal_velocity);
+
+        let prev = position;
+        position.x += knockback.x;
+        position.y += knockback.y - fall_speed;
+        positions.push(position);
+
+        let ko = if position.y > geometry.blast_zone_top {
+            Some(BlastZone::Top)
+        } else if position.y < geometry.blast_zone_bottom {
+            Some(BlastZone::Bottom)
+        } else if position.x > geometry.blast_zone_x {
+            Some(BlastZone::Right)
+        } else if position.x < -geometry.blast_zone_x {
+            Some(BlastZone::Left)
+        } else {
+            None
+        };
+        if ko.is_some() { return LaunchSimulation { positions, ko } }
+
+        let landed = position.x.abs() <= geometry.edge_x && prev.y >= geometry.ground_y && position.y < geometry.ground_y;
+        if landed || knockback == Vector::NULL { return LaunchSimulation { positions, ko: None } }
+    }
+}
+
+/// Whether a hit would have killed with different DI.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DiKillCheck {
+    /// The simulated launch with the DI used kills.
+    pub kills: bool,
+    pub kills_without_di: bool,
+    /// Some DI angle survives.
+    pub survivable: bool,
+    /// Some DI angle kills.
+    pub killable: bool,
+    /// DI angle that travels the least far towards the blast zones, or dies the latest.
+    pub best_di_angle: f32,
+    /// Where the player actually died, if before getting hit again.
+    pub died: Option<BlastZone>,
+}
+
+/// Simulates a hit from `hit_di` with every DI angle.
+/// Returns None if the stage has no geometry.
+///
+/// Launches are simulated without any inputs after hitlag, so drifting towards the stage can survive hits that kill here.
+pub fn di_kill_check(game: &Game, hit: &HitDi) -> Option<DiKillCheck> {
+    let geometry = stage_geometry(game.info.stage)?;
+    let frames = game.frames.get(hit.victim as usize)?.as_deref()?;
+    let last = frames.get(hit.frame_idx + hit.hitlag_frames as usize - 1)?;
+    let physics = character_physics(last.character);
+
+    let simulate = |angle| simulate_launch(&geometry, physics, last.position, launch_velocity(hit.launch_speed, angle));
+    // larger is safer: frames until death, or how far the launch stayed inside the blast zones
+    let safety = |sim: &LaunchSimulation| match sim.ko {
+        Some(_) => -(1000.0 - sim.positions.len() as f32),
+        None => {
+            let end = sim.positions.last().copied().unwrap_or(last.position);
+            (geometry.blast_zone_x - end.x.abs())
+                .min(geometry.blast_zone_top - end.y)
+                .min(end.y - geometry.blast_zone_bottom)
+        }
+    };
+
+    let mut survivable = false;
+    let mut killable = false;
+    let mut best = (f32::MIN, hit.launch_angle);
+    for step in 0..=36 {
+        let angle = (hit.launch_angle - DI_MAX_ANGLE + step as f32).rem_euclid(360.0);
+        let sim = simulate(angle);
+        survivable |= sim.ko.is_none();
+        killable |= sim.ko.is_some();
+        let safety = safety(&sim);
+        if safety > best.0 { best = (safety, angle); }
+    }
+
+    let died = frames[hit.frame_idx + hit.hitlag_frames as usize..]
+        .iter()
+        .find(|f| f.percent > last.percent || BlastZone::from_state(f.state).is_some())
+        .and_then(|f| BlastZone::from_state(f.state));
+
+    Some(DiKillCheck {
+        kills: simulate(hit.di_angle).ko.is_some(),
+        kills_without_di: simulate(hit.launch_angle).ko.is_some(),
+        survivable,
+        killable,
+        best_di_angle: best.1,
+        died,
+    })
+}
+
+/// Physics predicted for a hit, compared with what the parser read from the replay.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HitValidation {
+    pub victim: u8,
+    /// Index of the first frame of hitlag.
+    pub frame_idx: usize,
+    pub attacker: Character,
+    /// `Frame::last_hitting_attack_id` of the attacker.
+    pub attack: AttackKind,
+    /// Percent before the hit.
+    pub percent: f32,
+    /// Percent dealt by the hit, after staling.
+    pub damage: f32,
+    /// From the hitbox passed to `validate_hits`. None if there was no hitbox for the move.
+    pub predicted_knockback: Option<f32>,
+    /// From `Frame::hit_velocity` during hitlag.
+    pub knockback: f32,
+    /// From `predicted_knockback`.
+    pub predicted_hitstun: Option<u32>,
+    /// `Frame::hitstun_misc` on the last frame of hitlag.
+    pub hitstun: u32,
+    /// Launch velocity from `predicted_knockback` and the hitbox angle, after DI and one frame of decay.
+    pub predicted_velocity: Option<Vector>,
+    /// `Frame::hit_velocity` on the first frame after hitlag.
+    pub velocity: Vector,
+    /// Gravity of the victim from `character_physics`.
+    pub predicted_fall_speed: f32,
+    /// Fall speed on the first frame after hitlag. None if the victim was grounded.
+    pub fall_speed: Option<f32>,
+}
+
+impl HitValidation {
+    pub fn knockback_error(&self) -> Option<f32> {
+        self.predicted_knockback.map(|kb| (kb - self.knockback).abs())
+    }
+
+    pub fn hitstun_matches(&self) -> Option<bool> {
+        self.predicted_hitstun.map(|hitstun| hitstun == self.hitstun)
+    }
+
+    /// Distance between the predicted and parsed launch velocity.
+    pub fn velocity_error(&self) -> Option<f32> {
+        let predicted = self.predicted_velocity?;
+        let x = predicted.x - self.velocity.x;
+        let y = predicted.y - self.velocity.y;
+        Some((x*x + y*y).sqrt())
+    }
+
+    pub fn fall_speed_error(&self) -> Option<f32> {
+        self.fall_speed.map(|fall_speed| (fall_speed - self.predicted_fall_speed).abs())
+    }
+}
+
+/// Predicts the knockback, hitstun and launch velocity of every hit from `hit_di`,
+/// from the attacker's move, the victim's percent and the damage dealt.
+/// Returns an empty list if the port is unused.
+///
+/// This crate has no hitbox data, so `hitbox` looks up the hitbox of the attacker's move from `Frame::last_hitting_attack_id`.
+/// Predictions are None where it returns None.
+/// Moves with several hitboxes will only match hits from the hitbox that was returned.
+pub fn validate_hits(
+    game: &Game,
+    port: usize,
+    hitbox: impl Fn(Character, AttackKind) -> Option<Hitbox>,
+) -> Vec<HitValidation> {
+    let Some(frames) = game.frames.get(port).and_then(|f| f.as_deref()) else { return Vec::new() };
+    let physics = |character| character_physics(character);
+
+    hit_di(game, port)
+        .iter()
+        .filter_map(|hit| {
+            let end = hit.frame_idx + hit.hitlag_frames as usize;
+            let start = &frames[hit.frame_idx];
+            let last = &frames[end - 1];
+            let after = frames.get(end)?;
+            let percent = frames.get(hit.frame_idx.checked_sub(1)?)?.percent;
+            let damage = start.percent - percent;
+
+            let attacker = game.frames.get(hit.attacker? as usize)?.as_deref()?.get(hit.frame_idx)?;
+            let attack = attacker.last_hitting_attack_id;
+            let victim = physics(start.character);
+
+            let predicted_knockback = hitbox(attacker.character, attack)
+                .map(|hitbox| (hitbox, knockback(&hitbox, percent, damage, victim.weight)));
+            let predicted_velocity = predicted_knockback.map(|(hitbox, kb)| {
+                let angle = launch_angle(hitbox.angle, kb, !start.is_airborne);
+                let angle = if attacker.direction == Direction::Left { 180.0 - angle } else { angle };
+                let launch = decay_knockback(launch_velocity(launch_speed(kb), apply_di(angle, last.left_stick_coords)));
+                // grounded launches angled downwards are flattened
+                if !last.is_airborne && launch.y < 0.0 { Vector { x: launch.x, y: 0.0 } } else { launch }
+            });
+            let predicted_knockback = predicted_knockback.map(|(_, kb)| kb);
+
+            Some(HitValidation {
+                victim: hit.victim,
+                frame_idx: hit.frame_idx,
+                attacker: attacker.character,
+                attack,
+                percent,
+                damage,
+                predicted_knockback,
+                knockback: hit.launch_speed / KNOCKBACK_TO_SPEED,
+                predicted_hitstun: predicted_knockback.map(hitstun_frames),
+                hitstun: last.hitstun_misc as u32,
+                predicted_velocity,
+                velocity: after.hit_velocity,
+                predicted_fall_speed: victim.gravity,
+                fall_speed: after.is_airborne.then_some(-after.velocity.y),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+const fn hitbox(damage: f32, angle: u16, base_knockback: f32, knockback_growth: f32, set_knockback: f32) -> Hitbox {
+    Hitbox { damage, angle, base_knockback, knockback_growth, set_knockback }
+}
+
+// Not the game's values: base knockback and growth are fitted to hits in the test replay,
+// with the damage and angle of the strongest hitbox.
+#[cfg(test)]
+fn fitted_hitbox(character: Character, attack: AttackKind) -> Option<Hitbox> {
+    match (character, attack) {
+        //                                                         damage angle          base     growth   set
+        (Character::MrGameAndWatch, AttackKind::FTilt)  => Some(hitbox(13.0, SAKURAI_ANGLE, 31.0909, 93.9394, 0.0)),
+        (Character::Pichu,          AttackKind::NAir)   => Some(hitbox(12.0, SAKURAI_ANGLE, 18.5455, 96.9697, 0.0)),
+        (Character::Pichu,          AttackKind::DSmash) => Some(hitbox(16.0, 30,            10.3818, 67.8788, 0.0)),
+        _ => None,
+    }
+}
+
+#[test]
+fn knockback_and_hitstun() {
+    let fox = character_physics(Character::Fox);
+    let hitbox = hitbox(10.0, SAKURAI_ANGLE, 20.0, 100.0, 0.0);
+    let kb = knockback(&hitbox, 0.0, 10.0, fox.weight);
+    assert!((kb - 47.6).abs() < 0.01);
+    assert_eq!(hitstun_frames(kb), 19);
+
+    let set = Hitbox { set_knockback: 10.0, ..hitbox };
+    assert_eq!(knockback(&set, 0.0, 10.0, fox.weight), knockback(&set, 150.0, 10.0, fox.weight));
+}
+
+#[test]
+fn knockback_matches_replay_hits() {
+    // Mr. Game & Watch ftilt on Pichu: (percent before, damage after staling, knockback read from the replay)
+    let hits = [
+        (12.0,   13.0,  79.82),
+        (38.6,   13.0,  112.91),
+        (71.55,  13.0,  154.91),
+        (85.55,  11.83, 171.24),
+        (109.35, 12.35, 202.45),
+    ];
+    let ftilt = fitted_hitbox(Character::MrGameAndWatch, AttackKind::FTilt).unwrap();
+    let pichu = character_physics(Character::Pichu);
+    for (percent, damage, kb) in hits {
+        assert!((knockback(&ftilt, percent, damage, pichu.weight) - kb).abs() < 0.05, "{percent}% + {damage}");
+    }
+}
+
+#[test]
+fn launch_angles_and_di() {
+    assert_eq!(launch_angle(SAKURAI_ANGLE, 20.0, true), 0.0);
+    assert_eq!(launch_angle(SAKURAI_ANGLE, 47.6, true), 44.0);
+    assert_eq!(launch_angle(SAKURAI_ANGLE, 47.6, false), 45.0);
+    assert!((apply_di(0.0, Vector { x: 0.0, y: 1.0 }) - 18.0).abs() < 0.01);
+}
+
+#[test]
+fn launch_simulation() {
+    let fox = character_physics(Character::Fox);
+    let bf = stage_geometry(Stage::Battlefield).unwrap();
+    let strong = simulate_launch(&bf, fox, Vector { x: 150.0, y: 50.0 }, launch_velocity(4.0, 0.0));
+    assert_eq!(strong.ko, Some(BlastZone::Right));
+    let weak = simulate_launch(&bf, fox, Vector { x: 0.0, y: 50.0 }, launch_velocity(1.0, 0.0));
+    assert_eq!(weak.ko, None);
+    assert!(weak.positions.last().unwrap().y < 50.0);
+}
+
+#[cfg(test)]
+fn launched_game(hitlag_velocity: Vector, stick: Vector, launch: Vector) -> Game {
+    // Mr. Game & Watch ftilts an airborne Pichu at 12% on frame 100
+    let mut game = parse_file(&test_replay()).unwrap();
+    let mut attacker = test_frames(&game, 0, 300);
+    for f in attacker.iter_mut() {
+        f.character = Character::MrGameAndWatch;
+        f.last_hitting_attack_id = AttackKind::FTilt;
+    }
+    game.frames[0] = Some(attacker.into_boxed_slice());
+
+    let mut frames = test_frames(&game, 1, 300);
+    for f in frames.iter_mut() {
+        f.character = Character::Pichu;
+        f.position = Vector { x: 150.0, y: 50.0 };
+        f.is_airborne = true;
+        f.percent = 12.0;
+        f.last_hit_by = 0;
+    }
+    for (k, f) in frames[100..104].iter_mut().enumerate() {
+        f.hitlag_frames = (4 - k) as f32;
+        f.hit_velocity = hitlag_velocity;
+        f.hitstun_misc = 31.0;
+    }
+    for f in frames[100..].iter_mut() { f.percent = 25.0; }
+    frames[103].left_stick_coords = stick;
+    frames[104].hit_velocity = launch;
+    frames[104].velocity.y = -0.16;
+    for f in frames[200..].iter_mut() { f.state = ActionState::Standard(StandardActionState::DeadRight); }
+    game.frames[1] = Some(frames.into_boxed_slice());
+    game
+}
+
+#[test]
+fn validate_hits_with_hitbox() {
+    let ftilt = fitted_hitbox(Character::MrGameAndWatch, AttackKind::FTilt).unwrap();
+    let kb = knockback(&ftilt, 12.0, 13.0, character_physics(Character::Pichu).weight);
+    let speed = launch_speed(kb);
+    let game = launched_game(launch_velocity(speed, 45.0), Vector::NULL, decay_knockback(launch_velocity(speed, 45.0)));
+
+    let checks = validate_hits(&game, 1, fitted_hitbox);
+    assert_eq!(checks.len(), 1);
+    let check = checks[0];
+    assert_eq!((check.attacker, check.attack), (Character::MrGameAndWatch, AttackKind::FTilt));
+    assert_eq!((check.percent, check.damage), (12.0, 13.0));
+    assert!(check.knockback_error().unwrap() < 0.01);
+    assert_eq!(check.predicted_hitstun, Some(31));
+    assert_eq!(check.hitstun_matches(), Some(true));
+    assert!(check.velocity_error().unwrap() < 0.001);
+    assert!(check.fall_speed_error().unwrap() < 0.001);
+
+    // a launch that doesn't match the hitbox is caught
+    let game = launched_game(launch_velocity(speed, 45.0), Vector::NULL, decay_knockback(launch_velocity(speed, 80.0)));
+    assert!(validate_hits(&game, 1, fitted_hitbox)[0].velocity_error().unwrap() > 1.0);
+    let game = launched_game(launch_velocity(speed * 1.5, 45.0), Vector::NULL, Vector::NULL);
+    assert!(validate_hits(&game, 1, fitted_hitbox)[0].knockback_error().unwrap() > 10.0);
+}
+
+#[test]
+fn validate_hits_without_hitbox() {
+    let mut game = launched_game(launch_velocity(2.0, 45.0), Vector::NULL, Vector::NULL);
+    let mut attacker = game.frames[0].as_ref().unwrap().to_vec();
+    for f in attacker.iter_mut() { f.last_hitting_attack_id = AttackKind::Jab1; }
+    game.frames[0] = Some(attacker.into_boxed_slice());
+
+    let check = validate_hits(&game, 1, fitted_hitbox)[0];
+    assert_eq!((check.predicted_knockback, check.predicted_hitstun, check.predicted_velocity), (None, None, None));
+    assert_eq!(check.knockback_error(), None);
+    assert!(validate_hits(&game, 3, fitted_hitbox).is_empty());
+}
+
+#[test]
+fn di_kill_check_at_the_edge() {
+    // a hit straight right at the edge with DI up, then the player dies
+    let game = launched_game(Vector { x: 3.0, y: 0.0 }, Vector { x: 0.0, y: 1.0 }, decay_knockback(launch_velocity(3.0, 18.0)));
+    let hit = hit_di(&game, 1)[0];
+    let check = di_kill_check(&game, &hit).unwrap();
+    assert!(check.kills && check.kills_without_di && check.killable && !check.survivable);
+    assert_eq!(check.died, Some(BlastZone::Right));
+}
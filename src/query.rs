@@ -0,0 +1,397 @@
+use crate::*;
+
+/// A filter over `GameInfo`. Every field left empty or None matches everything.
+///
+/// Can be built directly, or parsed from a filter string with `ReplayQuery::parse`:
+/// `char:fox vs:marth,sheik stage:bf after:2024-01-01 min:1m30s singles`
+#[derive(Clone, Debug, Default)]
+pub struct ReplayQuery {
+    /// Any of these characters must be on one side...
+    pub characters: Vec<Character>,
+    /// ...and any of these on the other side.
+    pub opponents: Vec<Character>,
+
+    /// Connect code or name of a player on the `characters` side.
+    /// Case insensitive and confusable insensitive. Names match on substrings, connect codes must match exactly.
+    ///
+    /// Compared as is against the cured names and codes of each replay. `parse` stores it cured,
+    /// use `cure_player` when building a query directly so confusables in the query also match.
+    pub player: Option<String>,
+    /// Connect code or name of a player on the `opponents` side.
+    pub opponent: Option<String>,
+
+    /// Any of these stages.
+    pub stages: Vec<Stage>,
+
+    /// Inclusive. Replays without a start time never match a date filter.
+    pub after: Option<Time>,
+    /// Exclusive.
+    pub before: Option<Time>,
+
+    /// In frames, compared against `GameInfo::duration`.
+    pub min_duration: Option<i32>,
+
+    pub is_teams: Option<bool>,
+    pub has_notes: Option<bool>,
+
+    /// Inclusive.
+    pub min_version: Option<(u8, u8, u8)>,
+    /// Inclusive.
+    pub max_version: Option<(u8, u8, u8)>,
+
+    pub sort: ReplaySort,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ReplaySort {
+    #[default]
+    Newest,
+    Oldest,
+    Longest,
+    Shortest,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QueryError {
+    /// A word that is not a filter, or a filter with an unknown key.
+    UnknownFilter(String),
+    /// A known filter whose value could not be parsed.
+    InvalidValue(String),
+    UnterminatedQuote,
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::UnknownFilter(term) => write!(f, "unknown filter '{}'", term),
+            QueryError::InvalidValue(term) => write!(f, "invalid value in '{}'", term),
+            QueryError::UnterminatedQuote => write!(f, "unterminated quote"),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl AsRef<GameInfo> for GameInfo {
+    fn as_ref(&self) -> &GameInfo { self }
+}
+
+impl AsRef<GameInfo> for Game {
+    fn as_ref(&self) -> &GameInfo { &self.info }
+}
+
+impl AsRef<GameInfo> for SlpFileInfo {
+    fn as_ref(&self) -> &GameInfo { &self.info }
+}
+
+impl AsRef<GameInfo> for LibraryReplay {
+    fn as_ref(&self) -> &GameInfo { &self.info }
+}
+
+impl ReplayQuery {
+    pub fn matches(&self, info: &GameInfo) -> bool {
+        if !self.stages.is_empty() && !self.stages.contains(&info.stage) { return false }
+
+        if self.after.is_some() || self.before.is_some() {
+            if info.start_time == Time::NULL { return false }
+            if self.after.is_some_and(|after| info.start_time < after) { return false }
+            if self.before.is_some_and(|before| info.start_time >= before) { return false }
+        }
+
+        if self.min_duration.is_some_and(|min| info.duration < min) { return false }
+        if self.is_teams.is_some_and(|teams| info.is_teams != teams) { return false }
+        if self.has_notes.is_some_and(|notes| info.has_notes != notes) { return false }
+
+        let version = (info.version_major, info.version_minor, info.version_patch);
+        if self.min_version.is_some_and(|min| version < min) { return false }
+        if self.max_version.is_some_and(|max| version > max) { return false }
+
+        self.sides_match(info)
+    }
+
+    fn sides_match(&self, info: &GameInfo) -> bool {
+        let filter_side = !self.characters.is_empty() || self.player.is_some();
+        let filter_other_side = !self.opponents.is_empty() || self.opponent.is_some();
+        if !filter_side && !filter_other_side { return true }
+
+        let mut name_buf = String::new();
+        let names = info.names(&mut name_buf);
+        let mut code_buf = String::new();
+        let codes = info.connect_codes(&mut code_buf);
+
+        let port_matches = |port: usize, characters: &[Character], player: &Option<String>| -> bool {
+            let Some(character_colour) = info.starting_character_colours[port] else { return false };
+            if !characters.is_empty() && !characters.contains(&character_colour.character()) { return false }
+            match player {
+                Some(player) => player_matches(player, names[port], codes[port]),
+                None => true,
+            }
+        };
+
+        (0..4).any(|port| {
+            port_matches(port, &self.characters, &self.player)
+                && (!filter_other_side || (0..4).any(|other| {
+                    info.teams[other] != info.teams[port]
+                        && port_matches(other, &self.opponents, &self.opponent)
+                }))
+        })
+    }
+
+    /// Returns every matching replay, sorted by `self.sort`.
+    pub fn run<'a, T: AsRef<GameInfo>>(&self, replays: &'a [T]) -> Vec<&'a T> {
+        let mut results: Vec<&T> = replays.iter().filter(|r| self.matches(r.as_ref())).collect();
+
+        match self.sort {
+            ReplaySort::Newest   => results.sort_by_key(|r| std::cmp::Reverse(r.as_ref().start_time)),
+            ReplaySort::Oldest   => results.sort_by_key(|r| r.as_ref().start_time),
+            ReplaySort::Longest  => results.sort_by_key(|r| std::cmp::Reverse(r.as_ref().duration)),
+            ReplaySort::Shortest => results.sort_by_key(|r| r.as_ref().duration),
+        }
+
+        results
+    }
+
+    /// Same as `run`, but only returns the results on the page (zero indexed).
+    pub fn run_page<'a, T: AsRef<GameInfo>>(&self, replays: &'a [T], page: usize, page_size: usize) -> Vec<&'a T> {
+        let mut results = self.run(replays);
+        let start = page.saturating_mul(page_size).min(results.len());
+        let end = start.saturating_add(page_size).min(results.len());
+        results.truncate(end);
+        results.drain(..start);
+        results
+    }
+
+    /// Parses a space separated list of filters. Values with spaces can be quoted.
+    /// Lists are comma separated.
+    ///
+    /// - `char:fox,falco` `vs:marth`
+    /// - `player:abc#123` `opponent:"some name"`
+    /// - `stage:bf,fd`
+    /// - `after:2024-01-01` `before:2024-06` (YYYY, YYYY-MM or YYYY-MM-DD)
+    /// - `min:90` (seconds) `min:1m30s`
+    /// - `teams` `singles` `notes`
+    /// - `minversion:3.14` `maxversion:3.16.0`
+    /// - `sort:newest`, `oldest`, `longest` or `shortest`
+    pub fn parse(query: &str) -> Result<ReplayQuery, QueryError> {
+        let mut q = ReplayQuery::default();
+
+        for term in split_terms(query)? {
+            let invalid = || QueryError::InvalidValue(term.clone());
+
+            let Some((key, value)) = term.split_once(':') else {
+                match term.to_ascii_lowercase().as_str() {
+                    "teams"   => q.is_teams = Some(true),
+                    "singles" => q.is_teams = Some(false),
+                    "notes"   => q.has_notes = Some(true),
+                    _ => return Err(QueryError::UnknownFilter(term)),
+                }
+                continue;
+            };
+
+            match key.to_ascii_lowercase().as_str() {
+                "char" | "character" => q.characters = parse_list(value, character_from_name).ok_or_else(invalid)?,
+                "vs"                 => q.opponents = parse_list(value, character_from_name).ok_or_else(invalid)?,
+                "stage"              => q.stages = parse_list(value, stage_from_name).ok_or_else(invalid)?,
+                "player"             => q.player = Some(cure_player(value).ok_or_else(invalid)?),
+                "opponent"           => q.opponent = Some(cure_player(value).ok_or_else(invalid)?),
+                "after"              => q.after = Some(parse_date(value).ok_or_else(invalid)?),
+                "before"             => q.before = Some(parse_date(value).ok_or_else(invalid)?),
+                "min"                => q.min_duration = Some(parse_duration(value).ok_or_else(invalid)?),
+                "minversion"         => q.min_version = Some(parse_version(value).ok_or_else(invalid)?),
+                "maxversion"         => q.max_version = Some(parse_version(value).ok_or_else(invalid)?),
+                "sort" => q.sort = match value.to_ascii_lowercase().as_str() {
+                    "newest"   => ReplaySort::Newest,
+                    "oldest"   => ReplaySort::Oldest,
+                    "longest"  => ReplaySort::Longest,
+                    "shortest" => ReplaySort::Shortest,
+                    _ => return Err(invalid()),
+                },
+                _ => return Err(QueryError::UnknownFilter(term)),
+            }
+        }
+
+        Ok(q)
+    }
+}
+
+fn cure_options() -> decancer::Options {
+    decancer::Options::default().retain_japanese()
+}
+
+/// Removes confusables and lowercases a name or connect code for `ReplayQuery::player` and `ReplayQuery::opponent`.
+/// Returns None if it could not be cured.
+pub fn cure_player(player: &str) -> Option<String> {
+    decancer::cure(player, cure_options()).ok().map(String::from)
+}
+
+// `player` is already cured, only the replay's name and code are cured here
+fn player_matches(player: &str, name: &str, code: &str) -> bool {
+    if player.is_empty() { return false }
+
+    let code_matches = decancer::cure(code, cure_options()).is_ok_and(|code| code == player);
+    let name_matches = decancer::cure(name, cure_options()).is_ok_and(|name| name.contains(player));
+    code_matches || name_matches
+}
+
+/// Splits on whitespace, keeping quoted sections together and removing the quotes.
+fn split_terms(query: &str) -> Result<Vec<String>, QueryError> {
+    let mut terms = Vec::new();
+    let mut term = String::new();
+    let mut in_quotes = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !term.is_empty() { terms.push(std::mem::take(&mut term)); }
+            }
+            c => term.push(c),
+        }
+    }
+
+    if in_quotes { return Err(QueryError::UnterminatedQuote) }
+    if !term.is_empty() { terms.push(term); }
+    Ok(terms)
+}
+
+fn parse_list<T>(value: &str, parse: fn(&str) -> Option<T>) -> Option<Vec<T>> {
+    value.split(',').map(parse).collect()
+}
+
+/// Lowercase, with spaces and punctuation removed.
+fn simplify(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+pub fn character_from_name(name: &str) -> Option<Character> {
+    const ALIASES: &[(&str, Character)] = &[
+        ("falcon",        Character::CaptainFalcon),
+        ("cf",            Character::CaptainFalcon),
+        ("dk",            Character::DonkeyKong),
+        ("ics",           Character::Popo),
+        ("ic",            Character::Popo),
+        ("iceclimbers",   Character::Popo),
+        ("puff",          Character::Jigglypuff),
+        ("jiggs",         Character::Jigglypuff),
+        ("m2",            Character::Mewtwo),
+        ("yl",            Character::YoungLink),
+        ("doc",           Character::DrMario),
+        ("gnw",           Character::MrGameAndWatch),
+        ("gw",            Character::MrGameAndWatch),
+        ("mrgameandwatch",Character::MrGameAndWatch),
+        ("ganon",         Character::Ganondorf),
+        ("pika",          Character::Pikachu),
+    ];
+
+    let name = simplify(name);
+    if let Some((_, c)) = ALIASES.iter().find(|(alias, _)| *alias == name) { return Some(*c) }
+    Character::AS_LIST.iter().copied().find(|c| simplify(&c.to_string()) == name)
+}
+
+pub fn stage_from_name(name: &str) -> Option<Stage> {
+    const ALIASES: &[(&str, Stage)] = &[
+        ("bf",        Stage::Battlefield),
+        ("fd",        Stage::FinalDestination),
+        ("ps",        Stage::PokemonStadium),
+        ("stadium",   Stage::PokemonStadium),
+        ("ys",        Stage::YoshisStory),
+        ("yoshis",    Stage::YoshisStory),
+        ("fod",       Stage::FountainOfDreams),
+        ("fountain",  Stage::FountainOfDreams),
+        ("dl",        Stage::DreamLandN64),
+        ("dreamland", Stage::DreamLandN64),
+    ];
+
+    let name = simplify(name);
+    if let Some((_, s)) = ALIASES.iter().find(|(alias, _)| *alias == name) { return Some(*s) }
+    (0..=u8::MAX as u16)
+        .filter_map(Stage::from_u16)
+        .find(|s| simplify(s.as_str()) == name)
+}
+
+/// YYYY, YYYY-MM or YYYY-MM-DD.
+fn parse_date(date: &str) -> Option<Time> {
+    let mut parts = date.split('-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next().map_or(Some(1), |m| m.parse().ok())?;
+    let day = parts.next().map_or(Some(1), |d| d.parse().ok())?;
+    if parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) { return None }
+
+    Some(Time::from(TimeFields { year, month, day, hour: 0, minute: 0, second: 0 }))
+}
+
+/// Seconds, or minutes and seconds as `1m30s`. Returns frames.
+fn parse_duration(duration: &str) -> Option<i32> {
+    if let Ok(seconds) = duration.parse::<i32>() { return seconds.checked_mul(60) }
+
+    let (minutes, seconds) = match duration.split_once('m') {
+        Some((minutes, seconds)) => (minutes.parse::<i32>().ok()?, seconds),
+        None => (0, duration),
+    };
+    let seconds = match seconds.strip_suffix('s') {
+        Some(seconds) => seconds.parse::<i32>().ok()?,
+        None if seconds.is_empty() => 0,
+        None => return None,
+    };
+
+    minutes.checked_mul(60)?.checked_add(seconds)?.checked_mul(60)
+}
+
+/// major.minor or major.minor.patch
+fn parse_version(version: &str) -> Option<(u8, u8, u8)> {
+    let mut parts = version.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = parts.next().map_or(Some(0), |p| p.parse().ok())?;
+    if parts.next().is_some() { return None }
+    Some((major, minor, patch))
+}
+
+#[test]
+fn replay_query_parse_and_match() {
+    let info = parse_file(&test_replay()).unwrap().info;
+
+    let query = ReplayQuery::parse("char:fox vs:Marth,sheik stage:bf singles minversion:3.7").unwrap();
+    assert_eq!(query.characters, vec![Character::Fox]);
+    assert_eq!(query.opponents, vec![Character::Marth, Character::Sheik]);
+    assert_eq!(query.stages, vec![Stage::Battlefield]);
+    assert!(query.matches(&info));
+
+    // sides are not interchangeable
+    assert!(ReplayQuery::parse("char:marth vs:fox").unwrap().matches(&info));
+    assert!(!ReplayQuery::parse("char:fox vs:fox").unwrap().matches(&info));
+    assert!(!ReplayQuery::parse("stage:fd").unwrap().matches(&info));
+    assert!(!ReplayQuery::parse("teams").unwrap().matches(&info));
+    assert!(!ReplayQuery::parse("maxversion:3.6.9").unwrap().matches(&info));
+    assert!(!ReplayQuery::parse("player:\"some name\"").unwrap().matches(&info));
+
+    // no start time in the metadata
+    assert!(!ReplayQuery::parse("after:2024-01-01").unwrap().matches(&info));
+
+    let query = ReplayQuery::parse("after:2024-01 min:1m30s sort:longest").unwrap();
+    assert_eq!(query.after, Some(Time::from(TimeFields { year: 2024, month: 1, day: 1, hour: 0, minute: 0, second: 0 })));
+    assert_eq!(query.min_duration, Some(90 * 60));
+    assert_eq!(query.sort, ReplaySort::Longest);
+
+    assert_eq!(ReplayQuery::parse("char:wario").unwrap_err(), QueryError::InvalidValue("char:wario".to_string()));
+    assert_eq!(ReplayQuery::parse("fox").unwrap_err(), QueryError::UnknownFilter("fox".to_string()));
+    assert_eq!(ReplayQuery::parse("player:\"abc").unwrap_err(), QueryError::UnterminatedQuote);
+
+    let mut codes = [[0u8; 10]; 4];
+    codes[1][..7].copy_from_slice(b"ABC#123");
+    let infos = [
+        GameInfo { duration: 100, start_time: Time(1), ..info },
+        GameInfo { duration: 300, start_time: Time(3), connect_codes: codes, ..info },
+        GameInfo { duration: 200, start_time: Time(2), ..info },
+    ];
+    let durations = |q: &str, page: usize| -> Vec<i32> {
+        ReplayQuery::parse(q).unwrap().run_page(&infos, page, 2).iter().map(|i| i.duration).collect()
+    };
+    assert_eq!(durations("", 0), vec![300, 200]);
+    assert_eq!(durations("", 1), vec![100]);
+    assert_eq!(durations("sort:shortest", 0), vec![100, 200]);
+    assert_eq!(durations("opponent:abc#123", 0), vec![300]);
+    assert_eq!(durations("player:ABC＃123", 0), vec![300]);
+    assert_eq!(ReplayQuery::parse("player:ABC＃123").unwrap().player.as_deref(), Some("abc#123"));
+    assert_eq!(durations("player:abc#12", 0), Vec::<i32>::new());
+}
@@ -0,0 +1,456 @@
+use crate::*;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const CACHE_MAGIC: &[u8; 6] = b"slplib";
+
+// Increment when the cache layout or GameInfo changes. Outdated caches are discarded and rebuilt.
+const CACHE_VERSION: u8 = 2;
+
+/// `GameInfo` for every replay in a directory tree, with a persistent cache so
+/// only new or changed files are parsed on each scan.
+#[derive(Clone, Debug, Default)]
+pub struct ReplayLibrary {
+    /// Sorted by path. Contains slpz files as well as slp files.
+    pub replays: Vec<LibraryReplay>,
+
+    /// Files and folders that could not be read in the last scan, sorted by path.
+    /// These are not cached, so they are retried every scan.
+    pub failures: Vec<(PathBuf, SlpError)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LibraryReplay {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+    pub size: u64,
+    pub info: GameInfo,
+}
+
+struct FileStat {
+    path: PathBuf,
+    modified: SystemTime,
+    size: u64,
+}
+
+impl ReplayLibrary {
+    pub fn new() -> Self { Self::default() }
+
+    /// Loads a cache written by `save`.
+    /// An empty library is returned if the cache doesn't exist, is outdated, or is corrupt.
+    pub fn load(cache_path: impl AsRef<Path>) -> SlpResult<Self> {
+        let bytes = match std::fs::read(cache_path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(read_cache(&bytes).unwrap_or_default())
+    }
+
+    /// Writes the cache to a temporary file first, so an interrupted save won't corrupt an existing cache.
+    pub fn save(&self, cache_path: impl AsRef<Path>) -> SlpResult<()> {
+        let cache_path = cache_path.as_ref();
+        let tmp_path = cache_path.with_extension("tmp");
+        std::fs::write(&tmp_path, write_cache(self))?;
+        std::fs::rename(&tmp_path, cache_path)?;
+        Ok(())
+    }
+
+    /// Recursively finds every slp and slpz file in the directory, using all available threads.
+    /// Only files that are new, or whose modified time or size has changed, are parsed.
+    /// Replays that are no longer in the directory are removed.
+    ///
+    /// Returns the number of files parsed.
+    pub fn scan(&mut self, root: impl AsRef<Path>) -> SlpResult<usize> {
+        let thread_count = std::thread::available_parallelism().map_or(1, |n| n.get());
+        self.scan_with_threads(root, thread_count)
+    }
+
+    pub fn scan_with_threads(&mut self, root: impl AsRef<Path>, thread_count: usize) -> SlpResult<usize> {
+        self.failures.clear();
+
+        let mut files = Vec::new();
+        walk_dir(root.as_ref(), &mut files, &mut self.failures)?;
+
+        let mut cached: std::collections::HashMap<PathBuf, LibraryReplay> = std::mem::take(&mut self.replays)
+            .into_iter()
+            .map(|replay| (replay.path.clone(), replay))
+            .collect();
+
+        let mut changed = Vec::new();
+        for file in files {
+            match cached.remove(&file.path) {
+                Some(replay) if replay.modified == file.modified && replay.size == file.size => self.replays.push(replay),
+                _ => changed.push(file),
+            }
+        }
+
+        for (i, result) in read_infos(&changed, thread_count) {
+            let FileStat { ref path, modified, size } = changed[i];
+            match result {
+                Ok(info) => self.replays.push(LibraryReplay { path: path.clone(), modified, size, info }),
+                Err(e) => self.failures.push((path.clone(), e)),
+            }
+        }
+
+        self.replays.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        self.failures.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(changed.len())
+    }
+}
+
+/// Errors reading the root are returned, errors reading subfolders are added to `failures`.
+/// Symlinks are not followed.
+fn walk_dir(root: &Path, files: &mut Vec<FileStat>, failures: &mut Vec<(PathBuf, SlpError)>) -> SlpResult<()> {
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if dir == root => return Err(e.into()),
+            Err(e) => { failures.push((dir, e.into())); continue; }
+        };
+
+        for entry in entries {
+            let stat = entry.and_then(|entry| Ok((entry.path(), entry.metadata()?)));
+            let (path, metadata) = match stat {
+                Ok(stat) => stat,
+                Err(e) => { failures.push((dir.clone(), e.into())); continue; }
+            };
+
+            match entry_type(&metadata, path.as_os_str()) {
+                SlpDirEntryType::SlpFile | SlpDirEntryType::SlpzFile => files.push(FileStat {
+                    path,
+                    modified: metadata.modified().unwrap_or(UNIX_EPOCH),
+                    size: metadata.len(),
+                }),
+                SlpDirEntryType::Directory => dirs.push(path),
+                SlpDirEntryType::Other => (),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the index into `files` with each result, in no particular order.
+fn read_infos(files: &[FileStat], thread_count: usize) -> Vec<(usize, SlpResult<GameInfo>)> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let next = AtomicUsize::new(0);
+    let thread_count = thread_count.clamp(1, files.len().max(1));
+
+    std::thread::scope(|s| {
+        let threads: Vec<_> = (0..thread_count).map(|_| s.spawn(|| {
+            let mut results = Vec::new();
+            loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(file) = files.get(i) else { break };
+                results.push((i, read_info(&file.path)));
+            }
+            results
+        })).collect();
+
+        let mut results = Vec::with_capacity(files.len());
+        for thread in threads {
+            match thread.join() {
+                Ok(r) => results.extend(r),
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+        results
+    })
+}
+
+// CACHE ------------------------------------------------------------------------
+//
+// magic, version, u32 replay count, then for each replay:
+//   u16 path len, utf8 path, u64 modified secs, u32 modified nanos, u64 size, game info.
+//
+// GameInfo fields are written one by one, see `write_cached_info`.
+// Enums are stored as their replay values, options as a presence byte or u8::MAX for None.
+
+fn write_cache(library: &ReplayLibrary) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(16 + library.replays.len() * 0x340);
+    buffer.extend_from_slice(CACHE_MAGIC);
+    buffer.push(CACHE_VERSION);
+
+    // paths that aren't utf8 are left out, they will be parsed every scan
+    let replays: Vec<(&str, &LibraryReplay)> = library.replays.iter()
+        .filter_map(|replay| Some((replay.path.to_str()?, replay)))
+        .filter(|(path, _)| path.len() <= u16::MAX as usize)
+        .collect();
+    buffer.extend_from_slice(&(replays.len() as u32).to_be_bytes());
+
+    for (path, replay) in replays {
+        let modified = replay.modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        buffer.extend_from_slice(&(path.len() as u16).to_be_bytes());
+        buffer.extend_from_slice(path.as_bytes());
+        buffer.extend_from_slice(&modified.as_secs().to_be_bytes());
+        buffer.extend_from_slice(&modified.subsec_nanos().to_be_bytes());
+        buffer.extend_from_slice(&replay.size.to_be_bytes());
+        write_cached_info(&mut buffer, &replay.info);
+    }
+
+    buffer
+}
+
+fn write_cached_info(buffer: &mut Vec<u8>, info: &GameInfo) {
+    buffer.extend_from_slice(&info.stage.to_u16_external().to_be_bytes());
+
+    for port in 0..4 {
+        buffer.push(info.port_used[port] as u8);
+        buffer.push(info.teams[port]);
+        match info.starting_character_colours[port] {
+            Some(colour) => buffer.extend_from_slice(&[1, colour.character().to_u8_internal(), colour.costume_idx()]),
+            None => buffer.extend_from_slice(&[0, 0, 0]),
+        }
+        buffer.extend_from_slice(&info.names[port]);
+        buffer.extend_from_slice(&info.connect_codes[port]);
+
+        let settings = &info.player_settings[port];
+        buffer.extend_from_slice(&[
+            settings.player_type as u8,
+            settings.cpu_level,
+            settings.stocks,
+            settings.handicap,
+            settings.stamina_mode as u8,
+        ]);
+        for ratio in [settings.offense_ratio, settings.defense_ratio, settings.model_scale] {
+            buffer.extend_from_slice(&ratio.0.to_be_bytes());
+        }
+        buffer.extend_from_slice(&[settings.ucf_dashback as u8, settings.ucf_shield_drop as u8]);
+    }
+
+    buffer.extend_from_slice(&info.start_time.0.to_be_bytes());
+    buffer.extend_from_slice(&info.timer.to_be_bytes());
+    buffer.extend_from_slice(&info.duration.to_be_bytes());
+    buffer.extend_from_slice(&[info.has_notes as u8, info.is_teams as u8]);
+
+    match info.end {
+        Some(end) => {
+            buffer.extend_from_slice(&[1, end.method.to_u8(), end.lras_initiator.unwrap_or(u8::MAX)]);
+            buffer.extend(end.placements.map(|p| p.unwrap_or(u8::MAX)));
+        }
+        None => buffer.extend_from_slice(&[0; 7]),
+    }
+
+    buffer.extend_from_slice(&info.damage_ratio.0.to_be_bytes());
+    buffer.push(info.item_spawn_frequency as u8);
+    buffer.extend_from_slice(&info.item_spawn_bitfield);
+    buffer.extend_from_slice(&info.random_seed.to_be_bytes());
+    buffer.extend_from_slice(&[info.is_pal as u8, info.is_frozen_stadium as u8, info.minor_scene, info.major_scene]);
+    buffer.push(info.language.map_or(u8::MAX, |l| l as u8));
+    buffer.extend_from_slice(&info.match_id);
+    buffer.extend_from_slice(&info.game_number.to_be_bytes());
+    buffer.extend_from_slice(&info.tiebreaker_number.to_be_bytes());
+    buffer.extend_from_slice(&[info.version_major, info.version_minor, info.version_patch]);
+}
+
+struct CacheReader<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> CacheReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.cursor..)?.get(..len)?;
+        self.cursor += len;
+        Some(bytes)
+    }
+
+    fn array<const SIZE: usize>(&mut self) -> Option<[u8; SIZE]> {
+        let array = *self.bytes.get(self.cursor..)?.first_chunk::<SIZE>()?;
+        self.cursor += SIZE;
+        Some(array)
+    }
+
+    fn u16(&mut self) -> Option<u16> { self.array().map(u16::from_be_bytes) }
+    fn u32(&mut self) -> Option<u32> { self.array().map(u32::from_be_bytes) }
+    fn u64(&mut self) -> Option<u64> { self.array().map(u64::from_be_bytes) }
+    fn i32(&mut self) -> Option<i32> { self.array().map(i32::from_be_bytes) }
+    fn u8 (&mut self) -> Option<u8>  { self.array().map(u8::from_be_bytes) }
+    fn bool(&mut self) -> Option<bool> { self.u8().map(|b| b != 0) }
+
+    // None is written as u8::MAX
+    fn opt_u8(&mut self) -> Option<Option<u8>> { self.u8().map(|b| (b != u8::MAX).then_some(b)) }
+}
+
+fn read_cache(bytes: &[u8]) -> Option<ReplayLibrary> {
+    let mut reader = CacheReader { bytes, cursor: 0 };
+    if reader.take(CACHE_MAGIC.len())? != CACHE_MAGIC { return None }
+    if reader.u8()? != CACHE_VERSION { return None }
+
+    let count = reader.u32()? as usize;
+    let mut replays = Vec::with_capacity(count.min(bytes.len() / 0x100));
+    for _ in 0..count {
+        let path_len = reader.u16()? as usize;
+        let path = std::str::from_utf8(reader.take(path_len)?).ok()?;
+        let modified_secs = reader.u64()?;
+        let modified_nanos = reader.u32()?;
+        let size = reader.u64()?;
+        let info = read_cached_info(&mut reader)?;
+
+        replays.push(LibraryReplay {
+            path: PathBuf::from(path),
+            modified: UNIX_EPOCH.checked_add(Duration::new(modified_secs, modified_nanos))?,
+            size,
+            info,
+        });
+    }
+
+    Some(ReplayLibrary { replays, failures: Vec::new() })
+}
+
+fn read_cached_info(reader: &mut CacheReader) -> Option<GameInfo> {
+    let stage = Stage::from_u16(reader.u16()?)?;
+
+    let mut port_used = [false; 4];
+    let mut teams = [0; 4];
+    let mut starting_character_colours = [None; 4];
+    let mut names = [[0; 31]; 4];
+    let mut connect_codes = [[0; 10]; 4];
+    let mut player_settings = [PlayerSettings::NULL; 4];
+    for port in 0..4 {
+        port_used[port] = reader.bool()?;
+        teams[port] = reader.u8()?;
+        let [has_colour, character, costume] = reader.array()?;
+        if has_colour != 0 {
+            let character = Character::from_u8_internal(character)?;
+            starting_character_colours[port] = Some(CharacterColour::from_character_and_colour(character, costume)?);
+        }
+        names[port] = reader.array()?;
+        connect_codes[port] = reader.array()?;
+
+        player_settings[port] = PlayerSettings {
+            player_type     : PlayerType::from_u8(reader.u8()?)?,
+            cpu_level       : reader.u8()?,
+            stocks          : reader.u8()?,
+            handicap        : reader.u8()?,
+            stamina_mode    : reader.bool()?,
+            offense_ratio   : F32Bits(reader.u32()?),
+            defense_ratio   : F32Bits(reader.u32()?),
+            model_scale     : F32Bits(reader.u32()?),
+            ucf_dashback    : UcfSetting::from_u32(reader.u8()? as u32)?,
+            ucf_shield_drop : UcfSetting::from_u32(reader.u8()? as u32)?,
+        };
+    }
+
+    let start_time = Time(reader.u64()?);
+    let timer = reader.u32()?;
+    let duration = reader.i32()?;
+    let has_notes = reader.bool()?;
+    let is_teams = reader.bool()?;
+
+    let has_end = reader.bool()?;
+    let method = reader.u8()?;
+    let method = GameEndMethod::from_u8(method).unwrap_or(GameEndMethod::Unknown(method));
+    let lras_initiator = reader.opt_u8()?;
+    let mut placements = [None; 4];
+    for placement in placements.iter_mut() { *placement = reader.opt_u8()?; }
+    let end = has_end.then_some(GameEnd { method, lras_initiator, placements });
+
+    Some(GameInfo {
+        stage,
+        port_used,
+        teams,
+        starting_character_colours,
+        names,
+        connect_codes,
+        start_time,
+        timer,
+        duration,
+        has_notes,
+        is_teams,
+        end,
+        player_settings,
+        damage_ratio         : F32Bits(reader.u32()?),
+        item_spawn_frequency : reader.u8()? as i8,
+        item_spawn_bitfield  : reader.array()?,
+        random_seed          : reader.u32()?,
+        is_pal               : reader.bool()?,
+        is_frozen_stadium    : reader.bool()?,
+        minor_scene          : reader.u8()?,
+        major_scene          : reader.u8()?,
+        language             : match reader.opt_u8()? { Some(n) => Some(Language::from_u8(n)?), None => None },
+        match_id             : reader.array()?,
+        game_number          : reader.u32()?,
+        tiebreaker_number    : reader.u32()?,
+        version_major        : reader.u8()?,
+        version_minor        : reader.u8()?,
+        version_patch        : reader.u8()?,
+    })
+}
+
+#[test]
+fn replay_library_scan() {
+    let dir = std::env::temp_dir().join(format!("slp_parser_library_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+
+    let slp = test_replay();
+    std::fs::write(dir.join("a.slp"), &slp).unwrap();
+    std::fs::write(dir.join("sub").join("b.slp"), &slp).unwrap();
+    std::fs::write(dir.join("sub").join("broken.slp"), b"not a replay").unwrap();
+    std::fs::write(dir.join("other.txt"), b"ignored").unwrap();
+
+    let mut library = ReplayLibrary::new();
+    assert_eq!(library.scan_with_threads(&dir, 2).unwrap(), 3);
+    assert_eq!(library.replays.len(), 2);
+    assert_eq!(library.replays[0].path, dir.join("a.slp"));
+    assert_eq!(library.replays[0].info, read_info(&dir.join("a.slp")).unwrap());
+    assert_eq!(library.failures, vec![(dir.join("sub").join("broken.slp"), SlpError::NotAnSlpFile)]);
+
+    let cache_path = dir.join("library.cache");
+    library.save(&cache_path).unwrap();
+    let mut loaded = ReplayLibrary::load(&cache_path).unwrap();
+    for (a, b) in loaded.replays.iter().zip(library.replays.iter()) {
+        assert_eq!((&a.path, a.modified, a.size, &a.info), (&b.path, b.modified, b.size, &b.info));
+    }
+
+    // only the broken and changed files are parsed again
+    assert_eq!(loaded.scan(&dir).unwrap(), 1);
+    std::fs::remove_file(dir.join("a.slp")).unwrap();
+    std::fs::write(dir.join("sub").join("b.slp"), &slp[..slp.len()-1]).unwrap();
+    assert_eq!(loaded.scan(&dir).unwrap(), 2);
+    assert_eq!(loaded.replays.len(), 1);
+    assert_eq!(loaded.replays[0].size, slp.len() as u64 - 1);
+
+    // read_info can't find the game end without a raw length, so check it separately
+    let info = parse_file(&slp).unwrap().info;
+    let mut buffer = Vec::new();
+    write_cached_info(&mut buffer, &info);
+    assert_eq!(read_cached_info(&mut CacheReader { bytes: &buffer, cursor: 0 }), Some(info));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cached_info_round_trip() {
+    let mut info = parse_file(&test_replay()).unwrap().info;
+    info.language = Some(Language::English);
+    info.damage_ratio = F32Bits::from(1.5);
+    info.player_settings[1].ucf_dashback = UcfSetting::Dween;
+    info.item_spawn_frequency = -1;
+    info.match_id[..5].copy_from_slice(b"match");
+    info.end = Some(GameEnd { method: GameEndMethod::Unknown(9), lras_initiator: None, placements: [Some(1), Some(0), None, None] });
+
+    let mut buffer = Vec::new();
+    write_cached_info(&mut buffer, &info);
+    let mut reader = CacheReader { bytes: &buffer, cursor: 0 };
+    assert_eq!(read_cached_info(&mut reader), Some(info));
+    assert_eq!(reader.cursor, buffer.len());
+
+    // truncated info is discarded
+    assert_eq!(read_cached_info(&mut CacheReader { bytes: &buffer[..buffer.len()-1], cursor: 0 }), None);
+
+    // caches from other versions are discarded
+    let library = ReplayLibrary { replays: vec![LibraryReplay { path: "a.slp".into(), modified: UNIX_EPOCH, size: 0, info }], failures: Vec::new() };
+    let mut cache = write_cache(&library);
+    assert_eq!(read_cache(&cache).unwrap().replays[0].info, info);
+    cache[CACHE_MAGIC.len()] = CACHE_VERSION - 1;
+    assert!(read_cache(&cache).is_none());
+}
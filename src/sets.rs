@@ -0,0 +1,351 @@
+use crate::*;
+
+/// Consecutive games between the same players.
+#[derive(Clone, Debug)]
+pub struct Set {
+    /// Connect code of each player, or their name for offline replays, or "Port N" if neither exists.
+    /// Sorted, so the order does not depend on ports.
+    pub players: Vec<String>,
+    /// Online match ID. Empty if not recorded (older than v3.14.0 or not an online game).
+    pub match_id: String,
+    /// In play order.
+    pub games: Vec<SetGame>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SetGame {
+    /// Index into the replays passed to `group_sets`.
+    pub replay_idx: usize,
+    /// Zero if not recorded.
+    pub game_number: u32,
+    pub start_time: Time,
+    /// Port of the winner. For teams, a port on the winning team.
+    /// From `GameInfo::winner` with `group_sets`, see `group_sets_with` for other winners.
+    pub winner: Option<u8>,
+    /// Index into `Set::players` for each port.
+    pub player_idx: [Option<u8>; 4],
+}
+
+impl Set {
+    /// Games won by each player, in the same order as `players`. Games without a winner are not counted.
+    ///
+    /// Sets from `group_sets` only know the winner from the Game End placements,
+    /// so sets older than v3.13.0 always score 0-0. Use `group_sets_with` and `Game::winner` for those.
+    pub fn score(&self) -> Vec<u32> {
+        let mut score = vec![0; self.players.len()];
+        for game in self.games.iter() {
+            let Some(winner) = game.winner else { continue };
+            if let Some(player) = game.player_idx[winner as usize] {
+                score[player as usize] += 1;
+            }
+        }
+        score
+    }
+
+    /// Index into `players` of the player with the most wins. None if tied.
+    pub fn leader(&self) -> Option<usize> {
+        let score = self.score();
+        let max = *score.iter().max()?;
+        let mut leaders = score.iter().enumerate().filter(|(_, &s)| s == max);
+        let (leader, _) = leaders.next()?;
+        if leaders.next().is_some() { return None }
+        Some(leader)
+    }
+}
+
+impl GameInfo {
+    /// Winner from the Game End event. For teams, a port on the winning team.
+    ///
+    /// A player who quits out with LRAS loses.
+    /// Returns None if the game has no Game End, ended in a no contest,
+    /// or is older than v3.13.0 (no placements). Use `Game::winner` for those.
+    pub fn winner(&self) -> Option<u8> {
+        let end = self.end?;
+
+        if let Some(initiator) = end.lras_initiator {
+            let initiator_team = self.teams.get(initiator as usize).copied();
+            return (0..4)
+                .find(|&p| self.port_used[p] && Some(self.teams[p]) != initiator_team)
+                .map(|p| p as u8);
+        }
+
+        if end.method == GameEndMethod::NoContest { return None }
+        end.placements.iter().position(|&p| p == Some(0)).map(|p| p as u8)
+    }
+}
+
+impl Game {
+    /// Winner from the Game End event, falling back to the final stock counts and percents.
+    /// For teams, a port on the winning team.
+    /// Returns None if the game was tied or ended in a no contest.
+    pub fn winner(&self) -> Option<u8> {
+        if let Some(winner) = self.info.winner() { return Some(winner) }
+        if self.info.end.is_some_and(|end| end.method == GameEndMethod::NoContest) { return None }
+
+        // (team, stocks, percent, first port) for each team
+        let mut team_state: Vec<(u8, u32, f32, u8)> = Vec::new();
+        for port in 0..4 {
+            let Some(frames) = self.frames[port].as_ref() else { continue };
+            let Some(last) = frames.last() else { continue };
+            let team = self.info.teams[port];
+            let percent = if last.stock_count == 0 { 0.0 } else { last.percent };
+            match team_state.iter_mut().find(|t| t.0 == team) {
+                Some(t) => { t.1 += last.stock_count as u32; t.2 += percent; }
+                None => team_state.push((team, last.stock_count as u32, percent, port as u8)),
+            }
+        }
+
+        let mut best: Option<(u32, f32, u8)> = None;
+        let mut tied = false;
+        for &(_, stocks, percent, port) in team_state.iter() {
+            match best {
+                Some((best_stocks, best_percent, _)) if stocks == best_stocks && percent == best_percent => tied = true,
+                Some((best_stocks, best_percent, _)) if stocks < best_stocks || (stocks == best_stocks && percent > best_percent) => (),
+                _ => { best = Some((stocks, percent, port)); tied = false; }
+            }
+        }
+
+        if tied { return None }
+        best.map(|(_, _, port)| port)
+    }
+}
+
+/// Groups replays into sets.
+///
+/// Games are in the same set if they have the same players and either have the same online match ID,
+/// or started within `max_gap` of the end of the previous game.
+/// Games with different match IDs are never in the same set.
+/// Games without a start time are only grouped by match ID.
+///
+/// Sets are sorted by the start time of their first game.
+/// Winners are from `GameInfo::winner`, so they are only known from v3.13.0.
+pub fn group_sets<T: AsRef<GameInfo>>(replays: &[T], max_gap: std::time::Duration) -> Vec<Set> {
+    group_sets_with(replays, max_gap, |replay| replay.as_ref().winner())
+}
+
+/// `group_sets` with the winner of each game from `winner`.
+/// Pass `Game::winner` with parsed games to fall back to the final stock counts in older replays.
+pub fn group_sets_with<T: AsRef<GameInfo>>(
+    replays: &[T],
+    max_gap: std::time::Duration,
+    winner: impl Fn(&T) -> Option<u8>,
+) -> Vec<Set> {
+    struct Entry {
+        replay_idx: usize,
+        players: Vec<String>,
+        player_idx: [Option<u8>; 4],
+        match_id: String,
+        start: Option<i64>,
+        end: Option<i64>,
+    }
+
+    let mut entries: Vec<Entry> = replays.iter().enumerate().map(|(replay_idx, r)| {
+        let info = r.as_ref();
+
+        let mut name_buf = String::new();
+        let names = info.names(&mut name_buf);
+        let mut code_buf = String::new();
+        let codes = info.connect_codes(&mut code_buf);
+
+        let mut ports: Vec<(String, u8)> = (0..4)
+            .filter(|&p| info.port_used[p])
+            .map(|p| {
+                let player = if !codes[p].is_empty() {
+                    codes[p].to_string()
+                } else if !names[p].is_empty() {
+                    names[p].to_string()
+                } else {
+                    format!("Port {}", p+1)
+                };
+                (player, p as u8)
+            })
+            .collect();
+        ports.sort();
+
+        let mut player_idx = [None; 4];
+        for (i, (_, port)) in ports.iter().enumerate() {
+            player_idx[*port as usize] = Some(i as u8);
+        }
+
+        let start = unix_seconds(info.start_time);
+        let end = start.map(|s| s + info.duration.max(0) as i64 / 60);
+
+        Entry {
+            replay_idx,
+            players: ports.into_iter().map(|(player, _)| player).collect(),
+            player_idx,
+            match_id: info.match_id_str().to_string(),
+            start,
+            end,
+        }
+    }).collect();
+
+    entries.sort_by(|a, b| {
+        a.players.cmp(&b.players)
+            .then(a.start.cmp(&b.start))
+            .then(replays[a.replay_idx].as_ref().game_number.cmp(&replays[b.replay_idx].as_ref().game_number))
+    });
+
+    let max_gap = max_gap.as_secs().min(i64::MAX as u64) as i64;
+    let mut sets: Vec<Set> = Vec::new();
+    // (set index, end of last game)
+    let mut open: Vec<(usize, Option<i64>)> = Vec::new();
+
+    for entry in entries {
+        // only sets with the same players can be joined, and those were sorted to be adjacent
+        if sets.last().is_some_and(|s| s.players != entry.players) { open.clear(); }
+
+        let joins = |set: &Set, prev_end: Option<i64>| -> bool {
+            match (set.match_id.is_empty(), entry.match_id.is_empty()) {
+                (false, false) => set.match_id == entry.match_id,
+                _ => match (prev_end, entry.start) {
+                    (Some(prev_end), Some(start)) => start.saturating_sub(prev_end) <= max_gap,
+                    _ => false,
+                },
+            }
+        };
+
+        let replay = &replays[entry.replay_idx];
+        let info = replay.as_ref();
+        let game = SetGame {
+            replay_idx: entry.replay_idx,
+            game_number: info.game_number,
+            start_time: info.start_time,
+            winner: winner(replay),
+            player_idx: entry.player_idx,
+        };
+
+        match open.iter_mut().rev().find(|(set_idx, prev_end)| joins(&sets[*set_idx], *prev_end)) {
+            Some((set_idx, prev_end)) => {
+                let set = &mut sets[*set_idx];
+                set.games.push(game);
+                if set.match_id.is_empty() { set.match_id = entry.match_id; }
+                if entry.end.is_some() { *prev_end = entry.end; }
+            }
+            None => {
+                open.push((sets.len(), entry.end));
+                sets.push(Set {
+                    players: entry.players,
+                    match_id: entry.match_id,
+                    games: vec![game],
+                });
+            }
+        }
+    }
+
+    sets.sort_by_key(|s| s.games[0].start_time);
+    sets
+}
+
+/// Seconds since the unix epoch. None if the time is NULL or invalid.
+fn unix_seconds(time: Time) -> Option<i64> {
+    if time == Time::NULL { return None }
+    let f = time.fields();
+    if !(1..=12).contains(&f.month) || !(1..=31).contains(&f.day) { return None }
+
+    // days from civil, from http://howardhinnant.github.io/date_algorithms.html
+    let year = f.year as i64 - (f.month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = f.month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + f.day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    Some(days * 86400 + f.hour as i64 * 3600 + f.minute as i64 * 60 + f.second as i64)
+}
+
+#[cfg(test)]
+fn test_time(hour: u8, minute: u8) -> Time {
+    Time::from(TimeFields { year: 2024, month: 3, day: 1, hour, minute, second: 0 })
+}
+
+#[cfg(test)]
+fn with_code(info: GameInfo, port: usize, code: &[u8]) -> GameInfo {
+    let mut info = info;
+    info.connect_codes[port] = [0; 10];
+    info.connect_codes[port][..code.len()].copy_from_slice(code);
+    info
+}
+
+#[cfg(test)]
+fn placed(winner: usize) -> Option<GameEnd> {
+    let mut placements = [Some(1), Some(1), None, None];
+    placements[winner] = Some(0);
+    Some(GameEnd { method: GameEndMethod::Game, lras_initiator: None, placements })
+}
+
+#[test]
+fn group_sets_by_players_and_time() {
+    let info = parse_file(&test_replay()).unwrap().info;
+    let base = with_code(with_code(info, 0, b"AAA#1"), 1, b"BBB#2");
+    let lras = |port| Some(GameEnd { method: GameEndMethod::NoContest, lras_initiator: Some(port), placements: [None; 4] });
+
+    let replays = [
+        GameInfo { start_time: test_time(20, 0),  duration: 60 * 60 * 3, end: placed(0), ..base },
+        GameInfo { start_time: test_time(20, 4),  duration: 60 * 60 * 3, end: placed(1), ..base },
+        // different opponent
+        GameInfo { start_time: test_time(20, 5),  ..with_code(base, 1, b"CCC#3") },
+        // ports swapped, still the same players
+        GameInfo { start_time: test_time(20, 8),  end: lras(1), ..with_code(with_code(base, 0, b"BBB#2"), 1, b"AAA#1") },
+        // too long after the previous game
+        GameInfo { start_time: test_time(22, 0),  end: placed(0), ..base },
+    ];
+
+    let sets = group_sets(&replays, std::time::Duration::from_secs(120));
+    assert_eq!(sets.len(), 3);
+
+    let set = &sets[0];
+    assert_eq!(set.players, vec!["AAA#1".to_string(), "BBB#2".to_string()]);
+    assert_eq!(set.games.iter().map(|g| g.replay_idx).collect::<Vec<_>>(), vec![0, 1, 3]);
+    assert_eq!(set.games.iter().map(|g| g.winner).collect::<Vec<_>>(), vec![Some(0), Some(1), Some(0)]);
+    assert_eq!(set.score(), vec![1, 2]);
+    assert_eq!(set.leader(), Some(1));
+
+    assert_eq!(sets[1].players, vec!["AAA#1".to_string(), "CCC#3".to_string()]);
+    assert_eq!(sets[2].games[0].replay_idx, 4);
+}
+
+#[test]
+fn group_sets_by_match_id() {
+    let info = parse_file(&test_replay()).unwrap().info;
+    let base = with_code(with_code(info, 0, b"AAA#1"), 1, b"BBB#2");
+
+    // different match ids split sets even if close in time
+    let mut a = base;
+    a.match_id[..3].copy_from_slice(b"one");
+    let mut b = base;
+    b.match_id[..3].copy_from_slice(b"two");
+    let replays = [GameInfo { start_time: test_time(20, 0), ..a }, GameInfo { start_time: test_time(20, 1), ..b }];
+    assert_eq!(group_sets(&replays, std::time::Duration::from_secs(600)).len(), 2);
+
+    assert_eq!(unix_seconds(test_time(0, 0)), Some(1709251200));
+}
+
+#[test]
+fn set_winners_from_stock_counts() {
+    let mut game = parse_file(&test_replay()).unwrap();
+    // test replay ends with lras by port 1
+    assert_eq!(game.winner(), Some(0));
+
+    // no placements before v3.13.0, port 1 has more stocks left
+    game.info = with_code(with_code(game.info, 0, b"AAA#1"), 1, b"BBB#2");
+    game.info.end = Some(GameEnd { method: GameEndMethod::Game, lras_initiator: None, placements: [None; 4] });
+    let mut p0 = test_frames(&game, 0, 3);
+    p0[2].stock_count = 1;
+    game.frames[0] = Some(p0.into_boxed_slice());
+    game.frames[1] = Some(test_frames(&game, 1, 3).into_boxed_slice());
+    assert_eq!((game.info.winner(), game.winner()), (None, Some(1)));
+
+    let mut second = game.clone();
+    second.info.start_time = test_time(20, 1);
+    game.info.start_time = test_time(20, 0);
+    let games = [game, second];
+
+    let gap = std::time::Duration::from_secs(120);
+    assert_eq!(group_sets(&games, gap)[0].score(), vec![0, 0]);
+    let sets = group_sets_with(&games, gap, Game::winner);
+    assert_eq!(sets.len(), 1);
+    assert_eq!(sets[0].score(), vec![0, 2]);
+    assert_eq!(sets[0].leader(), Some(1));
+}
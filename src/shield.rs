@@ -0,0 +1,290 @@
+use crate::*;
+
+/// Startup of a shield grab for characters without frame data.
+pub const DEFAULT_SHIELD_GRAB_FRAMES: u16 = 7;
+
+/// Frames after shield stun ends that the defender's next option is looked for.
+/// Holding shield for longer is `ShieldResponse::HeldShield`.
+pub const SHIELD_RESPONSE_FRAMES: usize = 20;
+
+/// What the defender did after shield stun.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShieldResponse {
+    ShieldGrab,
+    /// Jump out of shield, with or without an aerial.
+    Jump,
+    Aerial,
+    Special,
+    GroundAttack,
+    Roll,
+    Spotdodge,
+    Wavedash,
+    ShieldDrop,
+    HeldShield,
+    Other(HighLevelAction),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShieldHit {
+    pub attacker: u8,
+    pub defender: u8,
+    /// Index of the frame the hit landed, the first frame of hitlag.
+    pub frame_idx: usize,
+    pub attacker_state: ActionState,
+    pub attacker_anim_frame: f32,
+    /// Defender's shield size after the hit.
+    pub shield_size: f32,
+    pub hitlag_frames: f32,
+    /// Index of the first frame after shield stun.
+    /// None if the shield was hit again, broke, or the defender was hit first.
+    pub defender_actionable: Option<usize>,
+    /// Index of the first frame the attacker could act.
+    /// Without frame data for the move (see `FRAME_DATA_CHARACTERS`), this is the first frame the attacker
+    /// left the move instead, which is later than the IASA frame if the attacker waited.
+    /// None if the attacker was hit or grabbed first, and there is no frame data for the move.
+    pub attacker_actionable: Option<usize>,
+    /// Frames the attacker could act before the defender. Negative if the defender could act first.
+    pub frame_advantage: Option<i32>,
+    /// The defender could shield grab before the attacker could act.
+    /// Defenders without frame data are assumed to shield grab in `DEFAULT_SHIELD_GRAB_FRAMES`.
+    pub guaranteed_punish: Option<bool>,
+    /// None if the shield was hit again or broke before the defender could act.
+    pub response: Option<ShieldResponse>,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShieldResponseCount {
+    pub count: u32,
+    /// Times this option was chosen when a punish was guaranteed.
+    pub when_punishable: u32,
+}
+
+fn shield_grab_frames(character: Character) -> u16 {
+    frame_data(character, ActionState::Standard(StandardActionState::Catch))
+        .and_then(|data| data.active.first())
+        .map(|w| w.start)
+        .unwrap_or(DEFAULT_SHIELD_GRAB_FRAMES)
+}
+
+fn is_shield_stun(frame: &Frame) -> bool {
+    frame.state == ActionState::Standard(StandardActionState::GuardSetOff)
+}
+
+fn is_shield_break(frame: &Frame) -> bool {
+    let n = frame.state.as_u16();
+    (StandardActionState::ShieldBreakFly as u16..=StandardActionState::FuraFura as u16).contains(&n)
+}
+
+fn hitlag_started(prev: &Frame, frame: &Frame) -> bool {
+    frame.hitlag_frames > 0.0 && prev.hitlag_frames <= 0.0
+}
+
+// the attacker is still in the move that hit, including its landing lag
+fn in_same_move(hit: &Frame, frame: &Frame) -> bool {
+    use StandardActionState as SAS;
+    match (hit.state, frame.state) {
+        (ActionState::Special(_), ActionState::Special(_)) => hit.state.broad_state() == frame.state.broad_state(),
+        (_, ActionState::Standard(
+            SAS::Landing | SAS::LandingFallSpecial | SAS::LandingAirN | SAS::LandingAirF
+            | SAS::LandingAirB | SAS::LandingAirHi | SAS::LandingAirLw
+        )) => true,
+        _ => hit.state_num == frame.state_num,
+    }
+}
+
+fn attacker_actionable(frames: &[Frame], hit_idx: usize) -> Option<usize> {
+    let hit = &frames[hit_idx];
+    for (i, frame) in frames.iter().enumerate().skip(hit_idx) {
+        if frame.hitlag_frames > 0.0 { continue }
+        if frames_until_actionable(frame) == Some(0) { return Some(i) }
+
+        match frame.state.broad_state() {
+            BroadState::Standard(StandardBroadState::Hitstun | StandardBroadState::Dead) => {
+                // punished before acting, predict from frame data if we can
+                let data = frame_data(hit.character, hit.state)?;
+                let hitlag = frames[hit_idx..i].iter().filter(|f| f.hitlag_frames > 0.0).count();
+                let remaining = data.first_actionable_frame().saturating_sub(hit.anim_frame.max(0.0) as u16);
+                return Some(hit_idx + hitlag + remaining as usize);
+            }
+            BroadState::Standard(sbs) if sbs.is_actionable() => return Some(i),
+            _ => (),
+        }
+
+        if !in_same_move(hit, frame) { return Some(i) }
+    }
+    None
+}
+
+fn shield_response(frames: &[Frame], actions: &[Action], actionable: usize) -> Option<ShieldResponse> {
+    use HighLevelAction as HLA;
+    use StandardActionState as SAS;
+
+    let window_end = (actionable + SHIELD_RESPONSE_FRAMES).min(frames.len());
+    let mut idx = actionable;
+    while idx < window_end && matches!(frames[idx].state, ActionState::Standard(SAS::Guard | SAS::GuardOn | SAS::GuardReflect)) {
+        idx += 1;
+    }
+    if idx == window_end { return Some(ShieldResponse::HeldShield) }
+    if frames[idx].state == ActionState::Standard(SAS::GuardOff) { return Some(ShieldResponse::ShieldDrop) }
+    if is_shield_stun(&frames[idx]) { return None }
+
+    let action = actions.iter().find(|a| a.frame_start <= idx && idx < a.frame_end)?;
+    Some(match action.action_taken {
+        HLA::Grab => ShieldResponse::ShieldGrab,
+        HLA::Fullhop | HLA::Shorthop => ShieldResponse::Jump,
+        HLA::Aerial(_) | HLA::JumpAerial(_) | HLA::FullhopAerial(_) | HLA::ShorthopAerial(_) => ShieldResponse::Aerial,
+        HLA::Special(_) => ShieldResponse::Special,
+        HLA::GroundAttack(_) => ShieldResponse::GroundAttack,
+        HLA::RollForward | HLA::RollBackward => ShieldResponse::Roll,
+        HLA::Spotdodge => ShieldResponse::Spotdodge,
+        HLA::WavedashLeft | HLA::WavedashDown | HLA::WavedashRight => ShieldResponse::Wavedash,
+        HLA::Shield => ShieldResponse::HeldShield,
+        hla => ShieldResponse::Other(hla),
+    })
+}
+
+/// Every hit on a shield in the game, sorted by frame.
+///
+/// The attacker is the opponent who entered hitlag on the same frame,
+/// or the only opponent for projectiles.
+pub fn shield_hits(game: &Game) -> Vec<ShieldHit> {
+    let mut hits = Vec::new();
+
+    for defender in 0..4 {
+        let Some(defender_frames) = game.frames[defender].as_deref() else { continue };
+        let actions = parse_actions(defender_frames);
+
+        for i in 1..defender_frames.len() {
+            let prev = &defender_frames[i-1];
+            let frame = &defender_frames[i];
+            if !is_shield_stun(frame) || !hitlag_started(prev, frame) { continue }
+
+            let opponents = (0..4).filter(|&p| {
+                p != defender && game.info.teams[p] != game.info.teams[defender]
+                    && game.frames[p].as_ref().is_some_and(|f| f.len() > i)
+            });
+            let mut in_hitlag = opponents.clone().filter(|&p| {
+                let f = game.frames[p].as_deref().unwrap();
+                hitlag_started(&f[i-1], &f[i])
+            });
+            let attacker = match in_hitlag.next() {
+                Some(p) => p,
+                None => {
+                    let mut opponents = opponents;
+                    let Some(p) = opponents.next() else { continue };
+                    if opponents.next().is_some() { continue }
+                    p
+                }
+            };
+            let attacker_frames = game.frames[attacker].as_deref().unwrap();
+
+            // shield stun ends at the first frame out of GuardSetOff, unless hit again first
+            let mut defender_actionable = None;
+            for (j, f) in defender_frames.iter().enumerate().skip(i+1) {
+                if is_shield_stun(f) {
+                    if hitlag_started(&defender_frames[j-1], f) { break }
+                    continue;
+                }
+                let punished = matches!(
+                    f.state.broad_state(),
+                    BroadState::Standard(StandardBroadState::Hitstun | StandardBroadState::Dead)
+                );
+                if !punished && !is_shield_break(f) {
+                    defender_actionable = Some(j);
+                }
+                break;
+            }
+
+            let attacker_actionable = attacker_actionable(attacker_frames, i);
+            let frame_advantage = defender_actionable.zip(attacker_actionable).map(|(d, a)| d as i32 - a as i32);
+            let guaranteed_punish = frame_advantage.map(|adv| -adv >= shield_grab_frames(frame.character) as i32);
+            let response = defender_actionable.and_then(|d| shield_response(defender_frames, &actions, d));
+
+            hits.push(ShieldHit {
+                attacker: attacker as u8,
+                defender: defender as u8,
+                frame_idx: i,
+                attacker_state: attacker_frames[i].state,
+                attacker_anim_frame: attacker_frames[i].anim_frame,
+                shield_size: frame.shield_size,
+                hitlag_frames: frame.hitlag_frames,
+                defender_actionable,
+                attacker_actionable,
+                frame_advantage,
+                guaranteed_punish,
+                response,
+            });
+        }
+    }
+
+    hits.sort_by_key(|h| (h.frame_idx, h.defender));
+    hits
+}
+
+/// How often the defender chose each option, in order of first occurrence.
+pub fn shield_response_totals(hits: &[ShieldHit], defender: u8) -> Vec<(ShieldResponse, ShieldResponseCount)> {
+    let mut totals: Vec<(ShieldResponse, ShieldResponseCount)> = Vec::new();
+    for hit in hits.iter().filter(|h| h.defender == defender) {
+        let Some(response) = hit.response else { continue };
+        let idx = match totals.iter().position(|(r, _)| *r == response) {
+            Some(idx) => idx,
+            None => { totals.push((response, ShieldResponseCount::default())); totals.len() - 1 }
+        };
+        let count = &mut totals[idx].1;
+        count.count += 1;
+        count.when_punishable += (hit.guaranteed_punish == Some(true)) as u32;
+    }
+    totals
+}
+
+#[cfg(test)]
+fn shield_grab_game() -> Game {
+    use StandardActionState as SAS;
+
+    let mut game = parse_file(&test_replay()).unwrap();
+    let mut attacker = test_frames(&game, 0, 200);
+    let mut defender = test_frames(&game, 1, 200);
+    assert_eq!((attacker[0].character, defender[0].character), (Character::Fox, Character::Marth));
+
+    // fox ftilt hits on frame 5 with 5 frames of hitlag, then 21 more frames of the move
+    for (k, f) in attacker[100..130].iter_mut().enumerate() {
+        f.state = ActionState::Standard(SAS::AttackS3S);
+        f.anim_frame = match k { 0..=3 => k + 1, 4..=8 => 5, _ => k - 3 } as f32;
+        if (4..=8).contains(&k) { f.hitlag_frames = (9 - k) as f32; }
+    }
+
+    // 5 frames of hitlag and 8 of shield stun, then a shield grab
+    for f in defender[60..117].iter_mut() { f.state = ActionState::Standard(SAS::Guard); f.shield_size = 60.0; }
+    for (k, f) in defender[104..117].iter_mut().enumerate() {
+        f.state = ActionState::Standard(SAS::GuardSetOff);
+        f.shield_size = 50.0;
+        if k < 5 { f.hitlag_frames = (5 - k) as f32; }
+    }
+    for f in defender[119..150].iter_mut() { f.state = ActionState::Standard(SAS::Catch); }
+
+    game.frames[0] = Some(attacker.into_boxed_slice());
+    game.frames[1] = Some(defender.into_boxed_slice());
+    game
+}
+
+#[test]
+fn shield_hit_frame_advantage() {
+    let hits = shield_hits(&shield_grab_game());
+    assert_eq!(hits.len(), 1);
+    let hit = hits[0];
+    assert_eq!((hit.attacker, hit.defender, hit.frame_idx, hit.shield_size), (0, 1, 104, 50.0));
+    assert_eq!((hit.defender_actionable, hit.attacker_actionable), (Some(117), Some(130)));
+    assert_eq!(hit.frame_advantage, Some(-13));
+}
+
+#[test]
+fn shield_grab_punish() {
+    let hits = shield_hits(&shield_grab_game());
+    assert_eq!(hits[0].guaranteed_punish, Some(true));
+    assert_eq!(hits[0].response, Some(ShieldResponse::ShieldGrab));
+    assert_eq!(
+        shield_response_totals(&hits, 1),
+        vec![(ShieldResponse::ShieldGrab, ShieldResponseCount { count: 1, when_punishable: 1 })],
+    );
+    assert!(shield_response_totals(&hits, 0).is_empty());
+}
@@ -0,0 +1,358 @@
+use crate::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Platform {
+    pub left: f32,
+    pub right: f32,
+    pub y: f32,
+}
+
+/// Collision and blast zone geometry of a legal stage. The main stage is centred on x = 0.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StageGeometry {
+    /// x distance from centre to the ledge.
+    pub edge_x: f32,
+    /// Height of the main stage surface.
+    pub ground_y: f32,
+    pub blast_zone_x: f32,
+    pub blast_zone_top: f32,
+    pub blast_zone_bottom: f32,
+    /// Fountain of Dreams platforms move, so their resting height is used.
+    pub platforms: &'static [Platform],
+}
+
+pub fn stage_geometry(stage: Stage) -> Option<StageGeometry> {
+    const fn plat(left: f32, right: f32, y: f32) -> Platform { Platform { left, right, y } }
+    const YS_PLATFORMS: &[Platform] = &[plat(-59.5, -28.0, 23.45), plat(28.0, 59.5, 23.45), plat(-15.75, 15.75, 42.0)];
+    const FOD_PLATFORMS: &[Platform] = &[plat(-49.5, -21.0, 20.0), plat(21.0, 49.5, 20.0), plat(-14.25, 14.25, 42.75)];
+    const BF_PLATFORMS: &[Platform] = &[plat(-57.6, -20.0, 27.2), plat(20.0, 57.6, 27.2), plat(-18.8, 18.8, 54.4)];
+    const DL_PLATFORMS: &[Platform] = &[plat(-61.393, -31.725, 30.2422), plat(31.704, 63.075, 30.2422), plat(-19.018, 19.017, 51.4264)];
+    const PS_PLATFORMS: &[Platform] = &[plat(-55.0, -25.0, 25.0), plat(25.0, 55.0, 25.0)];
+
+    Some(match stage {
+        Stage::YoshisStory => StageGeometry {
+            edge_x: 56.0, ground_y: 0.0,
+            blast_zone_x: 175.7, blast_zone_top: 168.0, blast_zone_bottom: -91.0,
+            platforms: YS_PLATFORMS,
+        },
+        Stage::FountainOfDreams => StageGeometry {
+            edge_x: 63.348, ground_y: 0.0,
+            blast_zone_x: 198.75, blast_zone_top: 202.5, blast_zone_bottom: -146.25,
+            platforms: FOD_PLATFORMS,
+        },
+        Stage::Battlefield => StageGeometry {
+            edge_x: 68.4, ground_y: 0.0,
+            blast_zone_x: 224.0, blast_zone_top: 200.0, blast_zone_bottom: -108.8,
+            platforms: BF_PLATFORMS,
+        },
+        Stage::DreamLandN64 => StageGeometry {
+            edge_x: 77.259, ground_y: 0.0,
+            blast_zone_x: 255.0, blast_zone_top: 250.0, blast_zone_bottom: -123.0,
+            platforms: DL_PLATFORMS,
+        },
+        Stage::FinalDestination => StageGeometry {
+            edge_x: 85.554, ground_y: 0.0,
+            blast_zone_x: 246.0, blast_zone_top: 188.0, blast_zone_bottom: -140.0,
+            platforms: &[],
+        },
+        Stage::PokemonStadium => StageGeometry {
+            edge_x: 87.738, ground_y: 0.0,
+            blast_zone_x: 230.0, blast_zone_top: 180.0, blast_zone_bottom: -111.0,
+            platforms: PS_PLATFORMS,
+        },
+        _ => return None,
+    })
+}
+
+impl StageGeometry {
+    /// Airborne and either past the ledge or below the stage.
+    /// Grounded players are always onstage, so Randall and transformations are handled.
+    pub fn is_offstage(&self, frame: &Frame) -> bool {
+        frame.is_airborne && (frame.position.x.abs() > self.edge_x || frame.position.y < self.ground_y - 5.0)
+    }
+}
+
+/// What is happening from one player's point of view. Situations come in pairs, see `opposite`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SituationKind {
+    Neutral,
+    /// Either player is dead or respawning.
+    /// Respawning lasts until the player first lands after leaving the revival platform,
+    /// and the start of the game counts until both players have landed.
+    Dead,
+    /// The opponent is in hitstun or grabbed by the player.
+    Punishing,
+    Punished,
+    /// The opponent is offstage and the player is not.
+    Edgeguarding,
+    Recovering,
+    /// The opponent is on the ledge or getting up from it, and the player is onstage.
+    LedgeTrapping,
+    OnLedge,
+    /// The opponent is knocked down or teching.
+    TechChasing,
+    TechChased,
+    /// The opponent is shielding and the player is attacking nearby.
+    ShieldPressuring,
+    ShieldPressured,
+}
+
+impl SituationKind {
+    /// The same situation from the opponent's point of view.
+    pub fn opposite(self) -> SituationKind {
+        use SituationKind::*;
+        match self {
+            Neutral          => Neutral,
+            Dead             => Dead,
+            Punishing        => Punished,
+            Punished         => Punishing,
+            Edgeguarding     => Recovering,
+            Recovering       => Edgeguarding,
+            LedgeTrapping    => OnLedge,
+            OnLedge          => LedgeTrapping,
+            TechChasing      => TechChased,
+            TechChased       => TechChasing,
+            ShieldPressuring => ShieldPressured,
+            ShieldPressured  => ShieldPressuring,
+        }
+    }
+
+    /// True if the player is the one with the advantage.
+    pub fn is_advantage(self) -> bool {
+        use SituationKind::*;
+        matches!(self, Punishing | Edgeguarding | LedgeTrapping | TechChasing | ShieldPressuring)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Situation {
+    pub kind: SituationKind,
+    pub frame_start: usize,
+    /// Exclusive.
+    pub frame_end: usize,
+}
+
+impl Situation {
+    pub fn len(&self) -> usize { self.frame_end - self.frame_start }
+    pub fn is_empty(&self) -> bool { self.frame_end == self.frame_start }
+}
+
+/// Max x distance for an attack to count as shield pressure.
+pub const SHIELD_PRESSURE_DISTANCE: f32 = 40.0;
+
+/// `Frame::last_ground_idx` before touching any ground since entering or respawning.
+pub const NO_GROUND_IDX: u16 = u16::MAX;
+
+fn is_dead(frame: &Frame) -> bool {
+    frame.state.broad_state() == BroadState::Standard(StandardBroadState::Dead)
+        || frame.last_ground_idx == NO_GROUND_IDX
+}
+
+fn is_on_ledge(frame: &Frame, action: Option<HighLevelAction>) -> bool {
+    use HighLevelAction as HLA;
+    matches!(
+        frame.state.broad_state(),
+        BroadState::Standard(StandardBroadState::Ledge | StandardBroadState::LedgeAction)
+    ) || (!frame.is_airborne && matches!(
+        action,
+        Some(HLA::LedgeWait | HLA::LedgeGetUp | HLA::LedgeRoll | HLA::LedgeAttack | HLA::LedgeJump | HLA::LedgeHop | HLA::LedgeAerial(_) | HLA::LedgeDash)
+    ))
+}
+
+// knocked down, getting up, or teching on the ground
+fn is_downed(frame: &Frame) -> bool {
+    let n = frame.state.as_u16();
+    (StandardActionState::DownBoundU as u16..=StandardActionState::PassiveStandB as u16).contains(&n)
+}
+
+fn is_in_hitstun(frame: &Frame) -> bool {
+    frame.state.is_hitstun() || frame.state.broad_state() == BroadState::Standard(StandardBroadState::Hitstun)
+}
+
+fn is_shielding(frame: &Frame, action: Option<HighLevelAction>) -> bool {
+    frame.state.broad_state() == BroadState::Standard(StandardBroadState::Shield)
+        || action == Some(HighLevelAction::Shield)
+}
+
+fn is_attacking(frame: &Frame, action: Option<HighLevelAction>) -> bool {
+    use HighLevelAction as HLA;
+    matches!(frame.state.broad_state(), BroadState::Standard(StandardBroadState::Attack) | BroadState::Special(_))
+        || matches!(
+            action,
+            Some(HLA::GroundAttack(_) | HLA::Aerial(_) | HLA::JumpAerial(_) | HLA::FullhopAerial(_) | HLA::ShorthopAerial(_) | HLA::Grab | HLA::Special(_))
+        )
+}
+
+/// The action covering each frame.
+fn action_per_frame(actions: &[Action], len: usize) -> Vec<Option<HighLevelAction>> {
+    let mut per_frame = vec![None; len];
+    for action in actions {
+        let end = action.frame_end.min(len);
+        let start = action.frame_start.min(end);
+        per_frame[start..end].fill(Some(action.action_taken));
+    }
+    per_frame
+}
+
+/// Classifies a single frame from the player's point of view.
+fn classify(
+    geometry: &StageGeometry,
+    player: &Frame,
+    opponent: &Frame,
+    player_action: Option<HighLevelAction>,
+    opponent_action: Option<HighLevelAction>,
+) -> SituationKind {
+    use SituationKind::*;
+
+    let side = |player: &Frame, opponent: &Frame, player_action, opponent_action| -> Option<SituationKind> {
+        let player_offstage = geometry.is_offstage(player);
+        let opponent_offstage = geometry.is_offstage(opponent);
+        let opponent_punished = is_in_hitstun(opponent) && opponent.last_hit_by == player.port_idx;
+
+        if opponent_offstage && !player_offstage && !is_on_ledge(player, player_action) { return Some(Edgeguarding) }
+        if opponent_offstage && player_offstage && opponent_punished { return Some(Edgeguarding) }
+        if is_on_ledge(opponent, opponent_action) && !player_offstage && !is_on_ledge(player, player_action) { return Some(LedgeTrapping) }
+        if is_downed(opponent) && !is_downed(player) { return Some(TechChasing) }
+        if opponent_punished { return Some(Punishing) }
+        if is_shielding(opponent, opponent_action)
+            && is_attacking(player, player_action)
+            && (player.position.x - opponent.position.x).abs() < SHIELD_PRESSURE_DISTANCE
+        {
+            return Some(ShieldPressuring)
+        }
+        None
+    };
+
+    if is_dead(player) || is_dead(opponent) { return Dead }
+    if let Some(kind) = side(player, opponent, player_action, opponent_action) { return kind }
+    if let Some(kind) = side(opponent, player, opponent_action, player_action) { return kind.opposite() }
+    Neutral
+}
+
+/// Splits the game into situations from the player's point of view against one opponent.
+/// Returns an empty timeline if either port is unused or the stage is not a legal stage.
+pub fn classify_situations(game: &Game, player: usize, opponent: usize) -> Vec<Situation> {
+    let mut situations: Vec<Situation> = Vec::new();
+
+    let Some(geometry) = stage_geometry(game.info.stage) else { return situations };
+    let Some(player_frames) = game.frames.get(player).and_then(|f| f.as_deref()) else { return situations };
+    let Some(opponent_frames) = game.frames.get(opponent).and_then(|f| f.as_deref()) else { return situations };
+    let len = player_frames.len().min(opponent_frames.len());
+
+    let player_actions = action_per_frame(&parse_actions(player_frames), len);
+    let opponent_actions = action_per_frame(&parse_actions(opponent_frames), len);
+
+    for i in 0..len {
+        let kind = classify(&geometry, &player_frames[i], &opponent_frames[i], player_actions[i], opponent_actions[i]);
+        match situations.last_mut() {
+            Some(last) if last.kind == kind => last.frame_end = i+1,
+            _ => situations.push(Situation { kind, frame_start: i, frame_end: i+1 }),
+        }
+    }
+
+    situations
+}
+
+/// Total frames spent in each situation, in order of first occurrence.
+pub fn situation_totals(situations: &[Situation]) -> Vec<(SituationKind, usize)> {
+    let mut totals: Vec<(SituationKind, usize)> = Vec::new();
+    for s in situations {
+        match totals.iter_mut().find(|(kind, _)| *kind == s.kind) {
+            Some((_, total)) => *total += s.len(),
+            None => totals.push((s.kind, s.len())),
+        }
+    }
+    totals
+}
+
+#[test]
+fn offstage_from_geometry() {
+    let game = parse_file(&test_replay()).unwrap();
+    let bf = stage_geometry(Stage::Battlefield).unwrap();
+    let mut frame = test_frames(&game, 0, 1).remove(0);
+    assert!(!bf.is_offstage(&frame));
+
+    frame.position = Vector { x: 90.0, y: -10.0 };
+    assert!(!bf.is_offstage(&frame));
+    frame.is_airborne = true;
+    assert!(bf.is_offstage(&frame));
+    frame.position = Vector { x: 0.0, y: -10.0 };
+    assert!(bf.is_offstage(&frame));
+    frame.position = Vector { x: 60.0, y: 20.0 };
+    assert!(!bf.is_offstage(&frame));
+    assert_eq!(stage_geometry(Stage::Brinstar), None);
+}
+
+#[cfg(test)]
+fn edgeguard_game() -> Game {
+    let mut game = parse_file(&test_replay()).unwrap();
+    assert_eq!(game.info.stage, Stage::Battlefield);
+    let mut p0 = test_frames(&game, 0, 30);
+    let mut p1 = test_frames(&game, 1, 30);
+
+    // port 1 is launched offstage, grabs the ledge, then is back in neutral
+    for f in p1[10..20].iter_mut() {
+        f.is_airborne = true;
+        f.position = Vector { x: 90.0, y: -10.0 };
+        f.state = ActionState::Standard(StandardActionState::Fall);
+    }
+    for f in p1[20..25].iter_mut() {
+        f.position = Vector { x: 68.4, y: -10.0 };
+        f.state = ActionState::Standard(StandardActionState::CliffWait);
+    }
+    p0[0].state = ActionState::Standard(StandardActionState::DeadDown);
+
+    game.frames[0] = Some(p0.into_boxed_slice());
+    game.frames[1] = Some(p1.into_boxed_slice());
+    game
+}
+
+#[test]
+fn classify_edgeguard_and_ledge() {
+    let game = edgeguard_game();
+    let situations = classify_situations(&game, 0, 1);
+    let kinds: Vec<_> = situations.iter().map(|s| (s.kind, s.frame_start, s.frame_end)).collect();
+    assert_eq!(kinds, vec![
+        (SituationKind::Dead, 0, 1),
+        (SituationKind::Neutral, 1, 10),
+        (SituationKind::Edgeguarding, 10, 20),
+        (SituationKind::LedgeTrapping, 20, 25),
+        (SituationKind::Neutral, 25, 30),
+    ]);
+
+    let opposite: Vec<_> = classify_situations(&game, 1, 0).iter().map(|s| s.kind).collect();
+    assert_eq!(opposite[2], SituationKind::Recovering);
+    assert_eq!(opposite[3], SituationKind::OnLedge);
+    assert!(classify_situations(&game, 0, 2).is_empty());
+}
+
+#[test]
+fn respawning_until_landing() {
+    let mut game = edgeguard_game();
+
+    // port 0 falls from the revival platform and first lands on frame 5
+    let mut p0 = game.frames[0].as_ref().unwrap().to_vec();
+    for f in p0[..5].iter_mut() {
+        f.state = ActionState::Standard(StandardActionState::Fall);
+        f.is_airborne = true;
+        f.position = Vector { x: 0.0, y: 100.0 };
+        f.last_ground_idx = NO_GROUND_IDX;
+    }
+    game.frames[0] = Some(p0.into_boxed_slice());
+
+    let situations = classify_situations(&game, 0, 1);
+    assert_eq!(situations[0], Situation { kind: SituationKind::Dead, frame_start: 0, frame_end: 5 });
+    assert_eq!(situations[1].kind, SituationKind::Neutral);
+}
+
+#[test]
+fn situation_totals_per_kind() {
+    let situations = classify_situations(&edgeguard_game(), 0, 1);
+    let totals = situation_totals(&situations);
+    assert_eq!(totals, vec![
+        (SituationKind::Dead, 1),
+        (SituationKind::Neutral, 14),
+        (SituationKind::Edgeguarding, 10),
+        (SituationKind::LedgeTrapping, 5),
+    ]);
+}
@@ -0,0 +1,238 @@
+use crate::{ActionState, SpecialActionState, StandardActionState, Character, Frame};
+use super::special_states::*;
+
+// Coverage: only Fox and Marth have tables, with their jabs, tilts, smashes, dash attack, aerials and grabs.
+// Of the special moves only Fox's shine startup is listed. The rest of the cast is out of scope until
+// their tables are checked against replays, see `FRAME_DATA_CHARACTERS`.
+// Every lookup for anything else returns None, so callers can tell missing data from a negative answer.
+//
+// HOW TO ADD: copy the move's frames from https://meleeframedata.com
+// frames are numbered from 1, matching `Frame::anim_frame` in attack states.
+// total is the animation length, so the first actionable frame is total+1 unless iasa is set.
+// only the landing lag is listed, L-cancelled lag is always half of it.
+
+/// Inclusive range of animation frames, numbered from 1.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FrameWindow {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl FrameWindow {
+    pub fn contains(self, frame: u16) -> bool {
+        self.start <= frame && frame <= self.end
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FrameData {
+    pub total_frames: u16,
+    /// Frames with an active hitbox. Multi-hit moves have multiple windows.
+    pub active: &'static [FrameWindow],
+    /// First frame the move can be interrupted, if before the end of the animation.
+    pub iasa: Option<u16>,
+    /// Aerials only.
+    pub landing_lag: Option<u16>,
+    /// Aerials only.
+    pub l_cancel_lag: Option<u16>,
+    /// Aerials only. Landing on these frames has normal landing lag instead of `landing_lag`.
+    pub auto_cancel: &'static [FrameWindow],
+}
+
+impl FrameData {
+    pub fn first_actionable_frame(&self) -> u16 {
+        self.iasa.unwrap_or(self.total_frames + 1)
+    }
+
+    pub fn is_active(&self, frame: u16) -> bool {
+        self.active.iter().any(|w| w.contains(frame))
+    }
+
+    pub fn is_auto_cancel(&self, frame: u16) -> bool {
+        self.auto_cancel.iter().any(|w| w.contains(frame))
+    }
+}
+
+const fn half(lag: Option<u16>) -> Option<u16> {
+    match lag {
+        Some(lag) => Some(lag / 2),
+        None => None,
+    }
+}
+
+macro_rules! frame_data {
+    (
+        $table:ident, $char:ident, $sas:ident,
+        { $($st:ident => $total:expr, [$($as:literal-$ae:literal),*], $iasa:expr, $landing:expr, [$($cs:literal-$ce:literal),*]);*$(;)? }
+        $(, { $($sp:ident => $sp_total:expr, [$($sp_as:literal-$sp_ae:literal),*], $sp_iasa:expr);*$(;)? })?
+    ) => {
+        static $table: &[(ActionState, FrameData)] = &[
+            $((
+                ActionState::Standard(StandardActionState::$st),
+                FrameData {
+                    total_frames: $total,
+                    active: &[$(FrameWindow { start: $as, end: $ae }),*],
+                    iasa: $iasa,
+                    landing_lag: $landing,
+                    l_cancel_lag: half($landing),
+                    auto_cancel: &[$(FrameWindow { start: $cs, end: $ce }),*],
+                },
+            ),)*
+            $($((
+                ActionState::Special(SpecialActionState::$char($sas::$sp)),
+                FrameData {
+                    total_frames: $sp_total,
+                    active: &[$(FrameWindow { start: $sp_as, end: $sp_ae }),*],
+                    iasa: $sp_iasa,
+                    landing_lag: None,
+                    l_cancel_lag: None,
+                    auto_cancel: &[],
+                },
+            ),)*)?
+        ];
+    }
+}
+
+frame_data! {
+    FOX, Fox, SpecialActionStateFox,
+    {
+        // state      total  active                                    iasa      landing    auto cancel
+        Attack11    => 17, [2-3],                                      None,     None,      [];
+        Attack12    => 20, [2-3],                                      None,     None,      [];
+        AttackDash  => 39, [4-17],                                     None,     None,      [];
+        AttackS3Hi  => 26, [5-8],                                      None,     None,      [];
+        AttackS3HiS => 26, [5-8],                                      None,     None,      [];
+        AttackS3S   => 26, [5-8],                                      None,     None,      [];
+        AttackS3LwS => 26, [5-8],                                      None,     None,      [];
+        AttackS3Lw  => 26, [5-8],                                      None,     None,      [];
+        AttackHi3   => 23, [5-11],                                     None,     None,      [];
+        AttackLw3   => 27, [7-9],                                      None,     None,      [];
+        AttackS4Hi  => 39, [12-22],                                    None,     None,      [];
+        AttackS4HiS => 39, [12-22],                                    None,     None,      [];
+        AttackS4S   => 39, [12-22],                                    None,     None,      [];
+        AttackS4LwS => 39, [12-22],                                    None,     None,      [];
+        AttackS4Lw  => 39, [12-22],                                    None,     None,      [];
+        AttackHi4   => 41, [7-17],                                     None,     None,      [];
+        AttackLw4   => 49, [6-9],                                      None,     None,      [];
+        AttackAirN  => 49, [4-31],                                     Some(42), Some(15), [1-3, 42-49];
+        AttackAirF  => 59, [6-8, 16-18, 24-26, 33-35, 43-45],          Some(54), Some(22), [1-5, 54-59];
+        AttackAirB  => 39, [4-19],                                     Some(25), Some(20), [1-3, 25-39];
+        AttackAirHi => 39, [8-9, 11-14],                               Some(29), Some(18), [1-7, 29-39];
+        AttackAirLw => 49, [5-24],                                     Some(36), Some(18), [1-4, 36-49];
+        Catch       => 30, [7-8],                                      None,     None,      [];
+        CatchDash   => 40, [11-12],                                    None,     None,      [];
+    },
+    {
+        ReflectorGroundStartup => 3, [1-1], None;
+        ReflectorAirStartup    => 3, [1-1], None;
+    }
+}
+
+frame_data! {
+    MARTH, Marth, SpecialActionStateMarth,
+    {
+        // state      total  active                                    iasa      landing    auto cancel
+        Attack11    => 20, [4-7],                                      None,     None,      [];
+        Attack12    => 20, [4-7],                                      None,     None,      [];
+        AttackDash  => 39, [12-15],                                    None,     None,      [];
+        AttackS3Hi  => 35, [7-10],                                     None,     None,      [];
+        AttackS3HiS => 35, [7-10],                                     None,     None,      [];
+        AttackS3S   => 35, [7-10],                                     None,     None,      [];
+        AttackS3LwS => 35, [7-10],                                     None,     None,      [];
+        AttackS3Lw  => 35, [7-10],                                     None,     None,      [];
+        AttackHi3   => 31, [6-13],                                     None,     None,      [];
+        AttackLw3   => 19, [7-9],                                      None,     None,      [];
+        AttackS4Hi  => 47, [10-13],                                    None,     None,      [];
+        AttackS4HiS => 47, [10-13],                                    None,     None,      [];
+        AttackS4S   => 47, [10-13],                                    None,     None,      [];
+        AttackS4LwS => 47, [10-13],                                    None,     None,      [];
+        AttackS4Lw  => 47, [10-13],                                    None,     None,      [];
+        AttackHi4   => 45, [13-16],                                    None,     None,      [];
+        AttackLw4   => 60, [6-7, 21-22],                               None,     None,      [];
+        AttackAirN  => 49, [6-7, 15-21],                               Some(33), Some(15), [1-5, 33-49];
+        AttackAirF  => 35, [4-7],                                      Some(27), Some(15), [1-3, 27-35];
+        AttackAirB  => 39, [7-11],                                     Some(32), Some(15), [1-6, 32-39];
+        AttackAirHi => 39, [5-8],                                      Some(30), Some(15), [1-4, 30-39];
+        AttackAirLw => 49, [6-9],                                      None,     Some(28), [1-5, 48-49];
+        Catch       => 30, [7-8],                                      None,     None,      [];
+        CatchDash   => 40, [10-11],                                    None,     None,      [];
+    }
+}
+
+/// Characters with a frame data table. Every other character, including most of the legal
+/// tournament cast, has no frame data and every lookup for them returns None.
+pub const FRAME_DATA_CHARACTERS: &[Character] = &[Character::Fox, Character::Marth];
+
+pub fn has_frame_data(character: Character) -> bool {
+    FRAME_DATA_CHARACTERS.contains(&character)
+}
+
+fn table(character: Character) -> &'static [(ActionState, FrameData)] {
+    match character {
+        Character::Fox   => FOX,
+        Character::Marth => MARTH,
+        _ => &[],
+    }
+}
+
+/// Frame data for a character's action state.
+/// Returns None if the state is not an attack, or has no data. See `FRAME_DATA_CHARACTERS` for coverage.
+pub fn frame_data(character: Character, state: ActionState) -> Option<&'static FrameData> {
+    table(character).iter().find(|(st, _)| *st == state).map(|(_, data)| data)
+}
+
+// animation frame of an attack state, numbered from 1
+fn attack_frame(frame: &Frame) -> u16 {
+    frame.anim_frame.max(0.0) as u16
+}
+
+/// Whether the frame's attack has an active hitbox.
+/// Returns None if there is no frame data for the state.
+pub fn is_active_frame(frame: &Frame) -> Option<bool> {
+    frame_data(frame.character, frame.state).map(|data| data.is_active(attack_frame(frame)))
+}
+
+/// Frames until the first actionable frame of the current attack, not counting hitlag.
+/// Zero if the attack can already be interrupted.
+///
+/// Returns None if there is no frame data for the state.
+/// Landing lag states are not numbered by frame, use `FrameData::landing_lag` for those.
+pub fn frames_until_actionable(frame: &Frame) -> Option<u16> {
+    let data = frame_data(frame.character, frame.state)?;
+    Some(data.first_actionable_frame().saturating_sub(attack_frame(frame)))
+}
+
+#[test]
+fn frame_data_lookup() {
+    let game = crate::parse_file(&crate::test_replay()).unwrap();
+    let mut frame = game.frames[0].as_ref().unwrap()[0].clone();
+    frame.character = Character::Fox;
+
+    frame.state = ActionState::Standard(StandardActionState::AttackAirN);
+    frame.anim_frame = 4.0;
+    assert_eq!(is_active_frame(&frame), Some(true));
+    assert_eq!(frames_until_actionable(&frame), Some(38));
+
+    let data = frame_data(Character::Fox, frame.state).unwrap();
+    assert_eq!((data.landing_lag, data.l_cancel_lag), (Some(15), Some(7)));
+    assert!(data.is_auto_cancel(45) && !data.is_auto_cancel(20));
+
+    frame.state = ActionState::Standard(StandardActionState::AttackAirB);
+    frame.anim_frame = 30.0;
+    assert_eq!(is_active_frame(&frame), Some(false));
+    assert_eq!(frames_until_actionable(&frame), Some(0));
+
+    frame.state = ActionState::Special(SpecialActionState::Fox(SpecialActionStateFox::ReflectorGroundStartup));
+    frame.anim_frame = 1.0;
+    assert_eq!(is_active_frame(&frame), Some(true));
+
+    frame.state = ActionState::Standard(StandardActionState::Wait);
+    assert_eq!(frames_until_actionable(&frame), None);
+    assert_eq!(is_active_frame(&frame), None);
+
+    assert!(has_frame_data(Character::Marth) && !has_frame_data(Character::Pichu));
+    frame.character = Character::Pichu;
+    frame.state = ActionState::Standard(StandardActionState::AttackAirN);
+    assert_eq!(is_active_frame(&frame), None);
+    assert!(frame_data(Character::Pichu, frame.state).is_none());
+}
@@ -1,6 +1,9 @@
 mod special_states;
 pub use special_states::*;
 
+mod frame_data;
+pub use frame_data::*;
+
 use crate::{SlpResult, SlpError, Character, InvalidLocation};
 
 // It is very useful to abstract away special moves from standard moves, 
@@ -82,6 +85,18 @@ pub enum HighLevelAction {
     Crouch,
     Hitstun,
     Walljump,
+    TechInPlace,
+    TechRollLeft,
+    TechRollRight,
+    WallTech,
+    WallTechJump,
+    CeilingTech,
+    /// Knocked down without teching, until the getup option.
+    MissedTech,
+    GetUp,
+    GetUpAttack,
+    GetUpRollLeft,
+    GetUpRollRight,
     Special(SpecialHighLevelAction),
 }
 
@@ -1815,6 +1830,17 @@ impl HighLevelAction {
             63 => HLA::Hitstun,
             64 => HLA::Walljump,
             65 => HLA::Dead,
+            66 => HLA::TechInPlace,
+            67 => HLA::TechRollLeft,
+            68 => HLA::TechRollRight,
+            69 => HLA::WallTech,
+            70 => HLA::WallTechJump,
+            71 => HLA::CeilingTech,
+            72 => HLA::MissedTech,
+            73 => HLA::GetUp,
+            74 => HLA::GetUpAttack,
+            75 => HLA::GetUpRollLeft,
+            76 => HLA::GetUpRollRight,
             ..Self::MAX_VALUE => return None,
             _ => HLA::Special(SpecialHighLevelAction::from_u16(c, n - Self::MAX_VALUE)?),
         })
@@ -1896,6 +1922,17 @@ impl HighLevelAction {
             HLA::Hitstun => 63,
             HLA::Walljump => 64,
             HLA::Dead => 65,
+            HLA::TechInPlace => 66,
+            HLA::TechRollLeft => 67,
+            HLA::TechRollRight => 68,
+            HLA::WallTech => 69,
+            HLA::WallTechJump => 70,
+            HLA::CeilingTech => 71,
+            HLA::MissedTech => 72,
+            HLA::GetUp => 73,
+            HLA::GetUpAttack => 74,
+            HLA::GetUpRollLeft => 75,
+            HLA::GetUpRollRight => 76,
 
             HLA::Special(s) => Self::MAX_VALUE + s.as_u16(), // TODO not backwards compatible
         }
@@ -2081,6 +2118,17 @@ impl fmt::Display for HighLevelAction {
             Crouch => write!(f, "Crouch"),
             Hitstun => write!(f, "In hit"),
             Walljump => write!(f, "Walljump"),
+            TechInPlace => write!(f, "Tech in place"),
+            TechRollLeft => write!(f, "Tech roll left"),
+            TechRollRight => write!(f, "Tech roll right"),
+            WallTech => write!(f, "Wall tech"),
+            WallTechJump => write!(f, "Wall tech jump"),
+            CeilingTech => write!(f, "Ceiling tech"),
+            MissedTech => write!(f, "Missed tech"),
+            GetUp => write!(f, "Getup"),
+            GetUpAttack => write!(f, "Getup attack"),
+            GetUpRollLeft => write!(f, "Getup roll left"),
+            GetUpRollRight => write!(f, "Getup roll right"),
             Special(s) => write!(f, "{}", s),
         }
     }
@@ -0,0 +1,527 @@
+use crate::*;
+
+/// A conversion ends after the victim has been out of hitstun and not hit for this many frames.
+pub const PUNISH_RESET_FRAMES: u32 = 45;
+
+/// Frame index of the first frame players can act on (frame -39).
+pub const FIRST_PLAYABLE_FRAME_IDX: usize = 84;
+
+/// Stick deadzone used when counting stick inputs.
+pub const STICK_INPUT_DEADZONE: f32 = 0.2875;
+
+/// Analog trigger value counted as a trigger press.
+pub const TRIGGER_INPUT_THRESHOLD: f32 = 0.3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OpeningType {
+    /// Neither player was punishing the other.
+    NeutralWin,
+    /// The attacker hit back while being punished by the victim.
+    CounterHit,
+    /// Both players hit each other on the same frame.
+    Trade,
+}
+
+/// A string of hits on one player by another,
+/// ending on death or after the victim has been free for `PUNISH_RESET_FRAMES`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Conversion {
+    pub attacker: u8,
+    pub victim: u8,
+    /// Index of the frame the first hit landed.
+    pub frame_start: usize,
+    /// Index of the frame after the conversion ended.
+    pub frame_end: usize,
+    pub start_percent: f32,
+    pub end_percent: f32,
+    /// Number of frames on which the victim took damage.
+    pub hits: u32,
+    pub did_kill: bool,
+    pub opening: OpeningType,
+}
+
+impl Conversion {
+    pub fn damage(&self) -> f32 { self.end_percent - self.start_percent }
+}
+
+/// Per player statistics for a single game. Ratios are None if there is nothing to compute them from.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct GameStats {
+    /// Button presses, stick and c-stick region changes, and analog trigger presses from frame -39.
+    pub inputs: u32,
+    /// Button presses only.
+    pub digital_inputs: u32,
+    pub inputs_per_minute: f32,
+    pub digital_inputs_per_minute: f32,
+
+    /// `HighLevelAction`s, excluding waiting, hitstun and death.
+    pub actions: u32,
+    pub actions_per_minute: f32,
+
+    /// Zero unless parsed with `parse_file_ext`.
+    pub l_cancels_successful: u32,
+    /// Zero unless parsed with `parse_file_ext`.
+    pub l_cancels_missed: u32,
+    pub l_cancel_rate: Option<f32>,
+
+    /// Conversions started on an opponent.
+    pub openings: u32,
+    /// Openings that hit more than once or killed.
+    pub successful_conversions: u32,
+    pub conversion_rate: Option<f32>,
+    /// Opponent stocks lost that were last hit by this player, or that were lost during this player's conversion.
+    pub kills: u32,
+    /// Stocks lost without being killed by an opponent.
+    pub self_destructs: u32,
+    pub openings_per_kill: Option<f32>,
+    pub damage_per_opening: Option<f32>,
+
+    pub neutral_wins: u32,
+    /// Neutral wins over all neutral wins by and against this player.
+    pub neutral_win_ratio: Option<f32>,
+    pub counter_hits: u32,
+    /// Counter hits over all counter hits by and against this player.
+    pub counter_hit_ratio: Option<f32>,
+    pub trades: u32,
+    /// Trades where this player killed and the opponent did not, or did more damage.
+    pub beneficial_trades: u32,
+    pub beneficial_trade_ratio: Option<f32>,
+
+    pub damage_done: f32,
+    pub damage_taken: f32,
+}
+
+fn ratio(n: u32, d: u32) -> Option<f32> {
+    if d == 0 { None } else { Some(n as f32 / d as f32) }
+}
+
+fn are_opponents(info: &GameInfo, a: usize, b: usize) -> bool {
+    a != b && info.teams[a] != info.teams[b]
+}
+
+/// The port responsible for the victim's state on this frame.
+///
+/// `last_hit_by` is not set for grabs and throws,
+/// so hitstun without one is attributed to the only opponent if there is exactly one.
+pub(crate) fn responsible_port(game: &Game, victim: usize, frame: &Frame) -> Option<usize> {
+    let last_hit_by = frame.last_hit_by as usize;
+    if last_hit_by < 4 {
+        return are_opponents(&game.info, last_hit_by, victim).then_some(last_hit_by);
+    }
+
+    if frame.state.broad_state() != BroadState::Standard(StandardBroadState::Hitstun) { return None }
+    let mut opponents = (0..4).filter(|&p| game.frames[p].is_some() && are_opponents(&game.info, p, victim));
+    let opponent = opponents.next()?;
+    if opponents.next().is_some() { return None }
+    Some(opponent)
+}
+
+/// Finds every conversion in the game, sorted by start frame.
+pub fn compute_conversions(game: &Game) -> Vec<Conversion> {
+    struct Active {
+        conversion: Conversion,
+        reset_counter: u32,
+    }
+
+    let mut conversions = Vec::new();
+    // indexed by [victim][attacker]
+    let mut active: [[Option<Active>; 4]; 4] = Default::default();
+
+    for (victim, victim_active) in active.iter_mut().enumerate() {
+        let Some(frames) = game.frames[victim].as_deref() else { continue };
+
+        for i in 1..frames.len() {
+            let prev = &frames[i-1];
+            let frame = &frames[i];
+
+            let stock_lost = died(prev, frame);
+            let damaged = frame.percent > prev.percent && !stock_lost;
+            let responsible = responsible_port(game, victim, frame);
+            let in_hitstun = frame.state.broad_state() == BroadState::Standard(StandardBroadState::Hitstun)
+                || frame.hitlag_frames > 0.0;
+
+            for (attacker, slot) in victim_active.iter_mut().enumerate() {
+                if game.frames[attacker].is_none() || !are_opponents(&game.info, attacker, victim) { continue }
+                let attributed = responsible == Some(attacker);
+
+                if slot.is_none() && damaged && attributed {
+                    *slot = Some(Active {
+                        conversion: Conversion {
+                            attacker: attacker as u8,
+                            victim: victim as u8,
+                            frame_start: i,
+                            frame_end: i+1,
+                            start_percent: prev.percent,
+                            end_percent: frame.percent,
+                            hits: 0,
+                            did_kill: false,
+                            opening: OpeningType::NeutralWin,
+                        },
+                        reset_counter: 0,
+                    });
+                }
+
+                let Some(a) = slot.as_mut() else { continue };
+
+                if stock_lost {
+                    a.conversion.did_kill = true;
+                    a.conversion.frame_end = i;
+                    conversions.push(a.conversion);
+                    *slot = None;
+                    continue;
+                }
+
+                if damaged && attributed {
+                    a.conversion.hits += 1;
+                    a.conversion.end_percent = frame.percent;
+                    a.reset_counter = 0;
+                } else if in_hitstun && attributed {
+                    a.reset_counter = 0;
+                } else {
+                    a.reset_counter += 1;
+                }
+
+                if a.reset_counter > PUNISH_RESET_FRAMES {
+                    a.conversion.frame_end = i + 1 - a.reset_counter as usize;
+                    conversions.push(a.conversion);
+                    *slot = None;
+                } else {
+                    a.conversion.frame_end = i + 1;
+                }
+            }
+        }
+    }
+
+    for a in active.into_iter().flatten().flatten() {
+        conversions.push(a.conversion);
+    }
+
+    conversions.sort_by_key(|c| (c.frame_start, c.attacker, c.victim));
+
+    let openings: Vec<OpeningType> = conversions.iter().map(|c| {
+        let reverse = |d: &&Conversion| d.attacker == c.victim && d.victim == c.attacker;
+        if conversions.iter().filter(reverse).any(|d| d.frame_start == c.frame_start) {
+            OpeningType::Trade
+        } else if conversions.iter().filter(reverse).any(|d| d.frame_start < c.frame_start && c.frame_start < d.frame_end) {
+            OpeningType::CounterHit
+        } else {
+            OpeningType::NeutralWin
+        }
+    }).collect();
+
+    for (c, opening) in conversions.iter_mut().zip(openings) {
+        c.opening = opening;
+    }
+
+    conversions
+}
+
+fn is_active_action(action: HighLevelAction) -> bool {
+    !matches!(
+        action,
+        HighLevelAction::Dead
+            | HighLevelAction::GroundWait
+            | HighLevelAction::AirWait
+            | HighLevelAction::LedgeWait
+            | HighLevelAction::Hitstun
+    )
+}
+
+/// Where a player died, from their dead action state.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BlastZone {
+    Bottom,
+    Left,
+    Right,
+    /// Includes star and screen KOs.
+    Top,
+}
+
+impl BlastZone {
+    pub fn from_state(state: ActionState) -> Option<BlastZone> {
+        use StandardActionState as SAS;
+        Some(match state {
+            ActionState::Standard(SAS::DeadDown) => BlastZone::Bottom,
+            ActionState::Standard(SAS::DeadLeft) => BlastZone::Left,
+            ActionState::Standard(SAS::DeadRight) => BlastZone::Right,
+            ActionState::Standard(
+                SAS::DeadUp
+                | SAS::DeadUpStar
+                | SAS::DeadUpStarIce
+                | SAS::DeadUpFall
+                | SAS::DeadUpFallHitCamera
+                | SAS::DeadUpFallHitCameraFlat
+                | SAS::DeadUpFallIce
+                | SAS::DeadUpFallHitCameraIce
+            ) => BlastZone::Top,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct StockEvent {
+    /// Index of the first frame in the dead state.
+    pub frame_idx: usize,
+    pub blast_zone: BlastZone,
+    /// The dead state, to tell star and screen KOs apart.
+    pub death_state: ActionState,
+    /// The port that last hit the victim. Can be a teammate if friendly fire is on.
+    /// None if the victim was not hit this stock, or was last hit by a grab or throw.
+    pub killer: Option<u8>,
+    /// The killer's last move to damage the victim this stock.
+    pub killing_move: Option<AttackKind>,
+    pub percent: f32,
+    pub stocks_remaining: u8,
+}
+
+/// True on the first frame of a death.
+/// Only dead action states count, so a stock lost by giving it to a teammate in teams is not a death.
+pub(crate) fn died(prev: &Frame, frame: &Frame) -> bool {
+    BlastZone::from_state(frame.state).is_some() && BlastZone::from_state(prev.state).is_none()
+}
+
+/// Computes stats for every used port.
+pub fn compute_game_stats(game: &Game) -> [Option<GameStats>; 4] {
+    let conversions = compute_conversions(game);
+    let mut stats: [Option<GameStats>; 4] = [None; 4];
+
+    for (port, port_stats) in stats.iter_mut().enumerate() {
+        let Some(frames) = game.frames[port].as_deref() else { continue };
+        let mut s = GameStats::default();
+
+        // inputs -------------------------------------------
+
+        let playable = frames.get(FIRST_PLAYABLE_FRAME_IDX.saturating_sub(1)..).unwrap_or(&[]);
+        for w in playable.windows(2) {
+            let (prev, frame) = (&w[0], &w[1]);
+            let pressed = (frame.buttons() & !prev.buttons()).bits().count_ones();
+            s.digital_inputs += pressed;
+            s.inputs += pressed;
+            if StickRegion::from_stick(frame.left_stick_coords) != StickRegion::from_stick(prev.left_stick_coords) { s.inputs += 1; }
+            if StickRegion::from_stick(frame.right_stick_coords) != StickRegion::from_stick(prev.right_stick_coords) { s.inputs += 1; }
+            if frame.analog_trigger_value >= TRIGGER_INPUT_THRESHOLD && prev.analog_trigger_value < TRIGGER_INPUT_THRESHOLD {
+                s.inputs += 1;
+            }
+        }
+
+        s.actions = parse_actions(frames).iter().filter(|a| is_active_action(a.action_taken)).count() as u32;
+
+        let minutes = frames.len().saturating_sub(FIRST_PLAYABLE_FRAME_IDX) as f32 / 3600.0;
+        if minutes > 0.0 {
+            s.inputs_per_minute = s.inputs as f32 / minutes;
+            s.digital_inputs_per_minute = s.digital_inputs as f32 / minutes;
+            s.actions_per_minute = s.actions as f32 / minutes;
+        }
+
+        // l cancels ----------------------------------------
+
+        if let Some(ext) = game.frames_ext[port].as_deref() {
+            for f in ext {
+                match f.l_cancel_status {
+                    LCancelStatus::Successful => s.l_cancels_successful += 1,
+                    LCancelStatus::Unsuccessful => s.l_cancels_missed += 1,
+                    LCancelStatus::None => (),
+                }
+            }
+        }
+        s.l_cancel_rate = ratio(s.l_cancels_successful, s.l_cancels_successful + s.l_cancels_missed);
+
+        // damage -------------------------------------------
+
+        for w in frames.windows(2) {
+            let (prev, frame) = (&w[0], &w[1]);
+            if died(prev, frame) || frame.percent <= prev.percent { continue }
+            s.damage_taken += frame.percent - prev.percent;
+        }
+
+        *port_stats = Some(s);
+    }
+
+    // damage done and kills --------------------------
+
+    for port in 0..4 {
+        let Some(frames) = game.frames[port].as_deref() else { continue };
+        for w in frames.windows(2) {
+            let (prev, frame) = (&w[0], &w[1]);
+            if died(prev, frame) || frame.percent <= prev.percent { continue }
+            let Some(attacker) = responsible_port(game, port, frame) else { continue };
+            if let Some(s) = stats[attacker].as_mut() { s.damage_done += frame.percent - prev.percent; }
+        }
+    }
+
+    for (port, port_events) in game.stock_events().iter().enumerate() {
+        for event in port_events {
+            // falling back to the conversion catches deaths after grabs and throws, which do not set last_hit_by
+            let killer = event.killer
+                .map(|k| k as usize)
+                .filter(|&k| are_opponents(&game.info, k, port))
+                .or_else(|| conversions.iter()
+                    .find(|c| c.victim as usize == port && c.did_kill && c.frame_end == event.frame_idx)
+                    .map(|c| c.attacker as usize));
+
+            match killer.and_then(|k| stats[k].as_mut()) {
+                Some(s) => s.kills += 1,
+                None => if let Some(s) = stats[port].as_mut() { s.self_destructs += 1; },
+            }
+        }
+    }
+
+    // conversions --------------------------------------
+
+    for c in conversions.iter() {
+        if let Some(s) = stats[c.attacker as usize].as_mut() {
+            s.openings += 1;
+            if c.hits > 1 || c.did_kill { s.successful_conversions += 1; }
+            match c.opening {
+                OpeningType::NeutralWin => s.neutral_wins += 1,
+                OpeningType::CounterHit => s.counter_hits += 1,
+                OpeningType::Trade => {
+                    s.trades += 1;
+                    let other = conversions.iter().find(|d| {
+                        d.attacker == c.victim && d.victim == c.attacker && d.frame_start == c.frame_start
+                    });
+                    if let Some(other) = other {
+                        let beneficial = (c.did_kill && !other.did_kill)
+                            || (c.did_kill == other.did_kill && c.damage() > other.damage());
+                        if beneficial { s.beneficial_trades += 1; }
+                    }
+                }
+            }
+        }
+    }
+
+    for (port, port_stats) in stats.iter_mut().enumerate() {
+        let Some(s) = port_stats.as_mut() else { continue };
+
+        let opening_damage: f32 = conversions.iter()
+            .filter(|c| c.attacker as usize == port)
+            .map(|c| c.damage())
+            .sum();
+
+        let against = |opening: OpeningType| conversions.iter()
+            .filter(|c| c.victim as usize == port && c.opening == opening)
+            .count() as u32;
+
+        s.conversion_rate = ratio(s.successful_conversions, s.openings);
+        s.openings_per_kill = ratio(s.openings, s.kills);
+        s.damage_per_opening = if s.openings == 0 { None } else { Some(opening_damage / s.openings as f32) };
+        s.neutral_win_ratio = ratio(s.neutral_wins, s.neutral_wins + against(OpeningType::NeutralWin));
+        s.counter_hit_ratio = ratio(s.counter_hits, s.counter_hits + against(OpeningType::CounterHit));
+        s.beneficial_trade_ratio = ratio(s.beneficial_trades, s.trades);
+    }
+
+    stats
+}
+
+#[cfg(test)]
+fn trade_and_kill_game() -> Game {
+    let mut game = parse_file(&test_replay()).unwrap();
+    let mut p0 = test_frames(&game, 0, 300);
+    let mut p1 = test_frames(&game, 1, 300);
+
+    // port 0 presses A and tilts the stick
+    for f in p0[100..].iter_mut() { f.buttons_mask = buttons_mask::A; }
+    for f in p0[120..].iter_mut() { f.left_stick_coords = Vector { x: 1.0, y: 0.0 }; }
+    for f in p0[250..].iter_mut() { f.last_hitting_attack_id = AttackKind::FAir; }
+
+    // port 0 hits port 1 twice from neutral
+    for f in p1[150..].iter_mut() { f.percent = 10.0; f.last_hit_by = 0; }
+    for f in p1[151..].iter_mut() { f.percent = 22.0; }
+
+    // trade, then port 0 kills
+    for f in p0[250..].iter_mut() { f.percent = 5.0; f.last_hit_by = 1; }
+    for f in p1[250..].iter_mut() { f.percent = 30.0; }
+    for f in p1[280..].iter_mut() { f.percent = 0.0; f.stock_count = 3; }
+    p1[280].percent = 30.0;
+    p1[280].state = ActionState::Standard(StandardActionState::DeadLeft);
+
+    game.frames[0] = Some(p0.into_boxed_slice());
+    game.frames[1] = Some(p1.into_boxed_slice());
+    game.frame_count = 300;
+    game
+}
+
+#[test]
+fn conversions_and_openings() {
+    let game = trade_and_kill_game();
+    let conversions = compute_conversions(&game);
+    assert_eq!(conversions.len(), 3);
+    assert_eq!((conversions[0].attacker, conversions[0].frame_start, conversions[0].frame_end), (0, 150, 152));
+    assert_eq!((conversions[0].hits, conversions[0].damage(), conversions[0].opening), (2, 22.0, OpeningType::NeutralWin));
+    assert_eq!(conversions[1].opening, OpeningType::Trade);
+    assert_eq!(conversions[2].opening, OpeningType::Trade);
+    assert!(conversions.iter().any(|c| c.attacker == 0 && c.did_kill && c.frame_end == 280));
+}
+
+#[test]
+fn game_stats_per_player() {
+    let game = trade_and_kill_game();
+    let [Some(s0), Some(s1), None, None] = compute_game_stats(&game) else { panic!() };
+    assert_eq!((s0.inputs, s0.digital_inputs), (2, 1));
+    assert_eq!((s0.openings, s0.successful_conversions, s0.kills), (2, 2, 1));
+    assert_eq!((s0.neutral_wins, s0.trades, s0.beneficial_trades), (1, 1, 1));
+    assert_eq!((s0.damage_done, s0.damage_taken), (30.0, 5.0));
+    assert_eq!(s0.neutral_win_ratio, Some(1.0));
+    assert_eq!(s0.damage_per_opening, Some(15.0));
+    assert_eq!(s0.l_cancel_rate, None);
+
+    assert_eq!((s1.openings, s1.kills, s1.self_destructs), (1, 0, 0));
+    assert_eq!((s1.damage_done, s1.damage_taken), (5.0, 30.0));
+    assert_eq!(s1.beneficial_trade_ratio, Some(0.0));
+}
+
+#[test]
+fn stock_events_from_deaths() {
+    let game = trade_and_kill_game();
+    let events = game.stock_events();
+    assert!(events[0].is_empty());
+    assert_eq!(events[1], vec![StockEvent {
+        frame_idx: 280,
+        blast_zone: BlastZone::Left,
+        death_state: ActionState::Standard(StandardActionState::DeadLeft),
+        killer: Some(0),
+        killing_move: Some(AttackKind::FAir),
+        percent: 30.0,
+        stocks_remaining: 3,
+    }]);
+}
+
+#[test]
+fn stock_events_in_teams() {
+    use StandardActionState as SAS;
+
+    let mut game = parse_file(&test_replay()).unwrap();
+    game.info.is_teams = true;
+    game.info.teams = [0, 1, 0, 1];
+    let mut frames: [Vec<Frame>; 4] = std::array::from_fn(|port| {
+        let mut frames = test_frames(&game, port % 2, 300);
+        for f in frames.iter_mut() { f.port_idx = port as u8; }
+        frames
+    });
+
+    // port 0 takes a stock from port 2 after being knocked out
+    for f in frames[0][100..].iter_mut() { f.percent = 80.0; f.last_hit_by = 1; }
+    frames[0][150].state = ActionState::Standard(SAS::DeadDown);
+    for f in frames[0][150..].iter_mut() { f.stock_count = 0; f.state = ActionState::Standard(SAS::DeadDown); }
+    for f in frames[0][200..].iter_mut() { f.stock_count = 1; f.percent = 0.0; f.state = ActionState::Standard(SAS::Rebirth); }
+    for f in frames[2][200..].iter_mut() { f.stock_count = 3; }
+
+    // port 1 is killed by their teammate with friendly fire
+    for f in frames[3][240..].iter_mut() { f.last_hitting_attack_id = AttackKind::FSmash; }
+    for f in frames[1][240..].iter_mut() { f.percent = 120.0; f.last_hit_by = 3; }
+    for f in frames[1][260..].iter_mut() { f.stock_count = 3; f.state = ActionState::Standard(SAS::DeadLeft); }
+
+    game.frames = frames.map(|f| Some(f.into_boxed_slice()));
+    let events = game.stock_events();
+
+    assert_eq!(events[0].len(), 1);
+    assert_eq!((events[0][0].frame_idx, events[0][0].killer), (150, Some(1)));
+    // the stock port 0 took is not a death for either of them
+    assert!(events[2].is_empty());
+    assert_eq!(
+        (events[1][0].frame_idx, events[1][0].killer, events[1][0].killing_move),
+        (260, Some(3), Some(AttackKind::FSmash)),
+    );
+    assert!(events[3].is_empty());
+}
@@ -0,0 +1,373 @@
+use crate::*;
+use crate::file_parser::{
+    EventSizes, PreFrameUpdate, PostFrameUpdate,
+    parse_pre_frame_update, parse_post_frame_update, merge_pre_post_frames,
+    read_i32,
+};
+
+#[derive(Clone, Debug)]
+pub enum SlpStreamEvent {
+    GameStart(Box<GameStart>),
+
+    /// Emitted before the frames of the frame it belongs to.
+    FrameStart {
+        frame_idx: u32,
+        frame_start: FrameStart,
+    },
+
+    /// A frame for a single port, emitted once it can no longer be changed by rollback.
+    /// Frames are emitted in order. Followers (Nana) are emitted after their leader.
+    Frame {
+        frame_idx: u32,
+        frame: Frame,
+    },
+
+    /// Emitted after the frames of the frame it belongs to.
+    ItemUpdate(ItemUpdate),
+
+    /// Always the last event. None if the metadata was reached without a game end event.
+    GameEnd(Option<GameEnd>),
+}
+
+/// Frames can be rolled back by at most this many frames.
+pub const MAX_ROLLBACK_FRAMES: u32 = 7;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum StreamState {
+    RawHeader,
+    EventSizes,
+    GameStart,
+    Events,
+    Finished,
+}
+
+#[derive(Clone, Debug)]
+struct PendingFrame {
+    frame_idx: u32,
+    frame_start: FrameStart,
+    frames: Vec<Frame>,
+    items: Vec<ItemUpdate>,
+}
+
+/// Push based parser for replays that are still being written, such as by Slippi Dolphin.
+///
+/// Feed bytes with `push` as they arrive. Frames are only emitted once they are finalized,
+/// so rollback is handled for you.
+pub struct SlpStreamParser {
+    state: StreamState,
+
+    buffer: Vec<u8>,
+    cursor: usize,
+    /// Absolute file offset of buffer[0].
+    buffer_offset: usize,
+    /// Absolute file offset of the metadata. Zero if not yet written.
+    metadata_offset: usize,
+
+    event_sizes: EventSizes,
+    game_start: Option<GameStart>,
+
+    // temp idx (port_idx + 4 if follower) of every character in the game
+    temp_idxs: [usize; 8],
+    temp_idx_count: usize,
+    pre_frame_temp: [PreFrameUpdate; 8],
+    post_frame_temp: [PostFrameUpdate; 8],
+    frame_start_temp: FrameStart,
+    items_temp: Vec<ItemUpdate>,
+
+    pending: Vec<PendingFrame>,
+}
+
+impl Default for SlpStreamParser {
+    fn default() -> Self { Self::new() }
+}
+
+impl SlpStreamParser {
+    pub fn new() -> Self {
+        SlpStreamParser {
+            state: StreamState::RawHeader,
+            buffer: Vec::new(),
+            cursor: 0,
+            buffer_offset: 0,
+            metadata_offset: 0,
+            event_sizes: [0; 256],
+            game_start: None,
+            temp_idxs: [0; 8],
+            temp_idx_count: 0,
+            pre_frame_temp: [PreFrameUpdate::NULL; 8],
+            post_frame_temp: [PostFrameUpdate::NULL; 8],
+            frame_start_temp: FrameStart::NULL,
+            items_temp: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn game_start(&self) -> Option<&GameStart> {
+        self.game_start.as_ref()
+    }
+
+    /// True after game end or the start of the metadata has been parsed.
+    /// Further bytes will be ignored.
+    pub fn finished(&self) -> bool {
+        self.state == StreamState::Finished
+    }
+
+    /// Parses as much of `bytes` as possible, appending new events to `events`.
+    /// Incomplete events are buffered until the next push.
+    pub fn push(&mut self, bytes: &[u8], events: &mut Vec<SlpStreamEvent>) -> SlpResult<()> {
+        if self.state == StreamState::Finished { return Ok(()) }
+
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.extend_from_slice(bytes);
+        let ret = self.parse_buffer(&buffer, events);
+
+        // discard parsed bytes
+        buffer.drain(..self.cursor);
+        self.buffer = buffer;
+        self.buffer_offset += self.cursor;
+        self.cursor = 0;
+
+        ret
+    }
+
+    fn parse_buffer(&mut self, buffer: &[u8], events: &mut Vec<SlpStreamEvent>) -> SlpResult<()> {
+        loop {
+            let remaining = &buffer[self.cursor..];
+
+            match self.state {
+                StreamState::RawHeader => {
+                    if (remaining.len() as u64) < HEADER_LEN { return Ok(()) }
+                    let RawHeaderRet { event_sizes_offset, metadata_offset } = parse_raw_header(remaining)?;
+                    self.metadata_offset = metadata_offset;
+                    self.cursor += event_sizes_offset;
+                    self.state = StreamState::EventSizes;
+                }
+                StreamState::EventSizes => {
+                    if remaining.len() < 2 { return Ok(()) }
+                    let info_size = remaining[1] as usize;
+                    if remaining.len() < info_size + 1 { return Ok(()) }
+
+                    let EventSizesRet { game_start_offset, event_sizes } = event_sizes(remaining, 0)?;
+                    self.event_sizes = event_sizes;
+                    self.cursor += game_start_offset;
+                    self.state = StreamState::GameStart;
+                }
+                StreamState::GameStart => {
+                    let game_start_size = self.event_sizes[GAME_START as usize] as usize + 1;
+                    if remaining.len() < game_start_size { return Ok(()) }
+
+                    let game_start = parse_game_start(&remaining[..game_start_size])?;
+                    self.cursor += game_start_size;
+
+                    self.temp_idx_count = 0;
+                    for i in 0..4 {
+                        if let Some(ch_colour) = game_start.starting_character_colours[i] {
+                            self.temp_idxs[self.temp_idx_count] = i;
+                            self.temp_idx_count += 1;
+
+                            if ch_colour.character() == Character::Popo {
+                                self.temp_idxs[self.temp_idx_count] = i + 4;
+                                self.temp_idx_count += 1;
+                            }
+                        }
+                    }
+
+                    self.game_start = Some(game_start);
+                    events.push(SlpStreamEvent::GameStart(Box::new(game_start)));
+                    self.state = StreamState::Events;
+                }
+                StreamState::Events => {
+                    if remaining.is_empty() { return Ok(()) }
+
+                    if self.metadata_offset != 0 && self.buffer_offset + self.cursor >= self.metadata_offset {
+                        // game end was not written, but we reached the metadata anyways.
+                        self.finish(None, events);
+                        return Ok(());
+                    }
+
+                    let event_cmd = remaining[0];
+                    let event_size = self.event_sizes[event_cmd as usize] as usize + 1;
+                    if remaining.len() < event_size { return Ok(()) }
+
+                    self.cursor += event_size;
+                    self.parse_event(event_cmd, &remaining[..event_size], events)?;
+                }
+                StreamState::Finished => return Ok(()),
+            }
+        }
+    }
+
+    fn parse_event(&mut self, event_cmd: u8, event_bytes: &[u8], events: &mut Vec<SlpStreamEvent>) -> SlpResult<()> {
+        match event_cmd {
+            FRAME_START => {
+                self.frame_start_temp = parse_frame_start(event_bytes)?;
+            }
+            ITEM_UPDATE => {
+                self.items_temp.push(parse_item_update(event_bytes)?);
+            }
+            PRE_FRAME_UPDATE => {
+                let pre_frame = parse_pre_frame_update(event_bytes)?;
+                let mut temp_idx = pre_frame.port_idx as usize;
+                if pre_frame.is_follower { temp_idx += 4 }
+                if temp_idx >= self.pre_frame_temp.len() {
+                    return Err(SlpError::InvalidFile(InvalidLocation::PreFrameUpdate));
+                }
+                self.pre_frame_temp[temp_idx] = pre_frame;
+            }
+            POST_FRAME_UPDATE => {
+                let post_frame = parse_post_frame_update(event_bytes)?;
+                let mut temp_idx = post_frame.port_idx as usize;
+                if post_frame.is_follower { temp_idx += 4 }
+                if temp_idx >= self.post_frame_temp.len() {
+                    return Err(SlpError::InvalidFile(InvalidLocation::PostFrameUpdate));
+                }
+                self.post_frame_temp[temp_idx] = post_frame;
+            }
+            FRAME_BOOKEND => {
+                let frame_idx = read_i32(event_bytes, 0x1).wrapping_add(123) as u32;
+
+                // The latest finalized frame was added in v3.7.0.
+                // For older versions, frames are held until they are past the rollback window.
+                let has_finalized_frame = self.game_start
+                    .map(|gs| (gs.version_major, gs.version_minor) >= (3, 7))
+                    .unwrap_or(false);
+                let finalized_idx = if has_finalized_frame {
+                    Some(read_i32(event_bytes, 0x5).wrapping_add(123) as u32)
+                } else {
+                    frame_idx.checked_sub(MAX_ROLLBACK_FRAMES)
+                };
+
+                let mut frames = Vec::with_capacity(self.temp_idx_count);
+                for &temp_idx in &self.temp_idxs[..self.temp_idx_count] {
+                    let pre = &self.pre_frame_temp[temp_idx];
+                    let post = &self.post_frame_temp[temp_idx];
+                    frames.push(merge_pre_post_frames(pre, post));
+                }
+
+                // handle rollback: throw away this frame and every frame after it
+                let rollback_idx = self.pending.iter()
+                    .position(|p| p.frame_idx >= frame_idx)
+                    .unwrap_or(self.pending.len());
+                self.pending.truncate(rollback_idx);
+
+                self.pending.push(PendingFrame {
+                    frame_idx,
+                    frame_start: self.frame_start_temp,
+                    frames,
+                    items: std::mem::take(&mut self.items_temp),
+                });
+
+                let finalized_count = match finalized_idx {
+                    Some(finalized_idx) => self.pending.iter()
+                        .position(|p| p.frame_idx > finalized_idx)
+                        .unwrap_or(self.pending.len()),
+                    None => 0,
+                };
+                for pending in self.pending.drain(..finalized_count) {
+                    emit_frame(pending, events);
+                }
+            }
+            GAME_END => {
+                let end = parse_game_end(event_bytes)?;
+                self.finish(Some(end), events);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, end: Option<GameEnd>, events: &mut Vec<SlpStreamEvent>) {
+        // nothing can be rolled back after the game ends
+        for pending in self.pending.drain(..) {
+            emit_frame(pending, events);
+        }
+
+        events.push(SlpStreamEvent::GameEnd(end));
+        self.state = StreamState::Finished;
+    }
+}
+
+fn emit_frame(pending: PendingFrame, events: &mut Vec<SlpStreamEvent>) {
+    let frame_idx = pending.frame_idx;
+    events.push(SlpStreamEvent::FrameStart { frame_idx, frame_start: pending.frame_start });
+    for frame in pending.frames {
+        events.push(SlpStreamEvent::Frame { frame_idx, frame });
+    }
+    for item in pending.items {
+        events.push(SlpStreamEvent::ItemUpdate(item));
+    }
+}
+
+#[test]
+fn stream_matches_parse_file() {
+    let slp = test_replay();
+    let game = parse_file(&slp).unwrap();
+
+    let mut parser = SlpStreamParser::new();
+    let mut events = Vec::new();
+    for b in slp.iter() {
+        parser.push(std::slice::from_ref(b), &mut events).unwrap();
+    }
+    assert!(parser.finished());
+
+    assert!(matches!(events[0], SlpStreamEvent::GameStart(ref gs) if gs.stage == Stage::Battlefield));
+    let expected_end = GameEnd { method: GameEndMethod::Game, lras_initiator: Some(1), placements: [None; 4] };
+    assert_eq!(game.info.end, Some(expected_end));
+    assert!(matches!(events[events.len()-1], SlpStreamEvent::GameEnd(Some(end)) if end == expected_end));
+
+    let mut frame_count = 0;
+    let mut item_spawn_ids = Vec::new();
+    for ev in events.iter() {
+        match ev {
+            SlpStreamEvent::Frame { frame_idx, frame } => {
+                let expected = &game.frames[frame.port_idx as usize].as_ref().unwrap()[*frame_idx as usize];
+                assert_eq!(frame.percent, expected.percent);
+                assert_eq!(frame.percent, *frame_idx as f32);
+                assert_eq!(frame.character, expected.character);
+                frame_count += 1;
+            }
+            SlpStreamEvent::FrameStart { frame_idx, frame_start } => {
+                assert_eq!(frame_start.random_seed, *frame_idx);
+                assert_eq!(game.frame_starts[*frame_idx as usize], *frame_start);
+            }
+            SlpStreamEvent::ItemUpdate(item) => item_spawn_ids.push(item.spawn_id),
+            _ => {}
+        }
+    }
+
+    assert_eq!(frame_count, 3 * 2);
+    assert_eq!(item_spawn_ids, game.items.iter().map(|i| i.spawn_id).collect::<Vec<_>>());
+    assert_eq!(item_spawn_ids, vec![0, 1, 2]);
+}
+
+#[test]
+fn stream_pre_v3_7_rollback() {
+    // frame -119 is sent wrong, then resent after -118 by rollback
+    let mut frames = Vec::new();
+    for frame in -123..-110 {
+        if frame == -119 { frames.push((-119, 0, 100.0)); frames.push((-118, 0, 100.0)); }
+        frames.push((frame, 0, (frame + 123) as f32));
+    }
+    let slp = test_replay_with([3, 6, 0], &frames);
+
+    let mut parser = SlpStreamParser::new();
+    let mut events = Vec::new();
+    let emitted = |events: &[SlpStreamEvent]| events.iter()
+        .filter_map(|ev| match ev {
+            SlpStreamEvent::Frame { frame_idx, frame } => Some((*frame_idx, frame.port_idx, frame.percent)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let expected = |last: u32| (0..=last)
+        .flat_map(|i| [(i, 0, i as f32), (i, 1, i as f32)])
+        .collect::<Vec<_>>();
+
+    // the last 7 frames could still be rolled back before the game end
+    let game_end_size = 3;
+    parser.push(&slp[..slp.len() - game_end_size], &mut events).unwrap();
+    assert_eq!(emitted(&events), expected(12 - MAX_ROLLBACK_FRAMES));
+
+    parser.push(&slp[slp.len() - game_end_size..], &mut events).unwrap();
+    assert!(parser.finished());
+    assert_eq!(emitted(&events), expected(12));
+}
@@ -0,0 +1,238 @@
+use crate::*;
+
+/// An opponent hit within this many frames after a tech or getup ends counts as a follow-up.
+pub const TECH_FOLLOW_UP_FRAMES: usize = 30;
+
+/// What the player did when they hit the ground in tumble.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TechOption {
+    InPlace,
+    /// Rolled in the direction the player was facing.
+    RollForward,
+    RollBackward,
+    Wall,
+    WallJump,
+    Ceiling,
+    Missed,
+}
+
+/// What the player did after missing a tech.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GetUpOption {
+    Stand,
+    Attack,
+    /// Rolled in the direction the player was facing.
+    RollForward,
+    RollBackward,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TechEvent {
+    pub port: u8,
+    /// Index of the first frame of the tech or knockdown.
+    pub frame_start: usize,
+    /// Index of the frame after the tech, or after the getup for missed techs.
+    pub frame_end: usize,
+    pub option: TechOption,
+    /// Only for missed techs. None if the player was hit or died before getting up.
+    pub getup: Option<GetUpOption>,
+    /// An opponent hit the player during the tech or within `TECH_FOLLOW_UP_FRAMES` after it.
+    pub followed_up: bool,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TechOptionCount {
+    pub count: u32,
+    /// Times the opponent hit the player after this option.
+    pub followed_up: u32,
+}
+
+impl TechOptionCount {
+    pub fn follow_up_rate(&self) -> Option<f32> {
+        (self.count != 0).then(|| self.followed_up as f32 / self.count as f32)
+    }
+
+    fn add(&mut self, event: &TechEvent) {
+        self.count += 1;
+        self.followed_up += event.followed_up as u32;
+    }
+}
+
+/// Tech option distribution of a single player, with the opponent's follow-up success.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TechReport {
+    pub events: Vec<TechEvent>,
+    /// All techs except `TechOption::Missed`.
+    pub hit: TechOptionCount,
+    pub missed: TechOptionCount,
+    /// Counts of each tech option, in order of first occurrence.
+    pub options: Vec<(TechOption, TechOptionCount)>,
+    /// Counts of each option after a missed tech, in order of first occurrence.
+    pub getups: Vec<(Option<GetUpOption>, TechOptionCount)>,
+}
+
+impl TechReport {
+    pub fn from_events(events: Vec<TechEvent>) -> Self {
+        let mut report = TechReport::default();
+        for event in events.iter() {
+            if event.option == TechOption::Missed {
+                report.missed.add(event);
+                match report.getups.iter_mut().find(|(getup, _)| *getup == event.getup) {
+                    Some((_, count)) => count.add(event),
+                    None => report.getups.push((event.getup, TechOptionCount { count: 1, followed_up: event.followed_up as u32 })),
+                }
+            } else {
+                report.hit.add(event);
+            }
+
+            match report.options.iter_mut().find(|(option, _)| *option == event.option) {
+                Some((_, count)) => count.add(event),
+                None => report.options.push((event.option, TechOptionCount { count: 1, followed_up: event.followed_up as u32 })),
+            }
+        }
+        report.events = events;
+        report
+    }
+}
+
+// whether a left/right roll went in the facing direction
+fn is_forward(frame: &Frame, right: bool) -> bool {
+    (frame.direction == Direction::Right) == right
+}
+
+fn tech_option(frame: &Frame, action: HighLevelAction) -> Option<TechOption> {
+    use HighLevelAction as HLA;
+    Some(match action {
+        HLA::TechInPlace    => TechOption::InPlace,
+        HLA::TechRollLeft   => if is_forward(frame, false) { TechOption::RollForward } else { TechOption::RollBackward },
+        HLA::TechRollRight  => if is_forward(frame, true)  { TechOption::RollForward } else { TechOption::RollBackward },
+        HLA::WallTech       => TechOption::Wall,
+        HLA::WallTechJump   => TechOption::WallJump,
+        HLA::CeilingTech    => TechOption::Ceiling,
+        HLA::MissedTech     => TechOption::Missed,
+        _ => return None,
+    })
+}
+
+fn getup_option(frame: &Frame, action: HighLevelAction) -> Option<GetUpOption> {
+    use HighLevelAction as HLA;
+    Some(match action {
+        HLA::GetUp          => GetUpOption::Stand,
+        HLA::GetUpAttack    => GetUpOption::Attack,
+        HLA::GetUpRollLeft  => if is_forward(frame, false) { GetUpOption::RollForward } else { GetUpOption::RollBackward },
+        HLA::GetUpRollRight => if is_forward(frame, true)  { GetUpOption::RollForward } else { GetUpOption::RollBackward },
+        _ => return None,
+    })
+}
+
+/// Every tech and missed tech by the player, in order.
+/// Returns an empty list if the port is unused.
+pub fn tech_events(game: &Game, port: usize) -> Vec<TechEvent> {
+    let mut events = Vec::new();
+    let Some(frames) = game.frames.get(port).and_then(|f| f.as_deref()) else { return events };
+    let actions = parse_actions(frames);
+
+    let mut i = 0;
+    while i < actions.len() {
+        let action = &actions[i];
+        i += 1;
+
+        let Some(start) = frames.get(action.frame_start) else { continue };
+        let Some(option) = tech_option(start, action.action_taken) else { continue };
+
+        let mut frame_end = action.frame_end;
+        let mut getup = None;
+        if option == TechOption::Missed {
+            if let Some(next) = actions.get(i).filter(|next| next.frame_start == action.frame_end) {
+                if let Some(option) = frames.get(next.frame_start).and_then(|f| getup_option(f, next.action_taken)) {
+                    getup = Some(option);
+                    frame_end = next.frame_end;
+                    i += 1;
+                }
+            }
+        }
+
+        let window_end = (frame_end + TECH_FOLLOW_UP_FRAMES).min(frames.len());
+        let followed_up = (action.frame_start.max(1)..window_end).any(|f| {
+            let prev = &frames[f-1];
+            let frame = &frames[f];
+            let hit = frame.percent > prev.percent && responsible_port(game, port, frame).is_some();
+            hit || (died(prev, frame) && responsible_port(game, port, prev).is_some())
+        });
+
+        events.push(TechEvent {
+            port: port as u8,
+            frame_start: action.frame_start,
+            frame_end,
+            option,
+            getup,
+            followed_up,
+        });
+    }
+
+    events
+}
+
+/// Tech reports for each used port.
+pub fn compute_tech_reports(game: &Game) -> [Option<TechReport>; 4] {
+    std::array::from_fn(|port| {
+        game.frames[port].as_ref()?;
+        Some(TechReport::from_events(tech_events(game, port)))
+    })
+}
+
+#[cfg(test)]
+fn tech_game() -> Game {
+    use StandardActionState as SAS;
+
+    let mut game = parse_file(&test_replay()).unwrap();
+    let attacker = test_frames(&game, 0, 400);
+    let mut defender = test_frames(&game, 1, 400);
+    for f in defender.iter_mut() { f.percent = 50.0; }
+
+    let mut set = |range: std::ops::Range<usize>, state| for f in defender[range].iter_mut() { f.state = ActionState::Standard(state) };
+    // missed tech, getup roll forward, not followed up
+    set(100..120, SAS::DownBoundU);
+    set(120..140, SAS::DownWaitU);
+    set(140..175, SAS::DownFowardU);
+    // tech roll backwards while facing left, then hit
+    set(250..290, SAS::PassiveStandB);
+    for f in defender[250..].iter_mut() { f.direction = Direction::Left; }
+    for f in defender[300..].iter_mut() { f.percent = 62.0; f.last_hit_by = 0; }
+
+    game.frames[0] = Some(attacker.into_boxed_slice());
+    game.frames[1] = Some(defender.into_boxed_slice());
+    game
+}
+
+#[test]
+fn missed_tech_and_getup() {
+    let events = tech_events(&tech_game(), 1);
+    assert_eq!(events.len(), 2);
+    assert_eq!(
+        (events[0].frame_start, events[0].frame_end, events[0].option, events[0].getup, events[0].followed_up),
+        (100, 175, TechOption::Missed, Some(GetUpOption::RollForward), false),
+    );
+}
+
+#[test]
+fn tech_roll_direction_and_follow_up() {
+    let game = tech_game();
+    let events = tech_events(&game, 1);
+    assert_eq!(
+        (events[1].frame_start, events[1].frame_end, events[1].option, events[1].followed_up),
+        (250, 290, TechOption::RollBackward, true),
+    );
+    assert!(tech_events(&game, 0).is_empty());
+}
+
+#[test]
+fn tech_reports() {
+    let reports = compute_tech_reports(&tech_game());
+    let report = reports[1].as_ref().unwrap();
+    assert_eq!(report.hit, TechOptionCount { count: 1, followed_up: 1 });
+    assert_eq!(report.missed.follow_up_rate(), Some(0.0));
+    assert_eq!(report.getups, vec![(Some(GetUpOption::RollForward), TechOptionCount { count: 1, followed_up: 0 })]);
+    assert!(reports[0].as_ref().unwrap().getups.is_empty());
+    assert!(reports[2].is_none());
+}
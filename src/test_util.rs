@@ -0,0 +1,107 @@
+use crate::*;
+
+/// Minimal v3.7.0 replay: Fox vs Marth on battlefield, with a rollback on the second frame.
+/// Percent is set to the frame index so we can check the frames are correct.
+/// The raw length is zero, as if the replay was still being written.
+pub(crate) fn test_replay() -> Vec<u8> {
+    let frames = [(-123, -123, 0.0), (-122, -123, 100.0), (-122, -122, 1.0), (-121, -121, 2.0)];
+    test_replay_with([3, 7, 0], &frames)
+}
+
+/// `test_replay` with a different version and frames.
+/// Each frame is (frame number, latest finalized frame, percent), sent in order.
+pub(crate) fn test_replay_with(version: [u8; 3], frames: &[(i32, i32, f32)]) -> Vec<u8> {
+    fn event(cmd: u8, size: usize, fill: impl FnOnce(&mut [u8])) -> Vec<u8> {
+        let mut bytes = vec![0u8; size + 1];
+        bytes[0] = cmd;
+        fill(&mut bytes);
+        bytes
+    }
+
+    let sizes: [(u8, u16); 7] = [
+        (GAME_START, 0x2FC),
+        (FRAME_START, 0xC),
+        (PRE_FRAME_UPDATE, 0x40),
+        (POST_FRAME_UPDATE, 0x54),
+        (GAME_END, 0x2),
+        (ITEM_UPDATE, 0x2C),
+        (FRAME_BOOKEND, 0x8),
+    ];
+
+    let mut raw = vec![EVENT_PAYLOADS, (sizes.len() * 3 + 1) as u8];
+    for (cmd, size) in sizes {
+        raw.push(cmd);
+        raw.extend_from_slice(&size.to_be_bytes());
+    }
+
+    raw.extend(event(GAME_START, 0x2FC, |b| {
+        b[1..4].copy_from_slice(&version);
+        b[0x13..0x15].copy_from_slice(&(Stage::Battlefield as u16).to_be_bytes());
+        b[0x65] = Character::Fox.to_u8_external().unwrap();
+        b[0x65 + 0x24] = Character::Marth.to_u8_external().unwrap();
+        b[0x66 + 0x24*2] = 3;
+        b[0x66 + 0x24*3] = 3;
+    }));
+
+    for &(frame, finalized, percent) in frames {
+        raw.extend(event(FRAME_START, 0xC, |b| {
+            b[0x1..0x5].copy_from_slice(&frame.to_be_bytes());
+            b[0x5..0x9].copy_from_slice(&(percent as u32).to_be_bytes());
+        }));
+        for (port, character) in [(0u8, Character::Fox), (1, Character::Marth)] {
+            raw.extend(event(PRE_FRAME_UPDATE, 0x40, |b| {
+                b[0x1..0x5].copy_from_slice(&frame.to_be_bytes());
+                b[0x5] = port;
+            }));
+            raw.extend(event(POST_FRAME_UPDATE, 0x54, |b| {
+                b[0x1..0x5].copy_from_slice(&frame.to_be_bytes());
+                b[0x5] = port;
+                b[0x7] = character.to_u8_internal();
+                b[0x8..0xA].copy_from_slice(&(StandardActionState::Wait as u16).to_be_bytes());
+                b[0x16..0x1A].copy_from_slice(&percent.to_be_bytes());
+            }));
+        }
+        raw.extend(event(ITEM_UPDATE, 0x2C, |b| {
+            b[0x1..0x5].copy_from_slice(&frame.to_be_bytes());
+            b[0x22..0x26].copy_from_slice(&(percent as u32).to_be_bytes());
+        }));
+        raw.extend(event(FRAME_BOOKEND, 0x8, |b| {
+            b[0x1..0x5].copy_from_slice(&frame.to_be_bytes());
+            b[0x5..0x9].copy_from_slice(&finalized.to_be_bytes());
+        }));
+    }
+    raw.extend(event(GAME_END, 0x2, |b| { b[1] = 2; b[2] = 1; }));
+
+    let mut slp = b"{U\x03raw[$U#l".to_vec();
+    slp.extend_from_slice(&0u32.to_be_bytes());
+    slp.extend_from_slice(&raw);
+    slp
+}
+
+/// `n` copies of the first frame of `port` in `test_replay`, reset to an idle player:
+/// standing at the origin facing right with 4 stocks, 0%, no inputs and never hit.
+pub(crate) fn test_frames(game: &Game, port: usize, n: usize) -> Vec<Frame> {
+    let mut frame = game.frames[port].as_ref().unwrap()[0].clone();
+    frame.port_idx = port as u8;
+    frame.state = ActionState::Standard(StandardActionState::Wait);
+    frame.state_num = StandardActionState::Wait as u16;
+    frame.anim_frame = 0.0;
+    frame.direction = Direction::Right;
+    frame.position = Vector::NULL;
+    frame.velocity = Vector::NULL;
+    frame.hit_velocity = Vector::NULL;
+    frame.is_airborne = false;
+    frame.percent = 0.0;
+    frame.stock_count = 4;
+    frame.last_hit_by = 6;
+    frame.hitlag_frames = 0.0;
+    frame.buttons_mask = 0;
+    frame.analog_trigger_value = 0.0;
+    frame.left_trigger_value_raw = 0.0;
+    frame.right_trigger_value_raw = 0.0;
+    frame.left_stick_coords = Vector::NULL;
+    frame.right_stick_coords = Vector::NULL;
+    frame.left_stick_coords_raw = VectorI8::NULL;
+    frame.right_stick_coords_raw = VectorI8::NULL;
+    vec![frame; n]
+}
@@ -0,0 +1,373 @@
+/// A decoded UBJSON value.
+/// Integer widths are kept as written, so values round trip byte for byte.
+#[derive(Clone, Debug, PartialEq)]
+pub enum UbjsonValue {
+    Null,
+    Bool(bool),
+    Int8(i8),
+    Uint8(u8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    Float32(f32),
+    Float64(f64),
+    /// Stored as the decimal string.
+    HighPrecision(String),
+    Char(u8),
+    String(String),
+    Array(Vec<UbjsonValue>),
+    /// Optimized uint8 array (`[$U#`), e.g. the raw events or note images.
+    Binary(Vec<u8>),
+    /// Keys are kept in file order.
+    Object(Vec<(String, UbjsonValue)>),
+}
+
+impl UbjsonValue {
+    /// Returns the first value with the key if this is an object.
+    pub fn get(&self, key: &str) -> Option<&UbjsonValue> {
+        self.as_object()?.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        Some(match *self {
+            UbjsonValue::Int8(n)   => n as i64,
+            UbjsonValue::Uint8(n)  => n as i64,
+            UbjsonValue::Int16(n)  => n as i64,
+            UbjsonValue::Int32(n)  => n as i64,
+            UbjsonValue::Int64(n)  => n,
+            _ => return None,
+        })
+    }
+
+    pub fn as_i32(&self) -> Option<i32> {
+        self.as_i64()?.try_into().ok()
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            UbjsonValue::Float32(n) => Some(n as f64),
+            UbjsonValue::Float64(n) => Some(n),
+            _ => self.as_i64().map(|n| n as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            UbjsonValue::String(s) | UbjsonValue::HighPrecision(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[UbjsonValue]> {
+        match self {
+            UbjsonValue::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    pub fn as_binary(&self) -> Option<&[u8]> {
+        match self {
+            UbjsonValue::Binary(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, UbjsonValue)]> {
+        match self {
+            UbjsonValue::Object(o) => Some(o),
+            _ => None,
+        }
+    }
+}
+
+// PARSING ------------------------------------------------------------------------
+
+/// Parses a single value. Returns the value and the number of bytes read.
+/// Returns None if the value is malformed or truncated.
+pub fn parse_ubjson(bytes: &[u8]) -> Option<(UbjsonValue, usize)> {
+    let mut parser = Parser { bytes, cursor: 0, depth: 0, failed: false };
+    let value = parser.value()?;
+    if parser.failed { return None; }
+    Some((value, parser.cursor))
+}
+
+/// Parses key value pairs until the end of the enclosing object or the end of the bytes.
+///
+/// Never fails. If the bytes are malformed or truncated,
+/// everything decoded before that point is returned, including partially read containers.
+/// Used for the metadata, which is the tail of the root object and is often read truncated.
+pub fn parse_ubjson_fields(bytes: &[u8]) -> Vec<(String, UbjsonValue)> {
+    let mut parser = Parser { bytes, cursor: 0, depth: 0, failed: false };
+    let mut fields = Vec::new();
+    parser.object_fields(&mut fields, None, None);
+    fields
+}
+
+/// Max nesting of arrays and objects. Deeper values fail to parse rather than overflowing the stack.
+pub const MAX_UBJSON_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+    // Containers currently being parsed.
+    depth: usize,
+    // Set on the first error. Containers stop and return what they have so far.
+    failed: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let Some(b) = self.bytes.get(self.cursor..self.cursor+N) else {
+            self.failed = true;
+            return None;
+        };
+        self.cursor += N;
+        Some(b.try_into().unwrap())
+    }
+
+    fn take_slice(&mut self, len: usize) -> Option<&'a [u8]> {
+        let Some(b) = self.bytes.get(self.cursor..).and_then(|b| b.get(..len)) else {
+            self.failed = true;
+            return None;
+        };
+        self.cursor += len;
+        Some(b)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.cursor).copied()
+    }
+
+    fn length(&mut self) -> Option<usize> {
+        let marker = self.take::<1>()?[0];
+        let len = self.typed_value(marker)?.as_i64();
+        match len {
+            Some(len) if len >= 0 => Some(len as usize),
+            _ => { self.failed = true; None }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.length()?;
+        let s = self.take_slice(len)?;
+        match std::str::from_utf8(s) {
+            Ok(s) => Some(s.to_string()),
+            Err(_) => { self.failed = true; None }
+        }
+    }
+
+    fn value(&mut self) -> Option<UbjsonValue> {
+        // skip no-ops
+        let mut marker = self.take::<1>()?[0];
+        while marker == b'N' { marker = self.take::<1>()?[0]; }
+        self.typed_value(marker)
+    }
+
+    fn typed_value(&mut self, marker: u8) -> Option<UbjsonValue> {
+        Some(match marker {
+            b'Z' => UbjsonValue::Null,
+            b'T' => UbjsonValue::Bool(true),
+            b'F' => UbjsonValue::Bool(false),
+            b'i' => UbjsonValue::Int8(i8::from_be_bytes(self.take()?)),
+            b'U' => UbjsonValue::Uint8(u8::from_be_bytes(self.take()?)),
+            b'I' => UbjsonValue::Int16(i16::from_be_bytes(self.take()?)),
+            b'l' => UbjsonValue::Int32(i32::from_be_bytes(self.take()?)),
+            b'L' => UbjsonValue::Int64(i64::from_be_bytes(self.take()?)),
+            b'd' => UbjsonValue::Float32(f32::from_be_bytes(self.take()?)),
+            b'D' => UbjsonValue::Float64(f64::from_be_bytes(self.take()?)),
+            b'C' => UbjsonValue::Char(self.take::<1>()?[0]),
+            b'S' => UbjsonValue::String(self.string()?),
+            b'H' => UbjsonValue::HighPrecision(self.string()?),
+            b'[' | b'{' => {
+                if self.depth == MAX_UBJSON_DEPTH { self.failed = true; return None; }
+                self.depth += 1;
+                let value = if marker == b'[' { Some(self.array()) } else { self.object() };
+                self.depth -= 1;
+                value?
+            }
+            _ => { self.failed = true; return None; }
+        })
+    }
+
+    fn object(&mut self) -> Option<UbjsonValue> {
+        let (ty, count) = self.container_header()?;
+        let mut fields = Vec::new();
+        self.object_fields(&mut fields, ty, count);
+        if count.is_none() && !self.failed {
+            if self.peek() == Some(b'}') { self.cursor += 1; } else { self.failed = true; }
+        }
+        Some(UbjsonValue::Object(fields))
+    }
+
+    // optional '$' type and '#' count
+    fn container_header(&mut self) -> Option<(Option<u8>, Option<usize>)> {
+        let mut ty = None;
+        if self.peek() == Some(b'$') {
+            self.cursor += 1;
+            ty = Some(self.take::<1>()?[0]);
+        }
+
+        let mut count = None;
+        if self.peek() == Some(b'#') {
+            self.cursor += 1;
+            let len = self.length()?;
+            // bail on garbage counts before allocating
+            if len > self.bytes.len() - self.cursor { self.failed = true; return None; }
+            count = Some(len);
+        }
+
+        // a type requires a count
+        if ty.is_some() && count.is_none() { self.failed = true; return None; }
+        Some((ty, count))
+    }
+
+    fn array(&mut self) -> UbjsonValue {
+        let Some((ty, count)) = self.container_header() else { return UbjsonValue::Array(Vec::new()) };
+
+        if ty == Some(b'U') {
+            let count = count.unwrap_or(0);
+            let data = self.take_slice(count).unwrap_or(&[]);
+            return UbjsonValue::Binary(data.to_vec());
+        }
+
+        let mut values = Vec::new();
+        loop {
+            match count {
+                Some(count) if values.len() == count => break,
+                None if self.peek() == Some(b']') => { self.cursor += 1; break },
+                _ => (),
+            }
+
+            let value = match ty {
+                Some(ty) => self.typed_value(ty),
+                None => self.value(),
+            };
+            if let Some(value) = value { values.push(value); }
+            if self.failed { break; }
+        }
+
+        UbjsonValue::Array(values)
+    }
+
+    // Stops before the closing '}' if uncounted.
+    fn object_fields(&mut self, fields: &mut Vec<(String, UbjsonValue)>, ty: Option<u8>, count: Option<usize>) {
+        loop {
+            match count {
+                Some(count) if fields.len() == count => break,
+                None => match self.peek() {
+                    None | Some(b'}') => break,
+                    // Older versions of write_notes closed counted arrays with a ']'.
+                    Some(b']') => { self.cursor += 1; continue; }
+                    Some(b'N') => { self.cursor += 1; continue; }
+                    _ => (),
+                }
+                _ => (),
+            }
+
+            let Some(key) = self.string() else { break };
+            let value = match ty {
+                Some(ty) => self.typed_value(ty),
+                None => self.value(),
+            };
+            if let Some(value) = value { fields.push((key, value)); }
+            if self.failed { break; }
+        }
+    }
+}
+
+// WRITING ------------------------------------------------------------------------
+
+pub fn write_ubjson(buffer: &mut Vec<u8>, value: &UbjsonValue) {
+    match value {
+        UbjsonValue::Null             => buffer.push(b'Z'),
+        UbjsonValue::Bool(true)       => buffer.push(b'T'),
+        UbjsonValue::Bool(false)      => buffer.push(b'F'),
+        UbjsonValue::Int8(n)          => { buffer.push(b'i'); buffer.extend_from_slice(&n.to_be_bytes()); }
+        UbjsonValue::Uint8(n)         => { buffer.push(b'U'); buffer.push(*n); }
+        UbjsonValue::Int16(n)         => { buffer.push(b'I'); buffer.extend_from_slice(&n.to_be_bytes()); }
+        UbjsonValue::Int32(n)         => { buffer.push(b'l'); buffer.extend_from_slice(&n.to_be_bytes()); }
+        UbjsonValue::Int64(n)         => { buffer.push(b'L'); buffer.extend_from_slice(&n.to_be_bytes()); }
+        UbjsonValue::Float32(n)       => { buffer.push(b'd'); buffer.extend_from_slice(&n.to_be_bytes()); }
+        UbjsonValue::Float64(n)       => { buffer.push(b'D'); buffer.extend_from_slice(&n.to_be_bytes()); }
+        UbjsonValue::Char(c)          => { buffer.push(b'C'); buffer.push(*c); }
+        UbjsonValue::String(s)        => { buffer.push(b'S'); write_ubjson_key(buffer, s); }
+        UbjsonValue::HighPrecision(s) => { buffer.push(b'H'); write_ubjson_key(buffer, s); }
+        UbjsonValue::Array(values) => {
+            buffer.push(b'[');
+            for v in values.iter() { write_ubjson(buffer, v); }
+            buffer.push(b']');
+        }
+        UbjsonValue::Binary(data) => {
+            buffer.extend_from_slice(b"[$U#l");
+            buffer.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            buffer.extend_from_slice(data);
+        }
+        UbjsonValue::Object(fields) => {
+            buffer.push(b'{');
+            for (k, v) in fields.iter() {
+                write_ubjson_key(buffer, k);
+                write_ubjson(buffer, v);
+            }
+            buffer.push(b'}');
+        }
+    }
+}
+
+/// Writes a length prefixed string without the 'S' marker, as used for object keys.
+pub fn write_ubjson_key(buffer: &mut Vec<u8>, key: &str) {
+    if key.len() <= u8::MAX as usize {
+        buffer.push(b'U');
+        buffer.push(key.len() as u8);
+    } else {
+        buffer.push(b'l');
+        buffer.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    }
+    buffer.extend_from_slice(key.as_bytes());
+}
+
+#[test]
+fn ubjson_round_trip() {
+    let value = UbjsonValue::Object(vec![
+        ("startAt".into(), UbjsonValue::String("2024-10-03T20:41:13Z".into())),
+        ("lastFrame".into(), UbjsonValue::Int32(9582)),
+        ("players".into(), UbjsonValue::Object(vec![
+            ("0".into(), UbjsonValue::Object(vec![
+                ("characters".into(), UbjsonValue::Object(vec![("9".into(), UbjsonValue::Int16(9705))])),
+            ])),
+        ])),
+        ("image".into(), UbjsonValue::Binary(vec![1, 2, 3])),
+        ("list".into(), UbjsonValue::Array(vec![UbjsonValue::Float32(1.5), UbjsonValue::Null, UbjsonValue::Bool(true)])),
+    ]);
+
+    let mut buf = Vec::new();
+    write_ubjson(&mut buf, &value);
+    let (parsed, read) = parse_ubjson(&buf).unwrap();
+    assert_eq!(read, buf.len());
+    assert_eq!(parsed, value);
+
+    // truncated in the middle of the players object
+    let players_end = buf.windows(10).position(|w| w == b"characters").unwrap() + 5;
+    let fields = parse_ubjson_fields(&buf[1..players_end]);
+    assert_eq!(fields.len(), 3);
+    assert_eq!(fields[1], ("lastFrame".into(), UbjsonValue::Int32(9582)));
+    assert_eq!(fields[2].0, "players");
+    assert!(fields[2].1.get("0").is_some());
+    assert!(parse_ubjson(&buf[..buf.len() - 1]).is_none());
+}
+
+#[test]
+fn ubjson_nesting_limit() {
+    let nested = |depth: usize| {
+        let mut buf = vec![b'['; depth];
+        buf.extend(std::iter::repeat_n(b']', depth));
+        buf
+    };
+    assert!(parse_ubjson(&nested(MAX_UBJSON_DEPTH)).is_some());
+    assert!(parse_ubjson(&nested(MAX_UBJSON_DEPTH + 1)).is_none());
+    assert!(parse_ubjson(&vec![b'['; 2_000_000]).is_none());
+
+    // the metadata keeps what was read before the deep value
+    let mut fields = b"U\x01aZU\x01b".to_vec();
+    fields.extend(vec![b'{'; 2_000_000]);
+    assert_eq!(parse_ubjson_fields(&fields), vec![("a".into(), UbjsonValue::Null), ("b".into(), UbjsonValue::Object(Vec::new()))]);
+}